@@ -0,0 +1,133 @@
+// Lending backends holding the base tokens deposited when wrapping
+
+pub mod solend;
+
+use crate::processor::FluidityData;
+
+use {
+    borsh::{BorshDeserialize, BorshSerialize},
+    solana_program::{
+        account_info::AccountInfo,
+        entrypoint::ProgramResult,
+        msg,
+        program_error::ProgramError,
+    },
+    spl_token::state::Mint,
+};
+
+// the lending backend a token's base tokens are deposited into
+#[derive(BorshDeserialize, BorshSerialize, Debug, PartialEq, Clone, Copy)]
+pub enum AdapterKind {
+    Solend,
+}
+
+impl AdapterKind {
+    // the byte the kind is packed as in a data account
+    pub(crate) fn to_byte(self) -> u8 {
+        match self {
+            AdapterKind::Solend => 0,
+        }
+    }
+
+    pub(crate) fn from_byte(byte: u8) -> Result<Self, ProgramError> {
+        match byte {
+            0 => Ok(AdapterKind::Solend),
+            _ => {
+                msg!("unknown lending adapter {}", byte);
+                Err(ProgramError::InvalidAccountData)
+            }
+        }
+    }
+}
+
+// accounts on the fluidity side of a deposit or withdrawal
+pub struct UserAccounts<'b, 'a> {
+    // the spl-token program
+    pub token_program: &'b AccountInfo<'a>,
+    // the obligation authority, which owns the deposited collateral
+    pub pda: &'b AccountInfo<'a>,
+    // seeds signing for the obligation authority
+    pub pda_seeds: &'b [&'b [u8]],
+    // the user's base token account
+    pub token_account: &'b AccountInfo<'a>,
+    // the owner of the user's token account, signing deposits
+    pub owner: &'b AccountInfo<'a>,
+}
+
+// collateral the obligation authority holds in the token's reserve and its
+// value in base tokens, and the value of all its deposits in the lending
+// market's quote currency, scaled by 10^18
+pub struct Deposited {
+    pub collateral_amount: u64,
+    pub value: u64,
+    pub market_value: u128,
+    // decimals of the base tokens the value is in
+    pub decimals: u8,
+}
+
+// operations the handlers need from a lending backend - the accounts passed
+// are the backend's own, in the order its adapter documents, and each
+// adapter checks them against those recorded in the token's data account
+pub trait LendingAdapter {
+    // deposit an amount of base tokens from the user's token account,
+    // returning the collateral credited to the obligation authority and the
+    // fluid tokens it backs - the amount while the prize pool covers what the
+    // collateral is worth less than it, otherwise what it's worth
+    fn deposit<'a>(
+        &self,
+        data: &FluidityData,
+        accounts: &[AccountInfo<'a>],
+        user: &UserAccounts<'_, 'a>,
+        fluid_mint: &Mint,
+        amount: u64,
+    ) -> Result<(u64, u64), ProgramError>;
+
+    // withdraw an amount of base tokens to the user's token account once the
+    // fluid tokens are burnt, rounding in the user's favour while what's left
+    // is worth the remaining fluid supply, and returning the collateral
+    // redeemed by the obligation authority
+    fn withdraw<'a>(
+        &self,
+        data: &FluidityData,
+        accounts: &[AccountInfo<'a>],
+        user: &UserAccounts<'_, 'a>,
+        fluid_mint: &Mint,
+        amount: u64,
+    ) -> Result<u64, ProgramError>;
+
+    // withdraw everything the obligation authority deposited to the user's
+    // token account, returning the collateral redeemed
+    fn withdraw_all<'a>(
+        &self,
+        data: &FluidityData,
+        accounts: &[AccountInfo<'a>],
+        user: &UserAccounts<'_, 'a>,
+    ) -> Result<u64, ProgramError>;
+
+    // accrue interest and update prices so the underlying value is current
+    fn refresh<'a>(&self, data: &FluidityData, accounts: &[AccountInfo<'a>]) -> ProgramResult;
+
+    // collateral deposited by the obligation authority and its value
+    fn deposited(&self, data: &FluidityData, accounts: &[AccountInfo]) -> Result<Deposited, ProgramError>;
+
+    // number of accounts at the start of accounts making up the token's TVL
+    // layout, which starts with the refresh layout
+    fn tvl_accounts_len(&self, data: &FluidityData, accounts: &[AccountInfo]) -> Result<usize, ProgramError>;
+
+    // the currency market values are quoted in, given the TVL layout
+    fn quote_currency(&self, data: &FluidityData, accounts: &[AccountInfo]) -> Result<[u8; 32], ProgramError>;
+
+    // value in base tokens of everything deposited by the obligation authority,
+    // for callers that don't need the rest of what deposited returns
+    #[allow(dead_code)]
+    fn underlying_value(&self, data: &FluidityData, accounts: &[AccountInfo]) -> Result<u64, ProgramError> {
+        Ok(self.deposited(data, accounts)?.value)
+    }
+}
+
+// get the adapter for a lending backend
+pub fn adapter(kind: AdapterKind) -> &'static dyn LendingAdapter {
+    match kind {
+        AdapterKind::Solend => &solend::Solend,
+    }
+}
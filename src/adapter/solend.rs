@@ -0,0 +1,757 @@
+// Solend adapter, depositing base tokens into a reserve and the collateral
+// into an obligation owned by the obligation authority
+
+use crate::{
+    adapter::{Deposited, LendingAdapter, UserAccounts},
+    error::{FluidityError, LendingError},
+    instruction::LendingInstruction,
+    math::{Decimal, TryAdd, TryDiv, TryMul},
+    processor::{check_recorded, prize_pool, FluidityData},
+    state::{CollateralExchangeRate, LendingMarket, Obligation, Reserve},
+};
+
+use {
+    std::str::FromStr,
+    solana_program::{
+        account_info::{next_account_info, AccountInfo},
+        entrypoint::ProgramResult,
+        instruction::{AccountMeta, Instruction},
+        msg,
+        program::{invoke, invoke_signed},
+        program_error::ProgramError,
+        program_pack::Pack,
+        pubkey::Pubkey,
+    },
+    spl_token::{self, state::Mint},
+};
+
+// the public key of the solend program
+pub const SOLEND: &str = "ALend7Ketfx5bxh6ghsCDXAoDrhvEmsXT3cynB6aPLgx";
+
+// deposit and withdraw take, in order: solend_program, collateral, reserve,
+// reserve_liquidity_supply, reserve_collateral_mint, lending_market,
+// lending_market_authority, reserve_collateral_supply, obligation,
+// pyth_price, switchboard_feed, clock
+//
+// deposited and underlying_value take obligation, then the reserve of each of
+// the obligation's deposits in order - refresh takes those followed by
+// solend_program, the pyth_price and switchboard_feed of each reserve in
+// order, and clock - and the TVL layout takes those followed by
+// lending_market
+pub struct Solend;
+
+// check an account is the solend program
+pub fn check_program(solend_program: &AccountInfo) -> ProgramResult {
+    if solend_program.key !=
+        &Pubkey::from_str(SOLEND).unwrap() {
+        msg!("bad Solend contract!");
+        return Err(FluidityError::InvalidSolendProgram.into());
+    }
+
+    Ok(())
+}
+
+// the accounts passed to solend when depositing and withdrawing
+struct ReserveAccounts<'b, 'a> {
+    solend_program: &'b AccountInfo<'a>,
+    collateral: &'b AccountInfo<'a>,
+    reserve: &'b AccountInfo<'a>,
+    liquidity_supply: &'b AccountInfo<'a>,
+    collateral_mint: &'b AccountInfo<'a>,
+    lending_market: &'b AccountInfo<'a>,
+    lending_market_authority: &'b AccountInfo<'a>,
+    collateral_supply: &'b AccountInfo<'a>,
+    obligation: &'b AccountInfo<'a>,
+    pyth_price: &'b AccountInfo<'a>,
+    switchboard_feed: &'b AccountInfo<'a>,
+    clock: &'b AccountInfo<'a>,
+}
+
+impl<'b, 'a> ReserveAccounts<'b, 'a> {
+    // read the accounts and check them against those recorded for the token,
+    // solend checks the market authority and oracles against these
+    fn load(data: &FluidityData, accounts: &'b [AccountInfo<'a>]) -> Result<Self, ProgramError> {
+        let accounts_iter = &mut accounts.iter();
+
+        let solend_program = next_account_info(accounts_iter)?;
+        let collateral = next_account_info(accounts_iter)?;
+        let reserve = next_account_info(accounts_iter)?;
+        let liquidity_supply = next_account_info(accounts_iter)?;
+        let collateral_mint = next_account_info(accounts_iter)?;
+        let lending_market = next_account_info(accounts_iter)?;
+        let lending_market_authority = next_account_info(accounts_iter)?;
+        let collateral_supply = next_account_info(accounts_iter)?;
+        let obligation = next_account_info(accounts_iter)?;
+        let pyth_price = next_account_info(accounts_iter)?;
+        let switchboard_feed = next_account_info(accounts_iter)?;
+        let clock = next_account_info(accounts_iter)?;
+
+        check_program(solend_program)?;
+
+        check_recorded(collateral, &data.collateral, FluidityError::InvalidCollateralAccount)?;
+        check_recorded(reserve, &data.reserve, FluidityError::InvalidReserve)?;
+        check_recorded(liquidity_supply, &data.liquidity_supply, FluidityError::InvalidLiquiditySupply)?;
+        check_recorded(collateral_mint, &data.collateral_mint, FluidityError::InvalidCollateralMint)?;
+        check_recorded(lending_market, &data.lending_market, FluidityError::InvalidLendingMarket)?;
+        check_recorded(collateral_supply, &data.collateral_supply, FluidityError::InvalidCollateralSupply)?;
+        check_recorded(obligation, &data.obligation, FluidityError::InvalidObligation)?;
+
+        // check collateral and obligation ownership
+        let obligation_state = Obligation::unpack(&obligation.data.borrow())?;
+        if obligation_state.owner != data.pda {
+            msg!("bad obligation ownership!");
+            return Err(FluidityError::InvalidObligationOwner.into());
+        }
+        let collateral_state = spl_token::state::Account::unpack(&collateral.data.borrow())?;
+        if collateral_state.owner != data.pda {
+            msg!("bad collateral ownership!");
+            return Err(FluidityError::InvalidCollateralOwner.into());
+        }
+
+        Ok(ReserveAccounts {
+            solend_program,
+            collateral,
+            reserve,
+            liquidity_supply,
+            collateral_mint,
+            lending_market,
+            lending_market_authority,
+            collateral_supply,
+            obligation,
+            pyth_price,
+            switchboard_feed,
+            clock,
+        })
+    }
+
+    fn refresh_reserve(&self) -> ProgramResult {
+        refresh_reserve(self.solend_program, self.reserve, self.pyth_price, self.switchboard_feed, self.clock)
+    }
+
+    // collateral the obligation has deposited in the reserve, zero if none
+    fn deposited_collateral(&self) -> Result<u64, ProgramError> {
+        let obligation = Obligation::unpack(&self.obligation.data.borrow())?;
+
+        Ok(obligation.deposits
+            .iter()
+            .find(|deposit| &deposit.deposit_reserve == self.reserve.key)
+            .map_or(0, |deposit| deposit.deposited_amount))
+    }
+
+    // the obligation's deposit in the reserve, valued as of the reserve's
+    // last refresh
+    fn deposited(&self) -> Result<Deposited, ProgramError> {
+        let reserve = Reserve::unpack(&self.reserve.data.borrow())?;
+        let collateral_amount = self.deposited_collateral()?;
+        let value = reserve.collateral_exchange_rate()?.collateral_to_liquidity(collateral_amount)?;
+
+        Ok(Deposited {
+            collateral_amount,
+            value,
+            market_value: market_value(&reserve, value)?.to_scaled_val()?,
+            decimals: reserve.liquidity.mint_decimals,
+        })
+    }
+
+    // withdraw collateral from the obligation, redeeming it to the user's
+    // token account
+    fn withdraw_collateral(&self, user: &UserAccounts<'_, 'a>, collateral_amount: u64) -> ProgramResult {
+        invoke_signed(
+            &Instruction::new_with_borsh(
+                *self.solend_program.key,
+                &LendingInstruction::WithdrawObligationCollateralAndRedeemReserveCollateral {
+                    collateral_amount,
+                },
+                vec![
+                    AccountMeta::new(*self.collateral_supply.key, false),
+                    AccountMeta::new(*self.collateral.key, false),
+                    AccountMeta::new(*self.reserve.key, false),
+                    AccountMeta::new(*self.obligation.key, false),
+                    AccountMeta::new(*self.lending_market.key, false),
+                    AccountMeta::new_readonly(*self.lending_market_authority.key, false),
+                    AccountMeta::new(*user.token_account.key, false),
+                    AccountMeta::new(*self.collateral_mint.key, false),
+                    AccountMeta::new(*self.liquidity_supply.key, false),
+                    AccountMeta::new(*user.pda.key, true),
+                    AccountMeta::new(*user.pda.key, true),
+                    AccountMeta::new_readonly(*self.clock.key, false),
+                    AccountMeta::new_readonly(*user.token_program.key, false),
+                ],
+            ),
+            &[
+                self.collateral_supply.clone(),
+                self.collateral.clone(),
+                self.reserve.clone(),
+                self.obligation.clone(),
+                self.lending_market.clone(),
+                self.lending_market_authority.clone(),
+                user.token_account.clone(),
+                self.collateral_mint.clone(),
+                self.liquidity_supply.clone(),
+                user.pda.clone(),
+                self.clock.clone(),
+                user.token_program.clone(),
+                self.solend_program.clone(),
+            ],
+            &[user.pda_seeds],
+        )
+    }
+}
+
+// value of an amount of a reserve's liquidity in the lending market's quote
+// currency, the market price being of a whole token
+fn market_value(reserve: &Reserve, liquidity: u64) -> Result<Decimal, ProgramError> {
+    let decimals = 10u64.checked_pow(reserve.liquidity.mint_decimals as u32)
+        .ok_or(LendingError::MathOverflow)?;
+
+    Decimal::from(liquidity).try_mul(reserve.liquidity.market_price)?.try_div(decimals)
+}
+
+// fluid tokens to mint for a deposit of an amount credited with an amount of
+// collateral - the amount if the prize pool after the deposit covers what the
+// collateral is worth less than it, otherwise only what the collateral is
+// worth
+pub(crate) fn deposit_fluid_amount(
+    exchange_rate: &CollateralExchangeRate,
+    amount: u64,
+    collateral_amount: u64,
+    prize_pool: u64,
+) -> Result<u64, ProgramError> {
+    if prize_pool >= amount {
+        return Ok(amount);
+    }
+
+    Ok(exchange_rate.collateral_to_liquidity(collateral_amount)?.min(amount))
+}
+
+// collateral to redeem for an unwrapped amount - rounded up so the
+// redemption's rounding is taken from the prize pool instead of the user if
+// what the rounded up collateral is worth is left in the pool of the
+// remaining fluid supply, and up to what the obligation authority deposited
+pub(crate) fn withdraw_collateral_amount(
+    exchange_rate: &CollateralExchangeRate,
+    amount: u64,
+    prize_pool: u64,
+    deposited_collateral: u64,
+) -> Result<u64, ProgramError> {
+    let collateral = exchange_rate.decimal_liquidity_to_collateral(amount.into())?;
+    let rounded_up = collateral.try_ceil_u64()?;
+    let rounded_up_value = exchange_rate.decimal_collateral_to_liquidity(rounded_up.into())?.try_ceil_u64()?;
+
+    let collateral_amount = if rounded_up_value <= prize_pool {
+        rounded_up
+    } else {
+        collateral.try_floor_u64()?
+    };
+
+    Ok(collateral_amount.min(deposited_collateral))
+}
+
+// refresh a reserve's interest and market price
+fn refresh_reserve<'a>(
+    solend_program: &AccountInfo<'a>,
+    reserve_info: &AccountInfo<'a>,
+    pyth_price_info: &AccountInfo<'a>,
+    switchboard_feed_info: &AccountInfo<'a>,
+    clock_info: &AccountInfo<'a>,
+) -> ProgramResult {
+    invoke(
+        &Instruction::new_with_borsh(
+            *solend_program.key,
+            &LendingInstruction::RefreshReserve,
+            vec![
+                AccountMeta::new(*reserve_info.key, false),
+                AccountMeta::new_readonly(*pyth_price_info.key, false),
+                AccountMeta::new_readonly(*switchboard_feed_info.key, false),
+                AccountMeta::new_readonly(*clock_info.key, false),
+            ],
+        ),
+        &[
+            reserve_info.clone(),
+            pyth_price_info.clone(),
+            switchboard_feed_info.clone(),
+            clock_info.clone(),
+            solend_program.clone(),
+        ],
+    )
+}
+
+// refresh an obligation's deposited value from the refreshed reserves of its
+// deposits
+fn refresh_obligation<'a>(
+    solend_program: &AccountInfo<'a>,
+    obligation_info: &AccountInfo<'a>,
+    reserve_infos: &[AccountInfo<'a>],
+    clock_info: &AccountInfo<'a>,
+) -> ProgramResult {
+    let mut account_metas = vec![
+        AccountMeta::new(*obligation_info.key, false),
+        AccountMeta::new_readonly(*clock_info.key, false),
+    ];
+    account_metas.extend(reserve_infos.iter().map(|reserve_info| AccountMeta::new(*reserve_info.key, false)));
+
+    let mut account_infos = vec![obligation_info.clone(), clock_info.clone()];
+    account_infos.extend(reserve_infos.iter().cloned());
+    account_infos.push(solend_program.clone());
+
+    invoke(
+        &Instruction::new_with_borsh(
+            *solend_program.key,
+            &LendingInstruction::RefreshObligation,
+            account_metas,
+        ),
+        &account_infos,
+    )
+}
+
+// read the token's obligation at the start of accounts, checking that the
+// reserve of each of its deposits follows it in order, and returning them
+fn load_obligation<'b, 'a>(
+    data: &FluidityData,
+    accounts: &'b [AccountInfo<'a>],
+) -> Result<(Obligation, &'b [AccountInfo<'a>]), ProgramError> {
+    let (obligation_info, rest) = accounts.split_first().ok_or(ProgramError::NotEnoughAccountKeys)?;
+    check_recorded(obligation_info, &data.obligation, FluidityError::InvalidObligation)?;
+
+    let obligation = Obligation::unpack(&obligation_info.data.borrow())?;
+    if obligation.deposits.is_empty() {
+        return Err(LendingError::ObligationDepositsEmpty.into());
+    }
+
+    let reserve_infos = rest.get(..obligation.deposits.len()).ok_or(ProgramError::NotEnoughAccountKeys)?;
+    for (deposit, reserve_info) in obligation.deposits.iter().zip(reserve_infos) {
+        check_recorded(reserve_info, &deposit.deposit_reserve, FluidityError::InvalidReserve)?;
+    }
+
+    Ok((obligation, reserve_infos))
+}
+
+impl LendingAdapter for Solend {
+    fn deposit<'a>(
+        &self,
+        data: &FluidityData,
+        accounts: &[AccountInfo<'a>],
+        user: &UserAccounts<'_, 'a>,
+        fluid_mint: &Mint,
+        amount: u64,
+    ) -> Result<(u64, u64), ProgramError> {
+        if amount < 2 {
+            msg!("Amount of liquidity less than two, Solend rounding error!");
+            return Err(FluidityError::AmountTooSmall.into());
+        }
+
+        let solend = ReserveAccounts::load(data, accounts)?;
+
+        solend.refresh_reserve()?;
+
+        // deposit liquidity from user token account
+        invoke(
+            &Instruction::new_with_borsh(
+                *solend.solend_program.key,
+                &LendingInstruction::DepositReserveLiquidity{liquidity_amount: amount},
+                vec![
+                    AccountMeta::new(*user.token_account.key, false),
+                    AccountMeta::new(*solend.collateral.key, false),
+                    AccountMeta::new(*solend.reserve.key, false),
+                    AccountMeta::new(*solend.liquidity_supply.key, false),
+                    AccountMeta::new(*solend.collateral_mint.key, false),
+                    AccountMeta::new(*solend.lending_market.key, false),
+                    AccountMeta::new_readonly(*solend.lending_market_authority.key, false),
+                    AccountMeta::new(*user.owner.key, true),
+                    AccountMeta::new_readonly(*solend.clock.key, false),
+                    AccountMeta::new_readonly(*user.token_program.key, false),
+                ],
+            ),
+            &[
+                user.token_account.clone(), solend.collateral.clone(), solend.reserve.clone(),
+                solend.liquidity_supply.clone(), solend.collateral_mint.clone(),
+                solend.lending_market.clone(), solend.lending_market_authority.clone(),
+                user.owner.clone(), solend.clock.clone(), user.token_program.clone(),
+            ],
+        )?;
+
+        // refresh reserve again
+        solend.refresh_reserve()?;
+
+        // calculate collateral amount
+        let reserve = Reserve::unpack(&solend.reserve.data.borrow())?;
+        let collateral_amount = reserve.collateral_exchange_rate()?.liquidity_to_collateral(amount)?;
+
+        // deposit collateral into obligation
+        invoke_signed(
+            &Instruction::new_with_borsh(
+                *solend.solend_program.key,
+                &LendingInstruction::DepositObligationCollateral{collateral_amount},
+                vec![
+                    AccountMeta::new(*solend.collateral.key, false),
+                    AccountMeta::new(*solend.collateral_supply.key, false),
+                    AccountMeta::new(*solend.reserve.key, false),
+                    AccountMeta::new(*solend.obligation.key, false),
+                    AccountMeta::new(*solend.lending_market.key, false),
+                    AccountMeta::new(*user.pda.key, true),
+                    AccountMeta::new(*user.pda.key, true),
+                    AccountMeta::new_readonly(*solend.clock.key, false),
+                    AccountMeta::new_readonly(*user.token_program.key, false),
+                ]
+            ),
+            &[
+                solend.collateral.clone(), solend.collateral_supply.clone(), solend.reserve.clone(),
+                solend.obligation.clone(), solend.lending_market.clone(), user.pda.clone(),
+                solend.clock.clone(), user.token_program.clone(),
+            ],
+            &[user.pda_seeds],
+        )?;
+
+        // a pool in deficit has nothing to cover the rounding with
+        let fluid_amount = deposit_fluid_amount(
+            &reserve.collateral_exchange_rate()?,
+            amount,
+            collateral_amount,
+            prize_pool(&solend.deposited()?, fluid_mint).unwrap_or(0),
+        )?;
+
+        Ok((collateral_amount, fluid_amount))
+    }
+
+    fn withdraw<'a>(
+        &self,
+        data: &FluidityData,
+        accounts: &[AccountInfo<'a>],
+        user: &UserAccounts<'_, 'a>,
+        fluid_mint: &Mint,
+        amount: u64,
+    ) -> Result<u64, ProgramError> {
+        let solend = ReserveAccounts::load(data, accounts)?;
+
+        solend.refresh_reserve()?;
+        refresh_obligation(
+            solend.solend_program,
+            solend.obligation,
+            std::slice::from_ref(solend.reserve),
+            solend.clock,
+        )?;
+
+        // calculate collateral amount from refreshed reserve, a pool in
+        // deficit having nothing to round up with
+        let reserve = Reserve::unpack(&solend.reserve.data.borrow())?;
+        let collateral_amount = withdraw_collateral_amount(
+            &reserve.collateral_exchange_rate()?,
+            amount,
+            prize_pool(&solend.deposited()?, fluid_mint).unwrap_or(0),
+            solend.deposited_collateral()?,
+        )?;
+
+        solend.withdraw_collateral(user, collateral_amount)?;
+
+        Ok(collateral_amount)
+    }
+
+    fn withdraw_all<'a>(
+        &self,
+        data: &FluidityData,
+        accounts: &[AccountInfo<'a>],
+        user: &UserAccounts<'_, 'a>,
+    ) -> Result<u64, ProgramError> {
+        let solend = ReserveAccounts::load(data, accounts)?;
+
+        solend.refresh_reserve()?;
+        refresh_obligation(
+            solend.solend_program,
+            solend.obligation,
+            std::slice::from_ref(solend.reserve),
+            solend.clock,
+        )?;
+
+        // the whole of the reserve's deposit, if any is left
+        let collateral_amount = solend.deposited_collateral()?;
+
+        if collateral_amount > 0 {
+            solend.withdraw_collateral(user, collateral_amount)?;
+        }
+
+        Ok(collateral_amount)
+    }
+
+    fn refresh<'a>(&self, data: &FluidityData, accounts: &[AccountInfo<'a>]) -> ProgramResult {
+        let (_, reserve_infos) = load_obligation(data, accounts)?;
+        let deposits = reserve_infos.len();
+
+        // the solend program, an oracle pair per reserve, then the clock
+        let rest = &accounts[1 + deposits..];
+        if rest.len() < 2 * deposits + 2 {
+            return Err(ProgramError::NotEnoughAccountKeys);
+        }
+        let solend_program = &rest[0];
+        let oracles = &rest[1..1 + 2 * deposits];
+        let clock_info = &rest[1 + 2 * deposits];
+
+        check_program(solend_program)?;
+
+        for (reserve_info, oracle) in reserve_infos.iter().zip(oracles.chunks(2)) {
+            refresh_reserve(solend_program, reserve_info, &oracle[0], &oracle[1], clock_info)?;
+        }
+        refresh_obligation(solend_program, &accounts[0], reserve_infos, clock_info)
+    }
+
+    fn deposited(&self, data: &FluidityData, accounts: &[AccountInfo]) -> Result<Deposited, ProgramError> {
+        let (obligation, reserve_infos) = load_obligation(data, accounts)?;
+
+        // only the token's own reserve backs its fluid tokens, the market
+        // value counts every deposit, each through its own reserve
+        let mut collateral_amount: u64 = 0;
+        let mut value: u64 = 0;
+        let mut total_market_value = Decimal::zero();
+        let mut value_decimals = 0;
+        for (deposit, reserve_info) in obligation.deposits.iter().zip(reserve_infos) {
+            let reserve = Reserve::unpack(&reserve_info.data.borrow())?;
+            let liquidity = reserve.collateral_exchange_rate()?
+                .collateral_to_liquidity(deposit.deposited_amount)?;
+
+            total_market_value = total_market_value.try_add(market_value(&reserve, liquidity)?)?;
+
+            if deposit.deposit_reserve == data.reserve {
+                collateral_amount = deposit.deposited_amount;
+                value = liquidity;
+                value_decimals = reserve.liquidity.mint_decimals;
+            }
+        }
+
+        Ok(Deposited {
+            collateral_amount,
+            value,
+            market_value: total_market_value.to_scaled_val()?,
+            decimals: value_decimals,
+        })
+    }
+
+    fn tvl_accounts_len(&self, data: &FluidityData, accounts: &[AccountInfo]) -> Result<usize, ProgramError> {
+        let (_, reserve_infos) = load_obligation(data, accounts)?;
+
+        // the obligation, and the solend program, clock and lending market,
+        // around the reserves and their oracles
+        Ok(4 + 3 * reserve_infos.len())
+    }
+
+    fn quote_currency(&self, data: &FluidityData, accounts: &[AccountInfo]) -> Result<[u8; 32], ProgramError> {
+        let lending_market_info = accounts.get(self.tvl_accounts_len(data, accounts)? - 1)
+            .ok_or(ProgramError::NotEnoughAccountKeys)?;
+        check_recorded(lending_market_info, &data.lending_market, FluidityError::InvalidLendingMarket)?;
+
+        Ok(LendingMarket::unpack(&lending_market_info.data.borrow())?.quote_currency)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        processor::InitFluidityDataParams,
+        state::{InitObligationParams, ObligationCollateral, PROGRAM_VERSION},
+    };
+
+    #[test]
+    fn test_deposited() {
+        let program_id = Pubkey::new_unique();
+        let solend_program = Pubkey::from_str(SOLEND).unwrap();
+        let pda = Pubkey::new_unique();
+        let obligation_key = Pubkey::new_unique();
+        let reserve_key = Pubkey::new_unique();
+
+        let fluidity_data = FluidityData::new(InitFluidityDataParams {
+            token_mint: Pubkey::new_unique(),
+            fluid_mint: Pubkey::new_unique(),
+            pda,
+            bump: 0,
+            reserve: reserve_key,
+            lending_market: Pubkey::new_unique(),
+            obligation: obligation_key,
+            collateral_mint: Pubkey::new_unique(),
+            liquidity_supply: Pubkey::new_unique(),
+            collateral_supply: Pubkey::new_unique(),
+            collateral: Pubkey::new_unique(),
+        });
+
+        // two liquidity per collateral
+        let mut reserve = Reserve {
+            version: PROGRAM_VERSION,
+            ..Reserve::default()
+        };
+        reserve.liquidity.available_amount = 200;
+        reserve.collateral.mint_total_supply = 100;
+        let mut reserve_data = vec![0; Reserve::LEN];
+        Reserve::pack(reserve, &mut reserve_data).unwrap();
+
+        let mut deposit = ObligationCollateral::new(reserve_key);
+        deposit.deposited_amount = 50;
+        let obligation = Obligation::new(InitObligationParams {
+            current_slot: 0,
+            lending_market: fluidity_data.lending_market,
+            owner: pda,
+            deposits: vec![deposit],
+            borrows: vec![],
+        });
+        let mut obligation_data = vec![0; Obligation::LEN];
+        Obligation::pack(obligation, &mut obligation_data).unwrap();
+
+        let mut obligation_lamports = 0;
+        let mut reserve_lamports = 0;
+        let mut other_lamports = 0;
+        let mut other_data = vec![];
+        let other_key = Pubkey::new_unique();
+        let obligation_info = AccountInfo::new(
+            &obligation_key, false, true, &mut obligation_lamports, &mut obligation_data,
+            &solend_program, false, 0,
+        );
+        let reserve_info = AccountInfo::new(
+            &reserve_key, false, true, &mut reserve_lamports, &mut reserve_data,
+            &solend_program, false, 0,
+        );
+        let other = AccountInfo::new(
+            &other_key, false, true, &mut other_lamports, &mut other_data, &program_id, false, 0,
+        );
+
+        let solend = Solend;
+        let deposited = solend.deposited(&fluidity_data, &[obligation_info.clone(), reserve_info.clone()]).unwrap();
+        assert_eq!(deposited.collateral_amount, 50);
+        assert_eq!(deposited.value, 100);
+        assert_eq!(
+            solend.underlying_value(&fluidity_data, &[obligation_info.clone(), reserve_info.clone()]),
+            Ok(100)
+        );
+        assert_eq!(
+            solend.underlying_value(&fluidity_data, &[other.clone(), reserve_info]),
+            Err(FluidityError::InvalidObligation.into())
+        );
+        assert_eq!(
+            solend.underlying_value(&fluidity_data, &[obligation_info, other]),
+            Err(FluidityError::InvalidReserve.into())
+        );
+    }
+
+    #[test]
+    fn test_deposited_across_reserves() {
+        let solend_program = Pubkey::from_str(SOLEND).unwrap();
+        let pda = Pubkey::new_unique();
+        let obligation_key = Pubkey::new_unique();
+        let lending_market_key = Pubkey::new_unique();
+        let reserve_keys = [Pubkey::new_unique(), Pubkey::new_unique()];
+
+        let fluidity_data = FluidityData::new(InitFluidityDataParams {
+            token_mint: Pubkey::new_unique(),
+            fluid_mint: Pubkey::new_unique(),
+            pda,
+            bump: 0,
+            reserve: reserve_keys[0],
+            lending_market: lending_market_key,
+            obligation: obligation_key,
+            collateral_mint: Pubkey::new_unique(),
+            liquidity_supply: Pubkey::new_unique(),
+            collateral_supply: Pubkey::new_unique(),
+            collateral: Pubkey::new_unique(),
+        });
+
+        // a 6 decimal token at $1 with two liquidity per collateral, and a
+        // 9 decimal token at $20 with one
+        let reserve_data = |available_amount, mint_total_supply, mint_decimals, price: u64| {
+            let mut reserve = Reserve {
+                version: PROGRAM_VERSION,
+                ..Reserve::default()
+            };
+            reserve.liquidity.available_amount = available_amount;
+            reserve.liquidity.mint_decimals = mint_decimals;
+            reserve.liquidity.market_price = Decimal::from(price);
+            reserve.collateral.mint_total_supply = mint_total_supply;
+            let mut data = vec![0; Reserve::LEN];
+            Reserve::pack(reserve, &mut data).unwrap();
+            data
+        };
+        let mut reserve_a_data = reserve_data(200_000_000, 100_000_000, 6, 1);
+        let mut reserve_b_data = reserve_data(1_000_000_000, 1_000_000_000, 9, 20);
+
+        let mut deposit_a = ObligationCollateral::new(reserve_keys[0]);
+        deposit_a.deposited_amount = 5_000_000;
+        let mut deposit_b = ObligationCollateral::new(reserve_keys[1]);
+        deposit_b.deposited_amount = 500_000_000;
+        let obligation = Obligation::new(InitObligationParams {
+            current_slot: 0,
+            lending_market: lending_market_key,
+            owner: pda,
+            deposits: vec![deposit_a, deposit_b],
+            borrows: vec![],
+        });
+        let mut obligation_data = vec![0; Obligation::LEN];
+        Obligation::pack(obligation, &mut obligation_data).unwrap();
+
+        let lending_market = LendingMarket {
+            version: PROGRAM_VERSION,
+            quote_currency: *b"USD\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0",
+            ..LendingMarket::default()
+        };
+        let mut lending_market_data = vec![0; LendingMarket::LEN];
+        LendingMarket::pack(lending_market, &mut lending_market_data).unwrap();
+
+        let mut lamports = [0; 4];
+        let [obligation_lamports, reserve_a_lamports, reserve_b_lamports, lending_market_lamports] = &mut lamports;
+        let obligation_info = AccountInfo::new(
+            &obligation_key, false, true, obligation_lamports, &mut obligation_data,
+            &solend_program, false, 0,
+        );
+        let reserve_a_info = AccountInfo::new(
+            &reserve_keys[0], false, true, reserve_a_lamports, &mut reserve_a_data,
+            &solend_program, false, 0,
+        );
+        let reserve_b_info = AccountInfo::new(
+            &reserve_keys[1], false, true, reserve_b_lamports, &mut reserve_b_data,
+            &solend_program, false, 0,
+        );
+        let lending_market_info = AccountInfo::new(
+            &lending_market_key, false, false, lending_market_lamports, &mut lending_market_data,
+            &solend_program, false, 0,
+        );
+
+        let solend = Solend;
+        let value_accounts = [obligation_info.clone(), reserve_a_info.clone(), reserve_b_info.clone()];
+        let deposited = solend.deposited(&fluidity_data, &value_accounts).unwrap();
+
+        // only the ten tokens in the token's own reserve back it, the market
+        // value adds the half a token at $20 through its own reserve
+        assert_eq!(deposited.value, 10_000_000);
+        assert_eq!(deposited.decimals, 6);
+        assert_eq!(deposited.collateral_amount, 5_000_000);
+        assert_eq!(deposited.market_value, Decimal::from(20u64).to_scaled_val().unwrap());
+
+        // and the other reserve's token is backed by its own deposit
+        let mut other_reserve_data = fluidity_data.clone();
+        other_reserve_data.reserve = reserve_keys[1];
+        let deposited = solend.deposited(&other_reserve_data, &value_accounts).unwrap();
+        assert_eq!(deposited.value, 500_000_000);
+        assert_eq!(deposited.decimals, 9);
+        assert_eq!(deposited.collateral_amount, 500_000_000);
+        assert_eq!(deposited.market_value, Decimal::from(20u64).to_scaled_val().unwrap());
+
+        // the reserves have to be in the order of the deposits
+        assert_eq!(
+            solend.deposited(&fluidity_data, &[obligation_info.clone(), reserve_b_info.clone(), reserve_a_info.clone()])
+                .map(|deposited| deposited.value),
+            Err(FluidityError::InvalidReserve.into())
+        );
+        assert_eq!(
+            solend.deposited(&fluidity_data, &value_accounts[..2]).map(|deposited| deposited.value),
+            Err(ProgramError::NotEnoughAccountKeys)
+        );
+
+        // the lending market ends the TVL layout, after the solend program,
+        // oracles and clock
+        let other = obligation_info.clone();
+        let mut tvl_accounts = value_accounts.to_vec();
+        tvl_accounts.extend(vec![other.clone(); 6]);
+        assert_eq!(solend.tvl_accounts_len(&fluidity_data, &tvl_accounts), Ok(10));
+        tvl_accounts.push(lending_market_info);
+        assert_eq!(solend.quote_currency(&fluidity_data, &tvl_accounts).unwrap()[..4], *b"USD\0");
+        tvl_accounts[9] = other;
+        assert_eq!(
+            solend.quote_currency(&fluidity_data, &tvl_accounts),
+            Err(FluidityError::InvalidLendingMarket.into())
+        );
+    }
+}
@@ -177,3 +177,152 @@ impl<T> DecodeError<T> for LendingError {
         "Lending Error"
     }
 }
+
+/// Errors that may be returned by the Fluidity program.
+#[derive(Clone, Debug, Eq, Error, FromPrimitive, PartialEq)]
+pub enum FluidityError {
+    // 1000, offset to not clash with LendingError
+    /// Wrapping and payouts are paused
+    #[error("Program is paused")]
+    Paused = 1000,
+    /// Unwrapping is paused
+    #[error("Unwrapping is paused")]
+    UnwrapPaused,
+    /// Expected the holder of a role to sign
+    #[error("Signer does not hold the required role")]
+    InvalidAuthority,
+    /// Expected the nominated admin to sign
+    #[error("Signer is not the pending admin")]
+    InvalidPendingAdmin,
+    /// The admin can only be handed over
+    #[error("Admin role cannot be granted or revoked")]
+    AdminNotGrantable,
+
+    // 1005
+    /// Expected the program's config account
+    #[error("Config account is invalid")]
+    InvalidConfigAccount,
+    /// Expected the data account derived for the token
+    #[error("Data account is invalid")]
+    InvalidDataAccount,
+    /// Expected the Solend program
+    #[error("Solend program is invalid")]
+    InvalidSolendProgram,
+    /// Base token mint doesn't match the data account
+    #[error("Token mint does not match the data account")]
+    InvalidTokenMint,
+    /// Fluid token mint doesn't match the data account
+    #[error("Fluid mint does not match the data account")]
+    InvalidFluidMint,
+
+    // 1010
+    /// Expected the canonical obligation authority of the token
+    #[error("Obligation authority is invalid")]
+    InvalidPda,
+    /// Expected an obligation owned by the obligation authority
+    #[error("Obligation owner is invalid")]
+    InvalidObligationOwner,
+    /// Expected a collateral account owned by the obligation authority
+    #[error("Collateral account owner is invalid")]
+    InvalidCollateralOwner,
+    /// Amount too small to survive Solend's rounding
+    #[error("Amount of liquidity is less than two")]
+    AmountTooSmall,
+
+    // 1015
+    /// Reserve doesn't match the data account
+    #[error("Reserve does not match the data account")]
+    InvalidReserve,
+    /// Lending market doesn't match the data account
+    #[error("Lending market does not match the data account")]
+    InvalidLendingMarket,
+    /// Obligation doesn't match the data account
+    #[error("Obligation does not match the data account")]
+    InvalidObligation,
+    /// Reserve collateral mint doesn't match the data account
+    #[error("Collateral mint does not match the data account")]
+    InvalidCollateralMint,
+    /// Reserve liquidity supply doesn't match the data account
+    #[error("Liquidity supply does not match the data account")]
+    InvalidLiquiditySupply,
+
+    // 1020
+    /// Reserve collateral supply doesn't match the data account
+    #[error("Collateral supply does not match the data account")]
+    InvalidCollateralSupply,
+    /// Obligation authority's collateral account doesn't match the data account
+    #[error("Collateral account does not match the data account")]
+    InvalidCollateralAccount,
+    /// Basis points above 100%
+    #[error("Basis points are above 10000")]
+    InvalidBasisPoints,
+    /// Payout receipt isn't derived from the transfer hash
+    #[error("Receipt account is invalid")]
+    InvalidReceipt,
+    /// Winning transfer already has a receipt
+    #[error("Transfer has already been paid out")]
+    PayoutAlreadyPaid,
+
+    // 1025
+    /// Winning transfer too old, or in the future
+    #[error("Transfer is outside the payout window")]
+    PayoutExpired,
+    /// Receipt still protects against replays
+    #[error("Receipt cannot be closed yet")]
+    ReceiptNotExpired,
+    /// Slot is no longer in the slot hashes sysvar
+    #[error("Transfer slot has no slot hash")]
+    SlotHashNotFound,
+    /// Draw didn't pick the transfer
+    #[error("Transfer did not win a prize")]
+    TransferDidNotWin,
+    /// Expected the transfer log derived for the token
+    #[error("Transfer log is invalid")]
+    InvalidTransferLog,
+
+    // 1030
+    /// Transfer log index not yet written, or already overwritten
+    #[error("Transfer is not in the transfer log")]
+    TransferNotLogged,
+    /// Payout accounts aren't the logged transfer's sender and receiver
+    #[error("Accounts do not match the logged transfer")]
+    InvalidTransferAccounts,
+    /// Expected the spl-token program
+    #[error("Token program is invalid")]
+    InvalidTokenProgram,
+    /// TVL log too small, or written with an unknown layout
+    #[error("TVL log is invalid")]
+    InvalidTvlLog,
+    /// Tokens logged together have to share a quote currency
+    #[error("Lending markets have different quote currencies")]
+    QuoteCurrencyMismatch,
+
+    // 1035
+    /// A token can't be deregistered while its fluid tokens are held
+    #[error("Fluid tokens are still held")]
+    FluidTokensOutstanding,
+    /// Deposits are worth less than the fluid supply, leaving nothing to pay out
+    #[error("Prize pool is empty")]
+    PrizePoolEmpty,
+    /// A wrap or unwrap realised less than the minimum it was given
+    #[error("Realised amount is below the minimum")]
+    SlippageExceeded,
+    /// Transfers of nothing, or to the sender, aren't logged
+    #[error("Transfer is empty or to its sender")]
+    InvalidTransfer,
+    /// A slot's limit of transfers has already been logged
+    #[error("Transfer log is full for this slot")]
+    TransferLogFull,
+}
+
+impl From<FluidityError> for ProgramError {
+    fn from(e: FluidityError) -> Self {
+        ProgramError::Custom(e as u32)
+    }
+}
+
+impl<T> DecodeError<T> for FluidityError {
+    fn type_of() -> &'static str {
+        "Fluidity Error"
+    }
+}
@@ -0,0 +1,216 @@
+// Events logged by the program's handlers, and decoding them from transaction logs
+
+use {
+    borsh::{BorshDeserialize, BorshSerialize},
+    solana_program::{clock::Slot, msg, pubkey::Pubkey},
+};
+
+// prefix of a line logging an event, followed by the base64 of its borsh
+// encoding, like the lines logged by sol_log_data
+pub const EVENT_PREFIX: &str = "Program data: ";
+
+// prefix the runtime adds to lines logged with msg
+const LOG_PREFIX: &str = "Program log: ";
+
+// scale of exchange rates, which are base tokens per collateral token
+pub const EXCHANGE_RATE_SCALE: u128 = 1_000_000_000_000_000_000;
+
+// events logged by the program
+#[derive(BorshDeserialize, BorshSerialize, Debug, PartialEq, Clone)]
+pub enum FluidityEvent {
+    // base tokens were wrapped into fluid tokens
+    Wrapped {
+        token_mint: Pubkey,
+        fluid_mint: Pubkey,
+        // the owner of the wrapped base tokens
+        owner: Pubkey,
+        // base tokens deposited
+        amount: u64,
+        // fluid tokens minted, which is less than the amount if the prize
+        // pool couldn't cover the deposit's rounding
+        fluid_amount: u64,
+        // collateral credited to the obligation authority
+        collateral_amount: u64,
+        // base tokens deposited per collateral token credited
+        exchange_rate: u128,
+    },
+    // fluid tokens were unwrapped into base tokens
+    Unwrapped {
+        token_mint: Pubkey,
+        fluid_mint: Pubkey,
+        // the owner of the burned fluid tokens
+        owner: Pubkey,
+        // fluid tokens burned
+        amount: u64,
+        // base tokens withdrawn to the owner, which can differ from the
+        // amount by the withdrawal's rounding
+        base_amount: u64,
+        // collateral redeemed by the obligation authority
+        collateral_amount: u64,
+        // base tokens withdrawn per collateral token redeemed
+        exchange_rate: u128,
+    },
+    // a winning transfer was paid out
+    PaidOut {
+        fluid_mint: Pubkey,
+        // the fluid token accounts paid
+        sender: Pubkey,
+        receiver: Pubkey,
+        sender_prize: u64,
+        receiver_prize: u64,
+        // the transfer identifying the payout's receipt
+        transfer_hash: [u8; 32],
+        transfer_slot: Slot,
+        // value of the deposits and supply of the fluid token the prize pool
+        // was valued at
+        deposited_value: u64,
+        fluid_supply: u64,
+    },
+    // the value of a token's deposits was logged
+    TvlLogged {
+        token_mint: Pubkey,
+        fluid_mint: Pubkey,
+        // value in base tokens of the deposits, with interest
+        deposited_value: u64,
+        // value in the lending market's quote currency, scaled by 10^18
+        market_value: u128,
+    },
+    // a token pair was registered
+    TokenRegistered {
+        token_mint: Pubkey,
+        fluid_mint: Pubkey,
+        // the lending reserve and collateral mint of the base token
+        reserve: Pubkey,
+        collateral_mint: Pubkey,
+    },
+    // a token pair was deregistered
+    TokenDeregistered {
+        token_mint: Pubkey,
+        fluid_mint: Pubkey,
+        // collateral redeemed to the treasury
+        collateral_amount: u64,
+        // fluid tokens left held, at most the dust allowed
+        fluid_supply: u64,
+    },
+}
+
+impl FluidityEvent {
+    // log the event
+    pub(crate) fn emit(&self) {
+        msg!("{}", self.encode());
+    }
+
+    // the line logging the event
+    pub fn encode(&self) -> String {
+        format!("{}{}", EVENT_PREFIX, base64::encode(self.try_to_vec().unwrap()))
+    }
+
+    // parse an event from a line logged by the program, with or without the
+    // runtime's log prefix
+    pub fn decode(line: &str) -> Option<Self> {
+        let line = line.strip_prefix(LOG_PREFIX).unwrap_or(line);
+        let data = base64::decode(line.strip_prefix(EVENT_PREFIX)?).ok()?;
+
+        Self::try_from_slice(&data).ok()
+    }
+}
+
+// base tokens per collateral token, scaled by EXCHANGE_RATE_SCALE, zero
+// when there's no collateral
+pub fn exchange_rate(amount: u64, collateral_amount: u64) -> u128 {
+    if collateral_amount == 0 {
+        return 0;
+    }
+
+    amount as u128 * EXCHANGE_RATE_SCALE / collateral_amount as u128
+}
+
+// parse the events logged by a program from a transaction's log lines,
+// skipping lines logged by the programs it invokes
+pub fn decode_logs<S: AsRef<str>>(program_id: &Pubkey, logs: &[S]) -> Vec<FluidityEvent> {
+    let program_id = program_id.to_string();
+    let mut invoked: Vec<&str> = Vec::new();
+    let mut events = Vec::new();
+
+    for line in logs {
+        let line = line.as_ref();
+        let words: Vec<&str> = line.split_whitespace().collect();
+
+        match words.as_slice() {
+            ["Program", program, "invoke", _depth] => invoked.push(program),
+            ["Program", program, "success"] if invoked.last() == Some(program) => {
+                invoked.pop();
+            }
+            ["Program", program, "failed:", ..] if invoked.last() == Some(program) => {
+                invoked.pop();
+            }
+            _ if invoked.last() == Some(&program_id.as_str()) => {
+                events.extend(FluidityEvent::decode(line));
+            }
+            _ => {}
+        }
+    }
+
+    events
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_decode_logs() {
+        let program_id = Pubkey::new_unique();
+        let solend = Pubkey::new_unique();
+        let wrapped = FluidityEvent::Wrapped {
+            token_mint: Pubkey::new_unique(),
+            fluid_mint: Pubkey::new_unique(),
+            owner: Pubkey::new_unique(),
+            amount: 1_000,
+            fluid_amount: 999,
+            collateral_amount: 800,
+            exchange_rate: exchange_rate(1_000, 800),
+        };
+        let unwrapped = FluidityEvent::Unwrapped {
+            token_mint: Pubkey::new_unique(),
+            fluid_mint: Pubkey::new_unique(),
+            owner: Pubkey::new_unique(),
+            amount: 1_000,
+            base_amount: 1_001,
+            collateral_amount: 801,
+            exchange_rate: exchange_rate(1_001, 801),
+        };
+        let logged = FluidityEvent::TvlLogged {
+            token_mint: Pubkey::new_unique(),
+            fluid_mint: Pubkey::new_unique(),
+            deposited_value: 1_250,
+            market_value: 1_250_000_000_000_000_000_000,
+        };
+
+        let logs = vec![
+            format!("Program {} invoke [1]", program_id),
+            format!("Program {} invoke [2]", solend),
+            // events are only read from the program itself
+            format!("{}{}", LOG_PREFIX, logged.encode()),
+            format!("Program {} success", solend),
+            "Program log: bad data account".to_string(),
+            format!("{}{}", LOG_PREFIX, wrapped.encode()),
+            format!("{}{}", EVENT_PREFIX, "not base64!"),
+            logged.encode(),
+            unwrapped.encode(),
+            format!("Program {} consumed 10000 of 200000 compute units", program_id),
+            format!("Program {} success", program_id),
+            format!("{}{}", LOG_PREFIX, wrapped.encode()),
+        ];
+
+        assert_eq!(decode_logs(&program_id, &logs), vec![wrapped, logged, unwrapped]);
+    }
+
+    #[test]
+    fn test_exchange_rate() {
+        assert_eq!(exchange_rate(1_000, 1_000), EXCHANGE_RATE_SCALE);
+        assert_eq!(exchange_rate(1_250, 1_000), EXCHANGE_RATE_SCALE / 4 * 5);
+        assert_eq!(exchange_rate(u64::MAX, 1), u64::MAX as u128 * EXCHANGE_RATE_SCALE);
+        assert_eq!(exchange_rate(1_000, 0), 0);
+    }
+}
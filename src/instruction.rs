@@ -1,25 +1,136 @@
 // instruction types
 
+use crate::{
+    processor::{
+        data_seed, fluid_mint_seed, logged_transfer_hash, obligation_authority_seed, staging_seed,
+        CONFIG_SEED, RECEIPT_SEED,
+    },
+    tvl::{tvl_data_seed, TVL_DATA_SEED},
+};
 use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    clock::Slot,
+    instruction::{AccountMeta, Instruction},
+    pubkey::{Pubkey, PubkeyError},
+    system_program, sysvar,
+};
 
 // fluidity instructions
 // enum for processes executable by fluidity smart contract
 #[derive(BorshDeserialize, BorshSerialize, Debug, PartialEq, Clone)]
 pub enum FluidityInstruction {
     // wrap fluid token
-    Wrap(u64, String, u8),
+    Wrap(u64, String),
     // unwrap fluid token
-    Unwrap(u64, String, u8),
-    // payout two accounts
-    Payout (u64, String, u8),
+    Unwrap(u64, String),
+    // payout two accounts for a winning transfer, given its hash and slot
+    Payout (u64, String, [u8; 32], Slot),
     // initialise solend obligation account
-    InitSolendObligation (u64, u64, String, u8),
-    LogTVL,
-    InitData(String, u64, u64, u8),
+    InitSolendObligation (u64, u64, String),
+    // write the value of the named tokens' deposits to their TVL logs, and
+    // their sum to a data account
+    LogTVL(Vec<String>),
+    InitData(String, u64, u64),
+    // create the config account with the initial admin
+    InitConfig(Pubkey),
+    // nominate a new admin
+    ProposeAdmin(Pubkey),
+    // accept the admin nomination
+    AcceptAdmin,
+    // give a key one of the roles
+    GrantRole(Role, Pubkey),
+    // take a role away from its holder
+    RevokeRole(Role),
+    // set the pause flags for wrapping and payouts, and for unwrapping, of
+    // every token or of the named token
+    SetPaused(Option<String>, bool, bool),
+    // set the share of the prize pool the named token's payouts can take,
+    // and the sender's share of each payout, in basis points
+    SetPayoutParams(String, u16, u16),
+    // payout many winning transfers
+    BatchPayout(Vec<Winner>, String),
+    // close the receipt of an old winning transfer, given its hash
+    CloseReceipt([u8; 32]),
+    // payout the sender and receiver of a transfer, given its index in the
+    // named token's transfer log, if the draw picks it as a winner
+    DrawPayout(String, u64),
+    // set the odds table of the named token's drawn payouts
+    SetDrawTiers(String, [DrawTier; DRAW_TIERS]),
+    // create the named token's fluid mint, data account and obligation
+    RegisterToken(String),
+    // set up the named token's transfer log in an account created for it
+    InitTransferLog(String),
+    // transfer fluid tokens, logging the transfer
+    TransferFluid(u64, String),
+    // payout the sender and receiver of a transfer, given its index in the
+    // named token's transfer log
+    PayoutTransfer(u64, String, u64),
+    // upgrade the named token's data account from the legacy layout it was
+    // written with
+    MigrateData(String, LegacyLayout),
+    // redeem what's left of the named token's deposits to a treasury account
+    // and close its data account
+    DeregisterToken(String),
+    // wrap fluid token, failing if fewer than the minimum are minted
+    WrapMinOut(u64, u64, String),
+    // unwrap fluid token, failing if fewer than the minimum base tokens are
+    // withdrawn
+    UnwrapMinOut(u64, u64, String),
+    // close the TVL data account written by the unversioned LogTVL, too small
+    // for a TVL log, so it can be created again with TVL_LOG_SPACE
+    CloseTvlData,
+}
+
+// the unversioned layouts data accounts were written with before they were
+// packed
+#[derive(BorshDeserialize, BorshSerialize, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum LegacyLayout {
+    // the mints and the pda, written by the first init_data
+    Mints,
+}
+
+// number of tiers in a token's odds table
+pub const DRAW_TIERS: usize = 4;
+
+// a tier of the odds table, winning one in `odds` draws
+#[derive(BorshSerialize, BorshDeserialize, Debug, Default, PartialEq, Clone, Copy)]
+pub struct DrawTier {
+    // the chance of winning is one in odds, zero disables the tier
+    pub odds: u32,
+    // the amount paid out when the tier wins
+    pub prize: u64,
+}
+
+// a winning transfer paid out by BatchPayout
+#[derive(BorshSerialize, BorshDeserialize, Debug, PartialEq, Clone)]
+pub struct Winner {
+    // the amount to pay out
+    pub amount: u64,
+    // the index of the sender among the payout accounts
+    pub sender: u8,
+    // the index of the receiver among the payout accounts
+    pub receiver: u8,
+    // the hash identifying the transfer, usually its signature's hash
+    pub transfer_hash: [u8; 32],
+    // the slot the transfer happened in
+    pub transfer_slot: Slot,
+}
+
+// roles that can be held in the config account
+#[derive(BorshDeserialize, BorshSerialize, Debug, PartialEq, Clone, Copy)]
+pub enum Role {
+    // hands over the admin and grants the other roles
+    Admin,
+    // runs payouts
+    PayoutOperator,
+    // registers token pairs
+    RegistryAdmin,
+    // pauses the program in an emergency
+    Guardian,
 }
 
 // solend instructions
-#[derive(BorshSerialize)]
+#[derive(BorshSerialize, BorshDeserialize)]
 pub enum LendingInstruction {
     InitLendingMarket,
 
@@ -114,3 +225,1089 @@ pub enum LendingInstruction {
         collateral_amount: u64,
     },
 }
+
+/// Solend accounts passed through by `Wrap` and `Unwrap`
+#[derive(Clone, Debug, PartialEq)]
+pub struct SolendAccounts {
+    /// The Solend lending program
+    pub solend_program: Pubkey,
+    /// The obligation authority's collateral token account
+    pub collateral: Pubkey,
+    /// The reserve of the base token
+    pub reserve: Pubkey,
+    /// The reserve's liquidity supply token account
+    pub reserve_liquidity_supply: Pubkey,
+    /// The reserve's collateral mint
+    pub reserve_collateral_mint: Pubkey,
+    /// The reserve's lending market
+    pub lending_market: Pubkey,
+    /// The lending market's derived authority
+    pub lending_market_authority: Pubkey,
+    /// The reserve's collateral supply token account
+    pub reserve_collateral_supply: Pubkey,
+    /// The obligation owned by the obligation authority
+    pub obligation: Pubkey,
+    /// The reserve's Pyth price account
+    pub pyth_price: Pubkey,
+    /// The reserve's Switchboard feed account
+    pub switchboard_feed: Pubkey,
+}
+
+/// The accounts `LogTVL` values a token's deposits with
+#[derive(Clone, Debug, PartialEq)]
+pub struct TvlAccounts {
+    /// The token's name
+    pub seed: String,
+    /// The token's data account
+    pub fluidity_data_account: Pubkey,
+    /// The token's obligation authority
+    pub pda_account: Pubkey,
+    /// The Solend program
+    pub solend_program: Pubkey,
+    /// The obligation owned by the obligation authority
+    pub obligation: Pubkey,
+    /// The obligation's lending market
+    pub lending_market: Pubkey,
+    /// The reserve of each of the obligation's deposits, in order
+    pub reserves: Vec<DepositReserve>,
+}
+
+/// A reserve an obligation has deposits in, with its oracles
+#[derive(Clone, Debug, PartialEq)]
+pub struct DepositReserve {
+    /// The reserve
+    pub reserve: Pubkey,
+    /// The reserve's Pyth price account
+    pub pyth_price: Pubkey,
+    /// The reserve's Switchboard feed account
+    pub switchboard_feed: Pubkey,
+}
+
+/// Derives the address of the program's config account
+pub fn find_config_address(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[CONFIG_SEED.as_bytes()], program_id)
+}
+
+/// Derives the obligation authority of a token from its name, the pda that
+/// owns the token's obligation and mints its fluid token
+pub fn find_obligation_authority_address(program_id: &Pubkey, seed: &str) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[obligation_authority_seed(seed).as_bytes()], program_id)
+}
+
+/// Derives the receipt of a winning transfer from its hash
+pub fn find_receipt_address(program_id: &Pubkey, transfer_hash: &[u8; 32]) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[RECEIPT_SEED.as_bytes(), transfer_hash], program_id)
+}
+
+/// Derives the data account of a token from its name
+pub fn find_data_address(program_id: &Pubkey, seed: &str) -> Result<Pubkey, PubkeyError> {
+    let (pda, _) = find_obligation_authority_address(program_id, seed);
+    Pubkey::create_with_seed(&pda, &data_seed(seed), program_id)
+}
+
+/// Derives the fluid mint of a token registered with `RegisterToken` from its
+/// name
+pub fn find_fluid_mint_address(program_id: &Pubkey, seed: &str) -> Result<Pubkey, PubkeyError> {
+    let (pda, _) = find_obligation_authority_address(program_id, seed);
+    Pubkey::create_with_seed(&pda, &fluid_mint_seed(seed), &spl_token::id())
+}
+
+/// Derives the account a token's data is staged in while `MigrateData`
+/// re-creates its data account
+pub fn find_staging_address(program_id: &Pubkey, seed: &str) -> Result<Pubkey, PubkeyError> {
+    let (pda, _) = find_obligation_authority_address(program_id, seed);
+    Pubkey::create_with_seed(&pda, &staging_seed(seed), program_id)
+}
+
+// accounts shared by wrap and unwrap, in the order the processor reads them
+#[allow(clippy::too_many_arguments)]
+fn wrap_accounts(
+    program_id: &Pubkey,
+    fluidity_data_account: &Pubkey,
+    token_mint: &Pubkey,
+    fluidity_mint: &Pubkey,
+    pda_account: &Pubkey,
+    sender: &Pubkey,
+    token_account: &Pubkey,
+    fluidity_account: &Pubkey,
+    solend: &SolendAccounts,
+) -> Vec<AccountMeta> {
+    let mut accounts = vec![
+        AccountMeta::new_readonly(*fluidity_data_account, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(*token_mint, false),
+        AccountMeta::new(*fluidity_mint, false),
+        AccountMeta::new(*pda_account, false),
+        AccountMeta::new(*sender, true),
+        AccountMeta::new(*token_account, false),
+        AccountMeta::new(*fluidity_account, false),
+        AccountMeta::new_readonly(find_config_address(program_id).0, false),
+    ];
+    accounts.extend(solend_reserve_accounts(solend));
+
+    accounts
+}
+
+// the solend accounts of a deposit or withdrawal, in the order the solend
+// adapter reads them
+fn solend_reserve_accounts(solend: &SolendAccounts) -> Vec<AccountMeta> {
+    vec![
+        AccountMeta::new_readonly(solend.solend_program, false),
+        AccountMeta::new(solend.collateral, false),
+        AccountMeta::new(solend.reserve, false),
+        AccountMeta::new(solend.reserve_liquidity_supply, false),
+        AccountMeta::new(solend.reserve_collateral_mint, false),
+        AccountMeta::new(solend.lending_market, false),
+        AccountMeta::new_readonly(solend.lending_market_authority, false),
+        AccountMeta::new(solend.reserve_collateral_supply, false),
+        AccountMeta::new(solend.obligation, false),
+        AccountMeta::new_readonly(solend.pyth_price, false),
+        AccountMeta::new_readonly(solend.switchboard_feed, false),
+        AccountMeta::new_readonly(sysvar::clock::id(), false),
+    ]
+}
+
+/// Creates a `Wrap` instruction.
+#[allow(clippy::too_many_arguments)]
+pub fn wrap(
+    program_id: &Pubkey,
+    fluidity_data_account: &Pubkey,
+    token_mint: &Pubkey,
+    fluidity_mint: &Pubkey,
+    pda_account: &Pubkey,
+    sender: &Pubkey,
+    token_account: &Pubkey,
+    fluidity_account: &Pubkey,
+    solend: &SolendAccounts,
+    amount: u64,
+    seed: String,
+) -> Instruction {
+    Instruction::new_with_borsh(
+        *program_id,
+        &FluidityInstruction::Wrap(amount, seed),
+        wrap_accounts(
+            program_id, fluidity_data_account, token_mint, fluidity_mint, pda_account,
+            sender, token_account, fluidity_account, solend,
+        ),
+    )
+}
+
+/// Creates an `Unwrap` instruction.
+#[allow(clippy::too_many_arguments)]
+pub fn unwrap(
+    program_id: &Pubkey,
+    fluidity_data_account: &Pubkey,
+    token_mint: &Pubkey,
+    fluidity_mint: &Pubkey,
+    pda_account: &Pubkey,
+    sender: &Pubkey,
+    token_account: &Pubkey,
+    fluidity_account: &Pubkey,
+    solend: &SolendAccounts,
+    amount: u64,
+    seed: String,
+) -> Instruction {
+    Instruction::new_with_borsh(
+        *program_id,
+        &FluidityInstruction::Unwrap(amount, seed),
+        wrap_accounts(
+            program_id, fluidity_data_account, token_mint, fluidity_mint, pda_account,
+            sender, token_account, fluidity_account, solend,
+        ),
+    )
+}
+
+/// Creates a `WrapMinOut` instruction, which fails if fewer than `min_out`
+/// fluid tokens are minted.
+#[allow(clippy::too_many_arguments)]
+pub fn wrap_min_out(
+    program_id: &Pubkey,
+    fluidity_data_account: &Pubkey,
+    token_mint: &Pubkey,
+    fluidity_mint: &Pubkey,
+    pda_account: &Pubkey,
+    sender: &Pubkey,
+    token_account: &Pubkey,
+    fluidity_account: &Pubkey,
+    solend: &SolendAccounts,
+    amount: u64,
+    min_out: u64,
+    seed: String,
+) -> Instruction {
+    Instruction::new_with_borsh(
+        *program_id,
+        &FluidityInstruction::WrapMinOut(amount, min_out, seed),
+        wrap_accounts(
+            program_id, fluidity_data_account, token_mint, fluidity_mint, pda_account,
+            sender, token_account, fluidity_account, solend,
+        ),
+    )
+}
+
+/// Creates an `UnwrapMinOut` instruction, which fails if fewer than
+/// `min_out` base tokens are withdrawn.
+#[allow(clippy::too_many_arguments)]
+pub fn unwrap_min_out(
+    program_id: &Pubkey,
+    fluidity_data_account: &Pubkey,
+    token_mint: &Pubkey,
+    fluidity_mint: &Pubkey,
+    pda_account: &Pubkey,
+    sender: &Pubkey,
+    token_account: &Pubkey,
+    fluidity_account: &Pubkey,
+    solend: &SolendAccounts,
+    amount: u64,
+    min_out: u64,
+    seed: String,
+) -> Instruction {
+    Instruction::new_with_borsh(
+        *program_id,
+        &FluidityInstruction::UnwrapMinOut(amount, min_out, seed),
+        wrap_accounts(
+            program_id, fluidity_data_account, token_mint, fluidity_mint, pda_account,
+            sender, token_account, fluidity_account, solend,
+        ),
+    )
+}
+
+/// Creates a `Payout` instruction.
+#[allow(clippy::too_many_arguments)]
+pub fn payout(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    fluidity_mint: &Pubkey,
+    pda_account: &Pubkey,
+    obligation: &Pubkey,
+    reserve: &Pubkey,
+    payout_account_a: &Pubkey,
+    payout_account_b: &Pubkey,
+    fluidity_data_account: &Pubkey,
+    amount: u64,
+    seed: String,
+    transfer_hash: [u8; 32],
+    transfer_slot: Slot,
+) -> Instruction {
+    Instruction::new_with_borsh(
+        *program_id,
+        &FluidityInstruction::Payout(amount, seed, transfer_hash, transfer_slot),
+        vec![
+            AccountMeta::new_readonly(find_config_address(program_id).0, false),
+            AccountMeta::new(*payer, true),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new(*fluidity_mint, false),
+            AccountMeta::new_readonly(*pda_account, false),
+            AccountMeta::new(*payout_account_a, false),
+            AccountMeta::new(*payout_account_b, false),
+            AccountMeta::new_readonly(*fluidity_data_account, false),
+            AccountMeta::new(find_receipt_address(program_id, &transfer_hash).0, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(sysvar::clock::id(), false),
+            AccountMeta::new_readonly(*obligation, false),
+            AccountMeta::new_readonly(*reserve, false),
+        ],
+    )
+}
+
+/// Creates a `DrawPayout` instruction. `sender` and `receiver` have to be
+/// the accounts logged for the transfer.
+#[allow(clippy::too_many_arguments)]
+pub fn draw_payout(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    fluidity_mint: &Pubkey,
+    pda_account: &Pubkey,
+    obligation: &Pubkey,
+    reserve: &Pubkey,
+    sender: &Pubkey,
+    receiver: &Pubkey,
+    fluidity_data_account: &Pubkey,
+    transfer_log: &Pubkey,
+    seed: String,
+    index: u64,
+) -> Instruction {
+    let transfer_hash = logged_transfer_hash(fluidity_mint, index);
+
+    Instruction::new_with_borsh(
+        *program_id,
+        &FluidityInstruction::DrawPayout(seed, index),
+        vec![
+            AccountMeta::new_readonly(find_config_address(program_id).0, false),
+            AccountMeta::new(*payer, true),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new(*fluidity_mint, false),
+            AccountMeta::new_readonly(*pda_account, false),
+            AccountMeta::new(*sender, false),
+            AccountMeta::new(*receiver, false),
+            AccountMeta::new_readonly(*fluidity_data_account, false),
+            AccountMeta::new(find_receipt_address(program_id, &transfer_hash).0, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(sysvar::clock::id(), false),
+            AccountMeta::new_readonly(*transfer_log, false),
+            AccountMeta::new_readonly(sysvar::slot_hashes::id(), false),
+            AccountMeta::new_readonly(*obligation, false),
+            AccountMeta::new_readonly(*reserve, false),
+        ],
+    )
+}
+
+/// Creates a `PayoutTransfer` instruction. `sender` and `receiver` have to
+/// be the accounts logged for the transfer.
+#[allow(clippy::too_many_arguments)]
+pub fn payout_transfer(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    fluidity_mint: &Pubkey,
+    pda_account: &Pubkey,
+    obligation: &Pubkey,
+    reserve: &Pubkey,
+    sender: &Pubkey,
+    receiver: &Pubkey,
+    fluidity_data_account: &Pubkey,
+    transfer_log: &Pubkey,
+    amount: u64,
+    seed: String,
+    index: u64,
+) -> Instruction {
+    let transfer_hash = logged_transfer_hash(fluidity_mint, index);
+
+    Instruction::new_with_borsh(
+        *program_id,
+        &FluidityInstruction::PayoutTransfer(amount, seed, index),
+        vec![
+            AccountMeta::new_readonly(find_config_address(program_id).0, false),
+            AccountMeta::new(*payer, true),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new(*fluidity_mint, false),
+            AccountMeta::new_readonly(*pda_account, false),
+            AccountMeta::new(*sender, false),
+            AccountMeta::new(*receiver, false),
+            AccountMeta::new_readonly(*fluidity_data_account, false),
+            AccountMeta::new(find_receipt_address(program_id, &transfer_hash).0, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(sysvar::clock::id(), false),
+            AccountMeta::new_readonly(*transfer_log, false),
+            AccountMeta::new_readonly(*obligation, false),
+            AccountMeta::new_readonly(*reserve, false),
+        ],
+    )
+}
+
+/// Creates a `BatchPayout` instruction. Each winner references its sender
+/// and receiver by index in `payout_accounts`, every one of which has to be
+/// referenced by a winner.
+#[allow(clippy::too_many_arguments)]
+pub fn batch_payout(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    fluidity_mint: &Pubkey,
+    pda_account: &Pubkey,
+    fluidity_data_account: &Pubkey,
+    obligation: &Pubkey,
+    reserve: &Pubkey,
+    payout_accounts: &[Pubkey],
+    winners: Vec<Winner>,
+    seed: String,
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new_readonly(find_config_address(program_id).0, false),
+        AccountMeta::new(*payer, true),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new(*fluidity_mint, false),
+        AccountMeta::new_readonly(*pda_account, false),
+        AccountMeta::new_readonly(*fluidity_data_account, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(sysvar::clock::id(), false),
+    ];
+    accounts.extend(payout_accounts.iter().map(|account| AccountMeta::new(*account, false)));
+    accounts.extend(winners.iter().map(|winner| {
+        AccountMeta::new(find_receipt_address(program_id, &winner.transfer_hash).0, false)
+    }));
+    accounts.push(AccountMeta::new_readonly(*obligation, false));
+    accounts.push(AccountMeta::new_readonly(*reserve, false));
+
+    Instruction::new_with_borsh(
+        *program_id,
+        &FluidityInstruction::BatchPayout(winners, seed),
+        accounts,
+    )
+}
+
+/// Creates a `CloseReceipt` instruction, sending the receipt's rent to
+/// `destination`.
+pub fn close_receipt(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    destination: &Pubkey,
+    transfer_hash: [u8; 32],
+) -> Instruction {
+    let mut accounts = config_accounts(program_id, payer);
+    accounts.push(AccountMeta::new(find_receipt_address(program_id, &transfer_hash).0, false));
+    accounts.push(AccountMeta::new(*destination, false));
+    accounts.push(AccountMeta::new_readonly(sysvar::clock::id(), false));
+
+    Instruction::new_with_borsh(
+        *program_id,
+        &FluidityInstruction::CloseReceipt(transfer_hash),
+        accounts,
+    )
+}
+
+/// Creates an `InitSolendObligation` instruction.
+#[allow(clippy::too_many_arguments)]
+pub fn init_solend_obligation(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    solend_program: &Pubkey,
+    obligation: &Pubkey,
+    lending_market: &Pubkey,
+    pda_account: &Pubkey,
+    fluidity_data_account: &Pubkey,
+    obligation_lamports: u64,
+    obligation_size: u64,
+    seed: String,
+) -> Instruction {
+    Instruction::new_with_borsh(
+        *program_id,
+        &FluidityInstruction::InitSolendObligation(obligation_lamports, obligation_size, seed),
+        vec![
+            AccountMeta::new_readonly(find_config_address(program_id).0, false),
+            AccountMeta::new(*payer, true),
+            AccountMeta::new_readonly(*solend_program, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new(*obligation, false),
+            AccountMeta::new(*lending_market, false),
+            AccountMeta::new(*pda_account, false),
+            AccountMeta::new_readonly(sysvar::clock::id(), false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(*fluidity_data_account, false),
+        ],
+    )
+}
+
+/// Creates a `LogTVL` instruction, logging every token in `tokens` to its
+/// TVL log derived from `base`, and their sum to `data_account`.
+pub fn log_tvl(
+    program_id: &Pubkey,
+    data_account: &Pubkey,
+    base: &Pubkey,
+    tokens: &[TvlAccounts],
+) -> Result<Instruction, PubkeyError> {
+    let mut accounts = vec![
+        AccountMeta::new(*data_account, false),
+        AccountMeta::new_readonly(*base, false),
+    ];
+    for token in tokens {
+        let tvl_log = Pubkey::create_with_seed(base, &tvl_data_seed(&token.seed), program_id)?;
+        accounts.push(AccountMeta::new(tvl_log, false));
+        accounts.push(AccountMeta::new_readonly(token.fluidity_data_account, false));
+        accounts.push(AccountMeta::new_readonly(token.pda_account, false));
+        accounts.push(AccountMeta::new(token.obligation, false));
+        accounts.extend(token.reserves.iter().map(|reserve| AccountMeta::new(reserve.reserve, false)));
+        accounts.push(AccountMeta::new_readonly(token.solend_program, false));
+        for reserve in &token.reserves {
+            accounts.push(AccountMeta::new_readonly(reserve.pyth_price, false));
+            accounts.push(AccountMeta::new_readonly(reserve.switchboard_feed, false));
+        }
+        accounts.push(AccountMeta::new_readonly(sysvar::clock::id(), false));
+        accounts.push(AccountMeta::new_readonly(token.lending_market, false));
+    }
+
+    Ok(Instruction::new_with_borsh(
+        *program_id,
+        &FluidityInstruction::LogTVL(tokens.iter().map(|token| token.seed.clone()).collect()),
+        accounts,
+    ))
+}
+
+/// Creates a `CloseTvlData` instruction, closing the TVL data account
+/// derived from `base` that the unversioned `LogTVL` wrote to. Once closed, it
+/// can be created again by `base` with `tvl::TVL_LOG_SPACE` in a later
+/// transaction.
+pub fn close_tvl_data(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    base: &Pubkey,
+    destination: &Pubkey,
+) -> Result<Instruction, PubkeyError> {
+    let mut accounts = config_accounts(program_id, admin);
+    accounts.push(AccountMeta::new(Pubkey::create_with_seed(base, TVL_DATA_SEED, program_id)?, false));
+    accounts.push(AccountMeta::new_readonly(*base, false));
+    accounts.push(AccountMeta::new(*destination, false));
+
+    Ok(Instruction::new_with_borsh(
+        *program_id,
+        &FluidityInstruction::CloseTvlData,
+        accounts,
+    ))
+}
+
+/// Creates an `InitData` instruction.
+#[allow(clippy::too_many_arguments)]
+pub fn init_data(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    data_account: &Pubkey,
+    token_mint: &Pubkey,
+    fluid_mint: &Pubkey,
+    pda_account: &Pubkey,
+    solend_program: &Pubkey,
+    reserve: &Pubkey,
+    obligation: &Pubkey,
+    collateral: &Pubkey,
+    seed: String,
+    lamports: u64,
+    space: u64,
+) -> Instruction {
+    Instruction::new_with_borsh(
+        *program_id,
+        &FluidityInstruction::InitData(seed, lamports, space),
+        vec![
+            AccountMeta::new_readonly(find_config_address(program_id).0, false),
+            AccountMeta::new(*payer, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new(*data_account, false),
+            AccountMeta::new_readonly(*token_mint, false),
+            AccountMeta::new_readonly(*fluid_mint, false),
+            AccountMeta::new_readonly(*pda_account, false),
+            AccountMeta::new_readonly(*solend_program, false),
+            AccountMeta::new_readonly(*reserve, false),
+            AccountMeta::new_readonly(*obligation, false),
+            AccountMeta::new_readonly(*collateral, false),
+        ],
+    )
+}
+
+/// Creates a `RegisterToken` instruction.
+#[allow(clippy::too_many_arguments)]
+pub fn register_token(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    token_mint: &Pubkey,
+    solend_program: &Pubkey,
+    reserve: &Pubkey,
+    lending_market: &Pubkey,
+    collateral: &Pubkey,
+    seed: String,
+) -> Result<Instruction, PubkeyError> {
+    let (pda, _) = find_obligation_authority_address(program_id, &seed);
+    let obligation = Pubkey::create_with_seed(&pda, &lending_market.to_string()[0..32], solend_program)?;
+
+    let accounts = vec![
+        AccountMeta::new_readonly(find_config_address(program_id).0, false),
+        AccountMeta::new(*payer, true),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new(find_data_address(program_id, &seed)?, false),
+        AccountMeta::new_readonly(*token_mint, false),
+        AccountMeta::new(find_fluid_mint_address(program_id, &seed)?, false),
+        AccountMeta::new_readonly(pda, false),
+        AccountMeta::new_readonly(*solend_program, false),
+        AccountMeta::new_readonly(*reserve, false),
+        AccountMeta::new(obligation, false),
+        AccountMeta::new_readonly(*collateral, false),
+        AccountMeta::new_readonly(*lending_market, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(sysvar::clock::id(), false),
+        AccountMeta::new_readonly(sysvar::rent::id(), false),
+    ];
+
+    Ok(Instruction::new_with_borsh(
+        *program_id,
+        &FluidityInstruction::RegisterToken(seed),
+        accounts,
+    ))
+}
+
+/// Creates an `InitConfig` instruction.
+pub fn init_config(program_id: &Pubkey, payer: &Pubkey, admin: &Pubkey) -> Instruction {
+    Instruction::new_with_borsh(
+        *program_id,
+        &FluidityInstruction::InitConfig(*admin),
+        vec![
+            AccountMeta::new(find_config_address(program_id).0, false),
+            AccountMeta::new(*payer, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+// accounts of the instructions that update the config account
+fn config_accounts(program_id: &Pubkey, authority: &Pubkey) -> Vec<AccountMeta> {
+    vec![
+        AccountMeta::new(find_config_address(program_id).0, false),
+        AccountMeta::new_readonly(*authority, true),
+    ]
+}
+
+/// Creates a `ProposeAdmin` instruction.
+pub fn propose_admin(program_id: &Pubkey, admin: &Pubkey, new_admin: &Pubkey) -> Instruction {
+    Instruction::new_with_borsh(
+        *program_id,
+        &FluidityInstruction::ProposeAdmin(*new_admin),
+        config_accounts(program_id, admin),
+    )
+}
+
+/// Creates an `AcceptAdmin` instruction.
+pub fn accept_admin(program_id: &Pubkey, pending_admin: &Pubkey) -> Instruction {
+    Instruction::new_with_borsh(
+        *program_id,
+        &FluidityInstruction::AcceptAdmin,
+        config_accounts(program_id, pending_admin),
+    )
+}
+
+/// Creates a `GrantRole` instruction.
+pub fn grant_role(program_id: &Pubkey, admin: &Pubkey, role: Role, key: &Pubkey) -> Instruction {
+    Instruction::new_with_borsh(
+        *program_id,
+        &FluidityInstruction::GrantRole(role, *key),
+        config_accounts(program_id, admin),
+    )
+}
+
+/// Creates a `RevokeRole` instruction.
+pub fn revoke_role(program_id: &Pubkey, admin: &Pubkey, role: Role) -> Instruction {
+    Instruction::new_with_borsh(
+        *program_id,
+        &FluidityInstruction::RevokeRole(role),
+        config_accounts(program_id, admin),
+    )
+}
+
+/// Creates a `SetPaused` instruction, pausing every token when no data
+/// account is given.
+pub fn set_paused(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    token: Option<(String, &Pubkey)>,
+    paused: bool,
+    unwrap_paused: bool,
+) -> Instruction {
+    let mut accounts = config_accounts(program_id, authority);
+    let seed = token.map(|(seed, fluidity_data_account)| {
+        accounts.push(AccountMeta::new(*fluidity_data_account, false));
+        seed
+    });
+
+    Instruction::new_with_borsh(
+        *program_id,
+        &FluidityInstruction::SetPaused(seed, paused, unwrap_paused),
+        accounts,
+    )
+}
+
+/// Creates a `SetPayoutParams` instruction.
+pub fn set_payout_params(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    fluidity_data_account: &Pubkey,
+    seed: String,
+    prize_pool_cap_bps: u16,
+    sender_share_bps: u16,
+) -> Instruction {
+    let mut accounts = config_accounts(program_id, admin);
+    accounts.push(AccountMeta::new(*fluidity_data_account, false));
+
+    Instruction::new_with_borsh(
+        *program_id,
+        &FluidityInstruction::SetPayoutParams(seed, prize_pool_cap_bps, sender_share_bps),
+        accounts,
+    )
+}
+
+/// Creates a `SetDrawTiers` instruction.
+pub fn set_draw_tiers(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    fluidity_data_account: &Pubkey,
+    seed: String,
+    draw_tiers: [DrawTier; DRAW_TIERS],
+) -> Instruction {
+    let mut accounts = config_accounts(program_id, admin);
+    accounts.push(AccountMeta::new(*fluidity_data_account, false));
+
+    Instruction::new_with_borsh(
+        *program_id,
+        &FluidityInstruction::SetDrawTiers(seed, draw_tiers),
+        accounts,
+    )
+}
+
+/// Creates an `InitTransferLog` instruction, for a transfer log already
+/// created with `processor::TRANSFER_LOG_SPACE` and owned by the program.
+pub fn init_transfer_log(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    pda_account: &Pubkey,
+    fluidity_data_account: &Pubkey,
+    transfer_log: &Pubkey,
+    seed: String,
+) -> Instruction {
+    let mut accounts = config_accounts(program_id, payer);
+    accounts.push(AccountMeta::new(*transfer_log, false));
+    accounts.push(AccountMeta::new_readonly(*pda_account, false));
+    accounts.push(AccountMeta::new(*fluidity_data_account, false));
+
+    Instruction::new_with_borsh(
+        *program_id,
+        &FluidityInstruction::InitTransferLog(seed),
+        accounts,
+    )
+}
+
+/// Creates a `TransferFluid` instruction.
+#[allow(clippy::too_many_arguments)]
+pub fn transfer_fluid(
+    program_id: &Pubkey,
+    fluidity_data_account: &Pubkey,
+    pda_account: &Pubkey,
+    sender: &Pubkey,
+    receiver: &Pubkey,
+    owner: &Pubkey,
+    transfer_log: &Pubkey,
+    amount: u64,
+    seed: String,
+) -> Instruction {
+    Instruction::new_with_borsh(
+        *program_id,
+        &FluidityInstruction::TransferFluid(amount, seed),
+        vec![
+            AccountMeta::new_readonly(*fluidity_data_account, false),
+            AccountMeta::new_readonly(*pda_account, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new(*sender, false),
+            AccountMeta::new(*receiver, false),
+            AccountMeta::new_readonly(*owner, true),
+            AccountMeta::new(*transfer_log, false),
+            AccountMeta::new_readonly(sysvar::clock::id(), false),
+        ],
+    )
+}
+
+/// Creates a `DeregisterToken` instruction.
+pub fn deregister_token(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    token_mint: &Pubkey,
+    fluidity_mint: &Pubkey,
+    treasury: &Pubkey,
+    solend: &SolendAccounts,
+    seed: String,
+) -> Result<Instruction, PubkeyError> {
+    let (pda, _) = find_obligation_authority_address(program_id, &seed);
+    let mut accounts = vec![
+        AccountMeta::new_readonly(find_config_address(program_id).0, false),
+        AccountMeta::new(*admin, true),
+        AccountMeta::new(find_data_address(program_id, &seed)?, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(*token_mint, false),
+        AccountMeta::new_readonly(*fluidity_mint, false),
+        AccountMeta::new(pda, false),
+        AccountMeta::new(*treasury, false),
+    ];
+    accounts.extend(solend_reserve_accounts(solend));
+
+    Ok(Instruction::new_with_borsh(
+        *program_id,
+        &FluidityInstruction::DeregisterToken(seed),
+        accounts,
+    ))
+}
+
+/// Creates a `MigrateData` instruction.
+#[allow(clippy::too_many_arguments)]
+pub fn migrate_data(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    token_mint: &Pubkey,
+    fluid_mint: &Pubkey,
+    solend_program: &Pubkey,
+    reserve: &Pubkey,
+    obligation: &Pubkey,
+    collateral: &Pubkey,
+    seed: String,
+    layout: LegacyLayout,
+) -> Result<Instruction, PubkeyError> {
+    let (pda, _) = find_obligation_authority_address(program_id, &seed);
+    let accounts = vec![
+        AccountMeta::new_readonly(find_config_address(program_id).0, false),
+        AccountMeta::new(*admin, true),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new(find_data_address(program_id, &seed)?, false),
+        AccountMeta::new(find_staging_address(program_id, &seed)?, false),
+        AccountMeta::new_readonly(*token_mint, false),
+        AccountMeta::new_readonly(*fluid_mint, false),
+        AccountMeta::new_readonly(pda, false),
+        AccountMeta::new_readonly(*solend_program, false),
+        AccountMeta::new_readonly(*reserve, false),
+        AccountMeta::new_readonly(*obligation, false),
+        AccountMeta::new_readonly(*collateral, false),
+    ];
+
+    Ok(Instruction::new_with_borsh(
+        *program_id,
+        &FluidityInstruction::MigrateData(seed, layout),
+        accounts,
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_wrap_accounts() {
+        let program_id = Pubkey::new_unique();
+        let sender = Pubkey::new_unique();
+        let solend = SolendAccounts {
+            solend_program: Pubkey::new_unique(),
+            collateral: Pubkey::new_unique(),
+            reserve: Pubkey::new_unique(),
+            reserve_liquidity_supply: Pubkey::new_unique(),
+            reserve_collateral_mint: Pubkey::new_unique(),
+            lending_market: Pubkey::new_unique(),
+            lending_market_authority: Pubkey::new_unique(),
+            reserve_collateral_supply: Pubkey::new_unique(),
+            obligation: Pubkey::new_unique(),
+            pyth_price: Pubkey::new_unique(),
+            switchboard_feed: Pubkey::new_unique(),
+        };
+        let instruction = wrap(
+            &program_id,
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &sender,
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &solend,
+            100,
+            "USDC".to_string(),
+        );
+
+        assert_eq!(instruction.program_id, program_id);
+        assert_eq!(instruction.accounts.len(), 21);
+        assert_eq!(instruction.accounts[8].pubkey, find_config_address(&program_id).0);
+        assert_eq!(instruction.accounts[10].pubkey, solend.collateral);
+
+        // the sender is the only signer
+        let signers: Vec<_> = instruction.accounts.iter().filter(|a| a.is_signer).collect();
+        assert_eq!(signers.len(), 1);
+        assert_eq!(signers[0].pubkey, sender);
+
+        assert_eq!(
+            FluidityInstruction::try_from_slice(&instruction.data).unwrap(),
+            FluidityInstruction::Wrap(100, "USDC".to_string())
+        );
+
+        // the min-out variants take the same accounts
+        let unwrap_min_out = unwrap_min_out(
+            &program_id,
+            &instruction.accounts[0].pubkey,
+            &instruction.accounts[2].pubkey,
+            &instruction.accounts[3].pubkey,
+            &instruction.accounts[4].pubkey,
+            &sender,
+            &instruction.accounts[6].pubkey,
+            &instruction.accounts[7].pubkey,
+            &solend,
+            100,
+            99,
+            "USDC".to_string(),
+        );
+        assert_eq!(unwrap_min_out.accounts, instruction.accounts);
+        assert_eq!(
+            FluidityInstruction::try_from_slice(&unwrap_min_out.data).unwrap(),
+            FluidityInstruction::UnwrapMinOut(100, 99, "USDC".to_string())
+        );
+    }
+
+    #[test]
+    fn test_log_tvl_accounts() {
+        let program_id = Pubkey::new_unique();
+        let base = Pubkey::new_unique();
+        let deposit_reserve = || DepositReserve {
+            reserve: Pubkey::new_unique(),
+            pyth_price: Pubkey::new_unique(),
+            switchboard_feed: Pubkey::new_unique(),
+        };
+        let token = |seed: &str, reserves| TvlAccounts {
+            seed: seed.to_string(),
+            fluidity_data_account: Pubkey::new_unique(),
+            pda_account: Pubkey::new_unique(),
+            solend_program: Pubkey::new_unique(),
+            obligation: Pubkey::new_unique(),
+            lending_market: Pubkey::new_unique(),
+            reserves,
+        };
+        let usdc = token("USDC", vec![deposit_reserve(), deposit_reserve()]);
+        let usdt = token("USDT", vec![deposit_reserve()]);
+
+        let instruction = log_tvl(&program_id, &Pubkey::new_unique(), &base, &[usdc.clone(), usdt.clone()]).unwrap();
+
+        // two reserves take 3 + 4 + 3 * 2 accounts, and one 3 + 4 + 3
+        assert_eq!(instruction.accounts.len(), 2 + 13 + 10);
+        assert_eq!(
+            instruction.accounts[2].pubkey,
+            Pubkey::create_with_seed(&base, "FLU:USDC_TVL", &program_id).unwrap()
+        );
+        let usdc_accounts: Vec<_> = instruction.accounts[5..15].iter().map(|meta| meta.pubkey).collect();
+        assert_eq!(usdc_accounts, vec![
+            usdc.obligation,
+            usdc.reserves[0].reserve,
+            usdc.reserves[1].reserve,
+            usdc.solend_program,
+            usdc.reserves[0].pyth_price,
+            usdc.reserves[0].switchboard_feed,
+            usdc.reserves[1].pyth_price,
+            usdc.reserves[1].switchboard_feed,
+            sysvar::clock::id(),
+            usdc.lending_market,
+        ]);
+        assert_eq!(instruction.accounts[15].pubkey,
+            Pubkey::create_with_seed(&base, "FLU:USDT_TVL", &program_id).unwrap());
+        assert_eq!(instruction.accounts[24].pubkey, usdt.lending_market);
+
+        assert_eq!(
+            FluidityInstruction::try_from_slice(&instruction.data).unwrap(),
+            FluidityInstruction::LogTVL(vec!["USDC".to_string(), "USDT".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_batch_payout_accounts() {
+        let program_id = Pubkey::new_unique();
+        let obligation = Pubkey::new_unique();
+        let reserve = Pubkey::new_unique();
+        let payout_accounts = [Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique()];
+        let winners = vec![
+            Winner { amount: 100, sender: 0, receiver: 1, transfer_hash: [1; 32], transfer_slot: 10 },
+            Winner { amount: 50, sender: 2, receiver: 0, transfer_hash: [2; 32], transfer_slot: 11 },
+        ];
+        let instruction = batch_payout(
+            &program_id,
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &obligation,
+            &reserve,
+            &payout_accounts,
+            winners.clone(),
+            "USDC".to_string(),
+        );
+
+        // the payout accounts and a receipt per winner come before the
+        // lending adapter's
+        assert_eq!(instruction.accounts.len(), 15);
+        for (meta, account) in instruction.accounts[8..11].iter().zip(payout_accounts.iter()) {
+            assert_eq!(&meta.pubkey, account);
+            assert!(meta.is_writable);
+        }
+        assert_eq!(instruction.accounts[11].pubkey, find_receipt_address(&program_id, &[1; 32]).0);
+        assert_eq!(instruction.accounts[12].pubkey, find_receipt_address(&program_id, &[2; 32]).0);
+        assert_eq!(instruction.accounts[13].pubkey, obligation);
+        assert_eq!(instruction.accounts[14].pubkey, reserve);
+
+        assert_eq!(
+            FluidityInstruction::try_from_slice(&instruction.data).unwrap(),
+            FluidityInstruction::BatchPayout(winners, "USDC".to_string())
+        );
+    }
+
+    #[test]
+    fn test_register_token_accounts() {
+        let program_id = Pubkey::new_unique();
+        let solend_program = Pubkey::new_unique();
+        let lending_market = Pubkey::new_unique();
+        let (pda, _) = find_obligation_authority_address(&program_id, "USDC");
+
+        let instruction = register_token(
+            &program_id, &Pubkey::new_unique(), &Pubkey::new_unique(), &solend_program,
+            &Pubkey::new_unique(), &lending_market, &Pubkey::new_unique(), "USDC".to_string(),
+        ).unwrap();
+
+        // the accounts created are derived from the pda, and writable
+        let created = [
+            (3, find_data_address(&program_id, "USDC").unwrap()),
+            (5, find_fluid_mint_address(&program_id, "USDC").unwrap()),
+            (
+                9,
+                Pubkey::create_with_seed(&pda, &lending_market.to_string()[0..32], &solend_program).unwrap(),
+            ),
+        ];
+        for (index, address) in created {
+            assert_eq!(instruction.accounts[index].pubkey, address);
+            assert!(instruction.accounts[index].is_writable);
+        }
+        assert_eq!(instruction.accounts[6].pubkey, pda);
+        assert_eq!(instruction.accounts.len(), 15);
+    }
+
+    #[test]
+    fn test_role_gated_accounts() {
+        let program_id = Pubkey::new_unique();
+        let payer = Pubkey::new_unique();
+
+        // role gated instructions start with the config account and the signer
+        let instructions = vec![
+            payout(
+                &program_id, &payer, &Pubkey::new_unique(), &Pubkey::new_unique(),
+                &Pubkey::new_unique(), &Pubkey::new_unique(), &Pubkey::new_unique(),
+                &Pubkey::new_unique(), &Pubkey::new_unique(), 1, "USDC".to_string(), [0; 32], 1,
+            ),
+            init_data(
+                &program_id, &payer, &Pubkey::new_unique(), &Pubkey::new_unique(),
+                &Pubkey::new_unique(), &Pubkey::new_unique(), &Pubkey::new_unique(),
+                &Pubkey::new_unique(), &Pubkey::new_unique(), &Pubkey::new_unique(),
+                "USDC".to_string(), 1, 1,
+            ),
+            batch_payout(
+                &program_id, &payer, &Pubkey::new_unique(), &Pubkey::new_unique(),
+                &Pubkey::new_unique(), &Pubkey::new_unique(), &Pubkey::new_unique(),
+                &[Pubkey::new_unique()],
+                vec![Winner { amount: 1, sender: 0, receiver: 0, transfer_hash: [0; 32], transfer_slot: 1 }],
+                "USDC".to_string(),
+            ),
+            grant_role(&program_id, &payer, Role::Guardian, &Pubkey::new_unique()),
+            set_paused(&program_id, &payer, None, true, false),
+            close_receipt(&program_id, &payer, &payer, [0; 32]),
+            draw_payout(
+                &program_id, &payer, &Pubkey::new_unique(), &Pubkey::new_unique(),
+                &Pubkey::new_unique(), &Pubkey::new_unique(), &Pubkey::new_unique(),
+                &Pubkey::new_unique(), &Pubkey::new_unique(), &Pubkey::new_unique(),
+                "USDC".to_string(), 1,
+            ),
+            set_draw_tiers(&program_id, &payer, &Pubkey::new_unique(), "USDC".to_string(), [DrawTier::default(); DRAW_TIERS]),
+            init_transfer_log(
+                &program_id, &payer, &Pubkey::new_unique(), &Pubkey::new_unique(),
+                &Pubkey::new_unique(), "USDC".to_string(),
+            ),
+            payout_transfer(
+                &program_id, &payer, &Pubkey::new_unique(), &Pubkey::new_unique(),
+                &Pubkey::new_unique(), &Pubkey::new_unique(), &Pubkey::new_unique(),
+                &Pubkey::new_unique(), &Pubkey::new_unique(), &Pubkey::new_unique(),
+                1, "USDC".to_string(), 0,
+            ),
+            register_token(
+                &program_id, &payer, &Pubkey::new_unique(), &Pubkey::new_unique(),
+                &Pubkey::new_unique(), &Pubkey::new_unique(), &Pubkey::new_unique(),
+                "USDC".to_string(),
+            ).unwrap(),
+            migrate_data(
+                &program_id, &payer, &Pubkey::new_unique(), &Pubkey::new_unique(),
+                &Pubkey::new_unique(), &Pubkey::new_unique(), &Pubkey::new_unique(),
+                &Pubkey::new_unique(), "USDC".to_string(), LegacyLayout::Mints,
+            ).unwrap(),
+            close_tvl_data(&program_id, &payer, &Pubkey::new_unique(), &payer).unwrap(),
+        ];
+        for instruction in instructions {
+            assert_eq!(instruction.accounts[0].pubkey, find_config_address(&program_id).0);
+            assert_eq!(instruction.accounts[1].pubkey, payer);
+            assert!(instruction.accounts[1].is_signer);
+        }
+    }
+}
@@ -13,9 +13,13 @@ use {
 
 pub mod instruction;
 pub mod processor;
-mod state;
-mod math;
-mod error;
+mod adapter;
+pub mod state;
+pub mod math;
+pub mod error;
+pub mod event;
+pub mod tvl;
+mod migrate;
 
 // declare the pubkey of the program
 #[cfg(all(target_arch = "bpf", not(feature = "exclude_entrypoint")))]
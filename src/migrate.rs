@@ -0,0 +1,68 @@
+// Reading the unversioned layouts data accounts were written with before they
+// were packed, for MigrateData
+
+use crate::instruction::LegacyLayout;
+
+use {
+    borsh::BorshDeserialize,
+    solana_program::{msg, program_error::ProgramError, pubkey::{Pubkey, PUBKEY_BYTES}},
+};
+
+// space taken by the mints and the pda in the Mints layout
+const LEGACY_MINTS_LEN: usize = PUBKEY_BYTES * 3;
+
+// the accounts recorded by a legacy layout
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) struct LegacyData {
+    pub token_mint: Pubkey,
+    pub fluid_mint: Pubkey,
+    pub pda: Pubkey,
+}
+
+// read a data account written with a legacy layout. init_data took the
+// account's space from its caller, so the account can be longer than the
+// layout but not shorter
+pub(crate) fn read_legacy_data(layout: LegacyLayout, data: &[u8]) -> Result<LegacyData, ProgramError> {
+    match layout {
+        LegacyLayout::Mints => {
+            if data.len() < LEGACY_MINTS_LEN {
+                msg!("data account is too short for its layout");
+                return Err(ProgramError::InvalidAccountData);
+            }
+
+            let input = &mut &data[..LEGACY_MINTS_LEN];
+            Ok(LegacyData {
+                token_mint: Pubkey::deserialize(input)?,
+                fluid_mint: Pubkey::deserialize(input)?,
+                pda: Pubkey::deserialize(input)?,
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use borsh::BorshSerialize;
+
+    #[test]
+    fn test_read_legacy_data() {
+        let token_mint = Pubkey::new_unique();
+        let fluid_mint = Pubkey::new_unique();
+        let pda = Pubkey::new_unique();
+        let mints = [token_mint, fluid_mint, pda].try_to_vec().unwrap();
+        let expected = LegacyData { token_mint, fluid_mint, pda };
+
+        // written into an account of exactly its size, or with room to spare
+        assert_eq!(read_legacy_data(LegacyLayout::Mints, &mints), Ok(expected.clone()));
+        let mut spare = mints.clone();
+        spare.resize(512, 0);
+        assert_eq!(read_legacy_data(LegacyLayout::Mints, &spare), Ok(expected));
+
+        // accounts too short for the layout are refused
+        assert_eq!(
+            read_legacy_data(LegacyLayout::Mints, &mints[..LEGACY_MINTS_LEN - 1]),
+            Err(ProgramError::InvalidAccountData)
+        );
+    }
+}
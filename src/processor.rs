@@ -1,46 +1,517 @@
 // Fluidity smart contract state processor
 
 use crate::{
-    state::{Obligation, Reserve},
+    adapter::{adapter, solend, AdapterKind, Deposited, UserAccounts},
+    error::{FluidityError, LendingError},
+    event::{exchange_rate, FluidityEvent},
+    migrate::read_legacy_data,
+    tvl::{append_tvl_record, tvl_data_seed, AggregateTvlRecord, TvlRecord, TVL_DATA_SEED, TVL_LOG_SPACE},
+    state::{pack_bool, unpack_bool, Obligation, Reserve, UNINITIALIZED_VERSION},
     math::*,
     instruction::*,
 };
 
 use {
-    std::{str::FromStr, convert::TryFrom},
+    arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs},
+    std::{str::FromStr, convert::TryInto},
     borsh::{BorshDeserialize, BorshSerialize},
     solana_program::{
         account_info::{next_account_info, AccountInfo},
         entrypoint::ProgramResult,
+        hash::hashv,
         instruction::{AccountMeta, Instruction},
         log::sol_log_compute_units,
         msg,
         program::{invoke, invoke_signed},
         program_error::ProgramError, 
-        pubkey::Pubkey,
+        pubkey::{Pubkey, PUBKEY_BYTES},
+        clock::{Clock, Slot},
+        rent::Rent,
         system_instruction, 
         system_program,
-        program_pack::{IsInitialized, Pack},
+        sysvar::{self, Sysvar},
+        program_option::COption,
+        program_pack::{IsInitialized, Pack, Sealed},
     },
     spl_token,
 };
 
-// the public key of the authority for payouts and initialisation
+// basis points making up the whole of an amount
+const MAX_BPS: u16 = 10_000;
+
+// payouts take at most 80% of the prize pool by default
+const DEFAULT_PRIZE_POOL_CAP_BPS: u16 = 8_000;
+
+// and split it 8:2 between the sender and receiver
+const DEFAULT_SENDER_SHARE_BPS: u16 = 8_000;
+
+// seed of a payout receipt, followed by the winning transfer's hash
+pub const RECEIPT_SEED: &str = "FLU:RECEIPT";
+
+// space allocated for a payout receipt
+pub const RECEIPT_SPACE: u64 = 64;
+
+// winning transfers can be paid out for this many slots (about five days),
+// after which their receipts can be closed
+pub const RECEIPT_LIFETIME_SLOTS: Slot = 1_000_000;
+
+// receipts of transfers whose slot was given by the operator are kept for a
+// second lifetime - the receipt was created within a lifetime of the given
+// slot, so paying the transfer again once it's closed means giving a slot
+// after the one the receipt was created in
+pub const UNLOGGED_RECEIPT_LIFETIME_SLOTS: Slot = 2 * RECEIPT_LIFETIME_SLOTS;
+
+// logged transfers are expected to be paid out within this many slots
+pub const TRANSFER_PAYOUT_LATENCY_SLOTS: Slot = 25;
+
+// most transfers logged in a single slot across all senders, so that
+// flooding the log can't overwrite a transfer before the payout latency has
+// passed. Anyone can still fill a slot, but it takes this many non-empty
+// transfers in that slot, each paying its fee, and only holds up logging
+// until the next slot
+pub const TRANSFER_LOG_SLOT_LIMIT: u64 = 200;
+
+// number of transfers kept in a token's transfer log, after which the oldest
+// are overwritten
+pub const TRANSFER_LOG_CAPACITY: u64 = TRANSFER_LOG_SLOT_LIMIT * TRANSFER_PAYOUT_LATENCY_SLOTS;
+
+// space taken by the transfer log's header and by each of its entries
+const TRANSFER_LOG_HEADER_SPACE: usize = 40;
+const TRANSFER_LOG_ENTRY_SPACE: usize = 80;
+
+// space of a token's transfer log, which is too large to be created over
+// cpi, so the registry admin creates it owned by this program before
+// InitTransferLog
+pub const TRANSFER_LOG_SPACE: u64 =
+    TRANSFER_LOG_HEADER_SPACE as u64 + TRANSFER_LOG_CAPACITY * TRANSFER_LOG_ENTRY_SPACE as u64;
+
+// the public key allowed to create the config account, after which the
+// admin stored in the config account is the only authority
 const AUTHORITY: &str = "sohTpNitFg3WZeEcbrMunnwoZJWP4t8yisPB5o3DGD5";
 
-// the public key of the solend program
-const SOLEND: &str = "ALend7Ketfx5bxh6ghsCDXAoDrhvEmsXT3cynB6aPLgx";
+// seed of the program derived config account
+pub const CONFIG_SEED: &str = "FLU:CONFIG";
+
+// space allocated for the config account, leaving room for new fields
+pub const CONFIG_SPACE: u64 = 256;
+
+// fluid tokens that can be left held when a token is deregistered, as
+// holders too small to unwrap can't be made to
+pub const DEREGISTER_DUST_AMOUNT: u64 = 1_000;
+
+
+// seed of a token's obligation authority, which owns its obligation and mints
+// its fluid token
+pub fn obligation_authority_seed(seed: &str) -> String {
+    format!("FLU:{}_OBLIGATION", seed)
+}
+
+// seed of a token's data account, derived from its obligation authority
+pub fn data_seed(seed: &str) -> String {
+    format!("FLU:{}_DATA", seed)
+}
+
+// seed of a token's fluid mint when created by RegisterToken, derived from its
+// obligation authority
+pub fn fluid_mint_seed(seed: &str) -> String {
+    format!("FLU:{}_MINT", seed)
+}
+
+// seed of the account a token's data is staged in while MigrateData
+// re-creates its data account
+pub fn staging_seed(seed: &str) -> String {
+    format!("FLU:{}_STAGING", seed)
+}
+
+// hash identifying a logged transfer in its payout receipt
+pub fn logged_transfer_hash(fluid_mint: &Pubkey, index: u64) -> [u8; 32] {
+    hashv(&[fluid_mint.as_ref(), &index.to_le_bytes()]).to_bytes()
+}
+
+// version of the data account layout written by init_data
+pub const FLUIDITY_DATA_VERSION: u8 = 1;
 
 // struct defining fludity data account
-#[derive(BorshDeserialize, BorshSerialize, Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct FluidityData {
+    // layout version of the account, zero until it's initialised
+    version: u8,
     token_mint: Pubkey,
     fluid_mint: Pubkey,
-    pda: Pubkey,
+    pub(crate) pda: Pubkey,
+    // bump seed of the obligation authority
+    bump: u8,
+    // the lending backend the base tokens are deposited into
+    adapter: AdapterKind,
+    // the solend reserve of the base token
+    pub(crate) reserve: Pubkey,
+    // the reserve's lending market
+    pub(crate) lending_market: Pubkey,
+    // the obligation owned by the pda
+    pub(crate) obligation: Pubkey,
+    // the reserve's collateral mint
+    pub(crate) collateral_mint: Pubkey,
+    // the reserve's liquidity supply account
+    pub(crate) liquidity_supply: Pubkey,
+    // the reserve's collateral supply account
+    pub(crate) collateral_supply: Pubkey,
+    // the pda's collateral token account
+    pub(crate) collateral: Pubkey,
+    // wrapping and payouts are paused for the token
+    paused: bool,
+    // unwrapping is paused for the token
+    unwrap_paused: bool,
+    // share of the prize pool a payout can take, in basis points
+    prize_pool_cap_bps: u16,
+    // share of a payout going to the sender, in basis points
+    sender_share_bps: u16,
+    // the odds table of drawn payouts, all tiers disabled by default
+    draw_tiers: [DrawTier; DRAW_TIERS],
+    // the transfer log created by InitTransferLog, default until then
+    transfer_log: Pubkey,
+}
+
+// the accounts a token's data account records
+pub struct InitFluidityDataParams {
+    pub token_mint: Pubkey,
+    pub fluid_mint: Pubkey,
+    // the obligation authority and its bump seed
+    pub pda: Pubkey,
+    pub bump: u8,
+    pub reserve: Pubkey,
+    pub lending_market: Pubkey,
+    pub obligation: Pubkey,
+    pub collateral_mint: Pubkey,
+    pub liquidity_supply: Pubkey,
+    pub collateral_supply: Pubkey,
+    pub collateral: Pubkey,
+}
+
+impl FluidityData {
+    // record a token deposited with the solend adapter, with the default
+    // settings
+    pub fn new(params: InitFluidityDataParams) -> Self {
+        Self {
+            version: FLUIDITY_DATA_VERSION,
+            token_mint: params.token_mint,
+            fluid_mint: params.fluid_mint,
+            pda: params.pda,
+            bump: params.bump,
+            adapter: AdapterKind::Solend,
+            reserve: params.reserve,
+            lending_market: params.lending_market,
+            obligation: params.obligation,
+            collateral_mint: params.collateral_mint,
+            liquidity_supply: params.liquidity_supply,
+            collateral_supply: params.collateral_supply,
+            collateral: params.collateral,
+            paused: false,
+            unwrap_paused: false,
+            prize_pool_cap_bps: DEFAULT_PRIZE_POOL_CAP_BPS,
+            sender_share_bps: DEFAULT_SENDER_SHARE_BPS,
+            draw_tiers: [DrawTier::default(); DRAW_TIERS],
+            transfer_log: Pubkey::default(),
+        }
+    }
+}
+
+impl Sealed for FluidityData {}
+impl IsInitialized for FluidityData {
+    fn is_initialized(&self) -> bool {
+        self.version != UNINITIALIZED_VERSION
+    }
+}
+
+// space taken by each tier of the odds table
+const DRAW_TIER_LEN: usize = 12; // 4 + 8
+
+const FLUIDITY_DATA_LEN: usize = 512; // 1 + 32 + 32 + 32 + 1 + 1 + 32 * 7 + 1 + 1 + 2 + 2 + 12 * 4 + 32 + 103
+impl Pack for FluidityData {
+    const LEN: usize = FLUIDITY_DATA_LEN;
+
+    fn pack_into_slice(&self, output: &mut [u8]) {
+        let output = array_mut_ref![output, 0, FLUIDITY_DATA_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (
+            version,
+            token_mint,
+            fluid_mint,
+            pda,
+            bump,
+            adapter,
+            reserve,
+            lending_market,
+            obligation,
+            collateral_mint,
+            liquidity_supply,
+            collateral_supply,
+            collateral,
+            paused,
+            unwrap_paused,
+            prize_pool_cap_bps,
+            sender_share_bps,
+            draw_tiers,
+            transfer_log,
+            _padding,
+        ) = mut_array_refs![
+            output,
+            1,
+            PUBKEY_BYTES,
+            PUBKEY_BYTES,
+            PUBKEY_BYTES,
+            1,
+            1,
+            PUBKEY_BYTES,
+            PUBKEY_BYTES,
+            PUBKEY_BYTES,
+            PUBKEY_BYTES,
+            PUBKEY_BYTES,
+            PUBKEY_BYTES,
+            PUBKEY_BYTES,
+            1,
+            1,
+            2,
+            2,
+            DRAW_TIER_LEN * DRAW_TIERS,
+            PUBKEY_BYTES,
+            103
+        ];
+
+        *version = self.version.to_le_bytes();
+        token_mint.copy_from_slice(self.token_mint.as_ref());
+        fluid_mint.copy_from_slice(self.fluid_mint.as_ref());
+        pda.copy_from_slice(self.pda.as_ref());
+        *bump = self.bump.to_le_bytes();
+        *adapter = self.adapter.to_byte().to_le_bytes();
+        reserve.copy_from_slice(self.reserve.as_ref());
+        lending_market.copy_from_slice(self.lending_market.as_ref());
+        obligation.copy_from_slice(self.obligation.as_ref());
+        collateral_mint.copy_from_slice(self.collateral_mint.as_ref());
+        liquidity_supply.copy_from_slice(self.liquidity_supply.as_ref());
+        collateral_supply.copy_from_slice(self.collateral_supply.as_ref());
+        collateral.copy_from_slice(self.collateral.as_ref());
+        pack_bool(self.paused, paused);
+        pack_bool(self.unwrap_paused, unwrap_paused);
+        *prize_pool_cap_bps = self.prize_pool_cap_bps.to_le_bytes();
+        *sender_share_bps = self.sender_share_bps.to_le_bytes();
+
+        for (tier, output) in self.draw_tiers.iter().zip(draw_tiers.chunks_exact_mut(DRAW_TIER_LEN)) {
+            let (odds, prize) = mut_array_refs![array_mut_ref![output, 0, DRAW_TIER_LEN], 4, 8];
+            *odds = tier.odds.to_le_bytes();
+            *prize = tier.prize.to_le_bytes();
+        }
+        transfer_log.copy_from_slice(self.transfer_log.as_ref());
+    }
+
+    fn unpack_from_slice(input: &[u8]) -> Result<Self, ProgramError> {
+        let input = array_ref![input, 0, FLUIDITY_DATA_LEN];
+        #[allow(clippy::ptr_offset_with_cast)]
+        let (
+            version,
+            token_mint,
+            fluid_mint,
+            pda,
+            bump,
+            adapter,
+            reserve,
+            lending_market,
+            obligation,
+            collateral_mint,
+            liquidity_supply,
+            collateral_supply,
+            collateral,
+            paused,
+            unwrap_paused,
+            prize_pool_cap_bps,
+            sender_share_bps,
+            draw_tiers,
+            transfer_log,
+            _padding,
+        ) = array_refs![
+            input,
+            1,
+            PUBKEY_BYTES,
+            PUBKEY_BYTES,
+            PUBKEY_BYTES,
+            1,
+            1,
+            PUBKEY_BYTES,
+            PUBKEY_BYTES,
+            PUBKEY_BYTES,
+            PUBKEY_BYTES,
+            PUBKEY_BYTES,
+            PUBKEY_BYTES,
+            PUBKEY_BYTES,
+            1,
+            1,
+            2,
+            2,
+            DRAW_TIER_LEN * DRAW_TIERS,
+            PUBKEY_BYTES,
+            103
+        ];
+
+        let version = u8::from_le_bytes(*version);
+        if version > FLUIDITY_DATA_VERSION {
+            msg!("data account version {} is newer than the program's", version);
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        let mut tiers = [DrawTier::default(); DRAW_TIERS];
+        for (tier, input) in tiers.iter_mut().zip(draw_tiers.chunks_exact(DRAW_TIER_LEN)) {
+            let (odds, prize) = array_refs![array_ref![input, 0, DRAW_TIER_LEN], 4, 8];
+            tier.odds = u32::from_le_bytes(*odds);
+            tier.prize = u64::from_le_bytes(*prize);
+        }
+
+        Ok(Self {
+            version,
+            token_mint: Pubkey::new_from_array(*token_mint),
+            fluid_mint: Pubkey::new_from_array(*fluid_mint),
+            pda: Pubkey::new_from_array(*pda),
+            bump: u8::from_le_bytes(*bump),
+            adapter: AdapterKind::from_byte(u8::from_le_bytes(*adapter))?,
+            reserve: Pubkey::new_from_array(*reserve),
+            lending_market: Pubkey::new_from_array(*lending_market),
+            obligation: Pubkey::new_from_array(*obligation),
+            collateral_mint: Pubkey::new_from_array(*collateral_mint),
+            liquidity_supply: Pubkey::new_from_array(*liquidity_supply),
+            collateral_supply: Pubkey::new_from_array(*collateral_supply),
+            collateral: Pubkey::new_from_array(*collateral),
+            paused: unpack_bool(paused)?,
+            unwrap_paused: unpack_bool(unwrap_paused)?,
+            prize_pool_cap_bps: u16::from_le_bytes(*prize_pool_cap_bps),
+            sender_share_bps: u16::from_le_bytes(*sender_share_bps),
+            draw_tiers: tiers,
+            transfer_log: Pubkey::new_from_array(*transfer_log),
+        })
+    }
+}
+
+// struct defining the program wide config account
+#[derive(BorshDeserialize, BorshSerialize, Debug, PartialEq, Clone)]
+pub struct FluidityConfig {
+    // the key allowed to run authority gated instructions
+    admin: Pubkey,
+    // the key nominated to become admin, default when there is none
+    pending_admin: Pubkey,
+    // bump seed of the config account address
+    bump: u8,
+    // the key allowed to run payouts
+    payout_operator: Pubkey,
+    // the key allowed to register tokens
+    registry_admin: Pubkey,
+    // the key allowed to pause the program
+    guardian: Pubkey,
+    // wrapping and payouts are paused for every token
+    paused: bool,
+    // unwrapping is paused for every token
+    unwrap_paused: bool,
+}
+
+// struct defining the receipt of a paid out winning transfer, stored at an
+// address derived from the transfer's hash so it can only be paid once
+#[derive(BorshDeserialize, BorshSerialize, Debug, PartialEq, Clone)]
+pub struct PayoutReceipt {
+    // the fluid token paid out
+    fluid_mint: Pubkey,
+    // the slot of the winning transfer
+    transfer_slot: Slot,
+    // amount minted to the sender
+    sender_prize: u64,
+    // amount minted to the receiver
+    receiver_prize: u64,
+    // the transfer and its slot were read from the transfer log, so the
+    // receipt can be closed once the slot is too old to be paid - the slots
+    // of other transfers are given by the operator, so their receipts are
+    // kept for longer
+    slot_logged: bool,
+}
+
+// header of a token's transfer log, followed by a ring buffer of entries
+#[derive(BorshDeserialize, BorshSerialize, Debug, PartialEq, Clone)]
+pub struct TransferLog {
+    // the fluid token whose transfers are logged
+    fluid_mint: Pubkey,
+    // index of the next transfer logged, its entry is at this modulo the
+    // capacity
+    next_index: u64,
+}
+
+// a fluid transfer logged by TransferFluid
+#[derive(BorshDeserialize, BorshSerialize, Debug, PartialEq, Clone)]
+pub struct TransferLogEntry {
+    // the sender's fluid token account
+    sender: Pubkey,
+    // the receiver's fluid token account
+    receiver: Pubkey,
+    // amount of fluid tokens transferred
+    amount: u64,
+    // slot of the transfer
+    slot: Slot,
+}
+
+// where the amount and the winning transfer of a payout come from
+enum PayoutSource {
+    // an amount for the transfer with a hash and slot
+    Amount(u64, [u8; 32], Slot),
+    // an amount drawn for the transfer at an index of the token's transfer
+    // log
+    Draw(u64),
+    // an amount for the transfer at an index of the token's transfer log
+    Logged(u64, u64),
+}
+
+impl FluidityConfig {
+    // the config created by InitConfig, where the admin is the only role held
+    pub fn new(admin: Pubkey, bump: u8) -> Self {
+        Self {
+            admin,
+            pending_admin: Pubkey::default(),
+            bump,
+            payout_operator: Pubkey::default(),
+            registry_admin: Pubkey::default(),
+            guardian: Pubkey::default(),
+            paused: false,
+            unwrap_paused: false,
+        }
+    }
+
+    // get the key holding a role, default when nobody holds it
+    fn role(&self, role: Role) -> Pubkey {
+        match role {
+            Role::Admin => self.admin,
+            Role::PayoutOperator => self.payout_operator,
+            Role::RegistryAdmin => self.registry_admin,
+            Role::Guardian => self.guardian,
+        }
+    }
+
+    // get a grantable role for updating, the admin can only be handed over
+    pub fn role_mut(&mut self, role: Role) -> Result<&mut Pubkey, ProgramError> {
+        match role {
+            Role::Admin => {
+                msg!("the admin can only be changed with ProposeAdmin");
+                Err(FluidityError::AdminNotGrantable.into())
+            }
+            Role::PayoutOperator => Ok(&mut self.payout_operator),
+            Role::RegistryAdmin => Ok(&mut self.registry_admin),
+            Role::Guardian => Ok(&mut self.guardian),
+        }
+    }
 }
 
-// wrap amount of token into corresponding fluidity token
-fn wrap(accounts: &[AccountInfo], program_id: &Pubkey, amount: u64, seed: String, bump: u8) -> ProgramResult {
+// wrap amount of token into corresponding fluidity token, failing if fewer
+// than min_out fluid tokens are minted - the amount is minted while the prize
+// pool can absorb what the deposit's collateral is worth less than it, and
+// what the collateral is worth otherwise
+fn wrap(
+    accounts: &[AccountInfo],
+    program_id: &Pubkey,
+    amount: u64,
+    min_out: u64,
+    seed: String,
+) -> ProgramResult {
     let accounts_iter = &mut accounts.iter();
 
     let fluidity_data_account = next_account_info(accounts_iter)?;
@@ -51,149 +522,45 @@ fn wrap(accounts: &[AccountInfo], program_id: &Pubkey, amount: u64, seed: String
     let sender = next_account_info(accounts_iter)?;
     let token_account = next_account_info(accounts_iter)?;
     let fluidity_account = next_account_info(accounts_iter)?;
+    let config_account = next_account_info(accounts_iter)?;
 
-    // Accounts to pass through to solend - not being verified by us
-    let solend_program = next_account_info(accounts_iter)?;
-    let collateral_info = next_account_info(accounts_iter)?;
-    let reserve_info = next_account_info(accounts_iter)?;
-    let reserve_liquidity_supply_info = next_account_info(accounts_iter)?;
-    let reserve_collateral_mint_info = next_account_info(accounts_iter)?;
-    let lending_market_info = next_account_info(accounts_iter)?;
-    let lending_market_authority_info = next_account_info(accounts_iter)?;
-    let destination_collateral_info = next_account_info(accounts_iter)?;
-    let obligation_info = next_account_info(accounts_iter)?;
-    let pyth_price_info = next_account_info(accounts_iter)?;
-    let switchboard_feed_info = next_account_info(accounts_iter)?;
-    let clock_info = next_account_info(accounts_iter)?;
-
-    if amount < 2 {
-        panic!("Amount of liquidity less than two, Solend rounding error!");
-    }
+    // the rest are passed through to the token's lending adapter, which checks
+    // them against the data account
+    let adapter_accounts = accounts_iter.as_slice();
 
-    // check solend contract
-    if solend_program.key !=
-        &Pubkey::from_str(SOLEND).unwrap() {
-        panic!("bad Solend contract!");
+    if token_program.key != &spl_token::id() {
+        msg!("bad token program");
+        return Err(FluidityError::InvalidTokenProgram.into());
     }
 
-    // create seed strings following format
-    let pda_seed = format!("FLU:{}_OBLIGATION", seed);
-    let data_seed = format!("FLU:{}_DATA", seed);
-
-    // check that data account is derived from pda
-    if fluidity_data_account.key !=
-        &Pubkey::create_with_seed(
-            pda_account.key,
-            &data_seed,
-            program_id,
-        ).unwrap() {
-            panic!("bad data account");
-    }
+    // check data account and pda, getting the pda's bump seed
+    let fluidity_data = load_fluidity_data(program_id, &seed, fluidity_data_account, pda_account)?;
+    let bump = fluidity_data.bump;
+    let pda_seed = obligation_authority_seed(&seed);
 
     // check mints
-    check_mints_and_pda(&fluidity_data_account, *token_mint.key, *fluidity_mint.key, *pda_account.key);
-
-    // check collateral and obligation ownership
-    let obligation = Obligation::unpack(&obligation_info.data.borrow())?;
-    if &obligation.owner != pda_account.key {
-        panic!("bad obligation ownership!");
-    }
-    let collateral = spl_token::state::Account::unpack(&collateral_info.data.borrow())?;
-    if &collateral.owner != pda_account.key {
-        panic!("bad collateral ownership!");
-    }
+    check_mints_and_pda(&fluidity_data_account, *token_mint.key, *fluidity_mint.key, *pda_account.key)?;
 
-    // refresh reserve
-    invoke(
-        &Instruction::new_with_borsh(
-            *solend_program.key,
-            &LendingInstruction::RefreshReserve,
-            vec![
-                AccountMeta::new(*reserve_info.key, false),
-                AccountMeta::new_readonly(*pyth_price_info.key, false),
-                AccountMeta::new_readonly(*switchboard_feed_info.key, false),
-                AccountMeta::new_readonly(*clock_info.key, false),
-            ],
-        ),
-        &[
-            reserve_info.clone(), pyth_price_info.clone(), switchboard_feed_info.clone(),
-            clock_info.clone(), solend_program.clone()
-        ]
-    )?;
+    check_not_paused(program_id, config_account, fluidity_data_account, false)?;
 
     // deposit liquidity from user token account
-    invoke(
-        &Instruction::new_with_borsh(
-            *solend_program.key,
-            &LendingInstruction::DepositReserveLiquidity{liquidity_amount: amount},
-            vec![
-                AccountMeta::new(*token_account.key, false),
-                AccountMeta::new(*collateral_info.key, false),
-                AccountMeta::new(*reserve_info.key, false),
-                AccountMeta::new(*reserve_liquidity_supply_info.key, false),
-                AccountMeta::new(*reserve_collateral_mint_info.key, false),
-                AccountMeta::new(*lending_market_info.key, false),
-                AccountMeta::new_readonly(*lending_market_authority_info.key, false),
-                AccountMeta::new(*sender.key, true),
-                AccountMeta::new_readonly(*clock_info.key, false),
-                AccountMeta::new_readonly(*token_program.key, false),
-            ],
-        ),
-        &[
-            token_account.clone(), collateral_info.clone(), reserve_info.clone(),
-            reserve_liquidity_supply_info.clone(), reserve_collateral_mint_info.clone(),
-            lending_market_info.clone(), lending_market_authority_info.clone(),
-            sender.clone(), clock_info.clone(), token_program.clone(),
-        ],
-    )?;
-
-    // refresh reserve again
-    invoke(
-        &Instruction::new_with_borsh(
-            *solend_program.key,
-            &LendingInstruction::RefreshReserve,
-            vec![
-                AccountMeta::new(*reserve_info.key, false),
-                AccountMeta::new_readonly(*pyth_price_info.key, false),
-                AccountMeta::new_readonly(*switchboard_feed_info.key, false),
-                AccountMeta::new_readonly(*clock_info.key, false),
-            ],
-        ),
-        &[
-            reserve_info.clone(), pyth_price_info.clone(), switchboard_feed_info.clone(),
-            clock_info.clone(), solend_program.clone()
-        ]
-    )?;
-
-    // calculate collateral amount
-    let reserve = Reserve::unpack(&reserve_info.data.borrow())?;
-    let collateral_amount = reserve.collateral_exchange_rate()?.liquidity_to_collateral(amount)?;
-
-    // deposit collateral into obligation
-    invoke_signed(
-        &Instruction::new_with_borsh(
-            *solend_program.key,
-            &LendingInstruction::DepositObligationCollateral{collateral_amount},
-            vec![
-                AccountMeta::new(*collateral_info.key, false),
-                AccountMeta::new(*destination_collateral_info.key, false),
-                AccountMeta::new(*reserve_info.key, false),
-                AccountMeta::new(*obligation_info.key, false),
-                AccountMeta::new(*lending_market_info.key, false),
-                AccountMeta::new(*pda_account.key, true),
-                AccountMeta::new(*pda_account.key, true),
-                AccountMeta::new_readonly(*clock_info.key, false),
-                AccountMeta::new_readonly(*token_program.key, false),
-            ]
-        ),
-        &[
-            collateral_info.clone(), destination_collateral_info.clone(), reserve_info.clone(), obligation_info.clone(),
-            lending_market_info.clone(), pda_account.clone(), clock_info.clone(), token_program.clone(),
-        ],
-        &[&[&pda_seed.as_bytes(), &[bump]]],
+    let fluid_mint = spl_token::state::Mint::unpack(&fluidity_mint.data.borrow())?;
+    let (collateral_amount, fluid_amount) = adapter(fluidity_data.adapter).deposit(
+        &fluidity_data,
+        adapter_accounts,
+        &UserAccounts {
+            token_program,
+            pda: pda_account,
+            pda_seeds: &[pda_seed.as_bytes(), &[bump]],
+            token_account,
+            owner: sender,
+        },
+        &fluid_mint,
+        amount,
     )?;
 
     // mint fluid tokens to user account
+    let fluid_before = token_balance(fluidity_account)?;
     invoke_signed(
         &spl_token::instruction::mint_to(
            &token_program.key,
@@ -201,17 +568,36 @@ fn wrap(accounts: &[AccountInfo], program_id: &Pubkey, amount: u64, seed: String
             &fluidity_account.key,
             &pda_account.key,
             &[&pda_account.key],
-            amount,
-        ).unwrap(),
+            fluid_amount,
+        )?,
         &[fluidity_mint.clone(), fluidity_account.clone(), pda_account.clone(), token_program.clone()],
         &[&[&pda_seed.as_bytes(), &[bump]]],
     )?;
+    check_min_out(fluid_before, token_balance(fluidity_account)?, min_out)?;
+
+    FluidityEvent::Wrapped {
+        token_mint: *token_mint.key,
+        fluid_mint: *fluidity_mint.key,
+        owner: *sender.key,
+        amount,
+        fluid_amount,
+        collateral_amount,
+        exchange_rate: exchange_rate(amount, collateral_amount),
+    }.emit();
 
     Ok(())
 }
 
-// unwrap amount of fluid token into corresponding token
-fn unwrap(accounts: &[AccountInfo], program_id: &Pubkey, amount: u64, seed: String, bump: u8) -> ProgramResult {
+// unwrap amount of fluid token into corresponding token, failing if fewer
+// than min_out base tokens are withdrawn - the adapter withdraws at least the
+// amount while the prize pool can absorb the rounding
+fn unwrap(
+    accounts: &[AccountInfo],
+    program_id: &Pubkey,
+    amount: u64,
+    min_out: u64,
+    seed: String,
+) -> ProgramResult {
     let accounts_iter = &mut accounts.iter();
 
     let fluidity_data_account = next_account_info(accounts_iter)?;
@@ -222,50 +608,24 @@ fn unwrap(accounts: &[AccountInfo], program_id: &Pubkey, amount: u64, seed: Stri
     let sender = next_account_info(accounts_iter)?;
     let token_account = next_account_info(accounts_iter)?;
     let fluidity_account = next_account_info(accounts_iter)?;
-    let solend_program = next_account_info(accounts_iter)?;
-    let collateral_info = next_account_info(accounts_iter)?;
-    let reserve_info = next_account_info(accounts_iter)?;
-    let reserve_liquidity_supply_info = next_account_info(accounts_iter)?;
-    let reserve_collateral_mint_info = next_account_info(accounts_iter)?;
-    let lending_market_info = next_account_info(accounts_iter)?;
-    let lending_market_authority_info = next_account_info(accounts_iter)?;
-    let deposited_collateral_info = next_account_info(accounts_iter)?;
-    let obligation_info = next_account_info(accounts_iter)?;
-    let pyth_price_info = next_account_info(accounts_iter)?;
-    let switchboard_feed_info = next_account_info(accounts_iter)?;
-    let clock_info = next_account_info(accounts_iter)?;
+    let config_account = next_account_info(accounts_iter)?;
 
-    // check solend contract
-    if solend_program.key !=
-        &Pubkey::from_str(SOLEND).unwrap() {
-        panic!("bad Solend contract!");
-    }
+    // the rest are passed through to the token's lending adapter
+    let adapter_accounts = accounts_iter.as_slice();
 
-    // check collateral and obligation ownership
-    let obligation = Obligation::unpack(&obligation_info.data.borrow())?;
-    if &obligation.owner != pda_account.key {
-        panic!("bad obligation ownership!");
-    }
-    let collateral = spl_token::state::Account::unpack(&collateral_info.data.borrow())?;
-    if &collateral.owner != pda_account.key {
-        panic!("bad collateral ownership!");
+    if token_program.key != &spl_token::id() {
+        msg!("bad token program");
+        return Err(FluidityError::InvalidTokenProgram.into());
     }
 
-    // create seed strings from provided token
-    let pda_seed = format!("FLU:{}_OBLIGATION", seed);
-    let data_seed = format!("FLU:{}_DATA", seed);
+    // check data account and pda, getting the pda's bump seed
+    let fluidity_data = load_fluidity_data(program_id, &seed, fluidity_data_account, pda_account)?;
+    let bump = fluidity_data.bump;
+    let pda_seed = obligation_authority_seed(&seed);
 
-    // check that data account is derived from pda
-    if fluidity_data_account.key !=
-        &Pubkey::create_with_seed(
-            pda_account.key,
-            &data_seed,
-            program_id,
-        ).unwrap() {
-            panic!("bad data account");
-    }
+    check_mints_and_pda(&fluidity_data_account, *token_mint.key, *fluidity_mint.key, *pda_account.key)?;
 
-    check_mints_and_pda(&fluidity_data_account, *token_mint.key, *fluidity_mint.key, *pda_account.key);
+    check_not_paused(program_id, config_account, fluidity_data_account, true)?;
 
     // burn fluid tokens
     invoke(
@@ -276,8 +636,7 @@ fn unwrap(accounts: &[AccountInfo], program_id: &Pubkey, amount: u64, seed: Stri
             &sender.key,
             &[&sender.key],
             amount,
-        )
-        .unwrap(),
+        )?,
         &[
             fluidity_account.clone(),
             fluidity_mint.clone(),
@@ -285,226 +644,377 @@ fn unwrap(accounts: &[AccountInfo], program_id: &Pubkey, amount: u64, seed: Stri
         ],
     )?;
 
-    // refresh reserve
-    invoke(
-        &Instruction::new_with_borsh(
-            *solend_program.key,
-            &LendingInstruction::RefreshReserve,
-            vec![
-                AccountMeta::new(*reserve_info.key, false),
-                AccountMeta::new_readonly(*pyth_price_info.key, false),
-                AccountMeta::new_readonly(*switchboard_feed_info.key, false),
-                AccountMeta::new_readonly(*clock_info.key, false),
-            ],
-        ),
-        &[
-            reserve_info.clone(),
-            pyth_price_info.clone(),
-            switchboard_feed_info.clone(),
-            clock_info.clone(),
-            solend_program.clone(),
-        ],
-    )?;
-
-    // refresh obligation
-    invoke(
-        &Instruction::new_with_borsh(
-            *solend_program.key,
-            &LendingInstruction::RefreshObligation,
-            vec![
-                AccountMeta::new(*obligation_info.key, false),
-                AccountMeta::new_readonly(*clock_info.key, false),
-                AccountMeta::new(*reserve_info.key, false),
-            ],
-        ),
-        &[
-            obligation_info.clone(),
-            clock_info.clone(),
-            reserve_info.clone(),
-            solend_program.clone(),
-        ],
+    // withdraw from the lending backend to the user's token account
+    let fluid_mint = spl_token::state::Mint::unpack(&fluidity_mint.data.borrow())?;
+    let token_before = token_balance(token_account)?;
+    let collateral_amount = adapter(fluidity_data.adapter).withdraw(
+        &fluidity_data,
+        adapter_accounts,
+        &UserAccounts {
+            token_program,
+            pda: pda_account,
+            pda_seeds: &[pda_seed.as_bytes(), &[bump]],
+            token_account,
+            owner: sender,
+        },
+        &fluid_mint,
+        amount,
     )?;
+    let token_after = token_balance(token_account)?;
+    check_min_out(token_before, token_after, min_out)?;
+    let base_amount = token_after.saturating_sub(token_before);
 
-    // calculate collateral amount from refreshed reserve
-    let reserve = Reserve::unpack(&reserve_info.data.borrow())?;
-    let collateral_amount = reserve.collateral_exchange_rate()?.liquidity_to_collateral(amount)?;
-
-    // withdraw from solend to the user's token account
-    invoke_signed(
-        &Instruction::new_with_borsh(
-            *solend_program.key,
-            &LendingInstruction::WithdrawObligationCollateralAndRedeemReserveCollateral {
-                collateral_amount,
-            },
-            vec![
-                AccountMeta::new(*deposited_collateral_info.key, false),
-                AccountMeta::new(*collateral_info.key, false),
-                AccountMeta::new(*reserve_info.key, false),
-                AccountMeta::new(*obligation_info.key, false),
-                AccountMeta::new(*lending_market_info.key, false),
-                AccountMeta::new_readonly(*lending_market_authority_info.key, false),
-                AccountMeta::new(*token_account.key, false),
-                AccountMeta::new(*reserve_collateral_mint_info.key, false),
-                AccountMeta::new(*reserve_liquidity_supply_info.key, false),
-                AccountMeta::new(*pda_account.key, true),
-                AccountMeta::new(*pda_account.key, true),
-                AccountMeta::new_readonly(*clock_info.key, false),
-                AccountMeta::new_readonly(*token_program.key, false),
-            ],
-        ),
-        &[
-            deposited_collateral_info.clone(),
-            collateral_info.clone(),
-            reserve_info.clone(),
-            obligation_info.clone(),
-            lending_market_info.clone(),
-            lending_market_authority_info.clone(),
-            token_account.clone(),
-            reserve_collateral_mint_info.clone(),
-            reserve_liquidity_supply_info.clone(),
-            pda_account.clone(),
-            clock_info.clone(),
-            token_program.clone(),
-            solend_program.clone(),
-        ],
-        &[&[&pda_seed.as_bytes(), &[bump]]],
-    )?;
+    FluidityEvent::Unwrapped {
+        token_mint: *token_mint.key,
+        fluid_mint: *fluidity_mint.key,
+        owner: *sender.key,
+        amount,
+        base_amount,
+        collateral_amount,
+        exchange_rate: exchange_rate(base_amount, collateral_amount),
+    }.emit();
 
     Ok(())
 }
 
-// takes an amount of tokens, and two acounts and pays out in an 8:2 split,
-// totalling at most 80% of the prize pool - must be run by authority
-fn payout(accounts: &[AccountInfo], amount: u64, seed: String, bump: u8) -> ProgramResult {
+// takes an amount of tokens, and two acounts and pays out split between them,
+// totalling at most the token's share of the prize pool, recording a receipt
+// for the winning transfer - the transfer can be read from the token's
+// transfer log, and its amount drawn from it and the slot hashes - must be
+// run by authority
+fn payout(
+    accounts: &[AccountInfo],
+    program_id: &Pubkey,
+    source: PayoutSource,
+    seed: String,
+) -> ProgramResult {
     let accounts_iter = &mut accounts.iter();
 
+    // checked against the payout operator role in process
+    let config_account = next_account_info(accounts_iter)?;
+    let payer = next_account_info(accounts_iter)?;
     let token_program = next_account_info(accounts_iter)?;
     let fluidity_mint = next_account_info(accounts_iter)?;
     let pda_account = next_account_info(accounts_iter)?;
-    let obligation_info = next_account_info(accounts_iter)?;
-    let reserve_info = next_account_info(accounts_iter)?;
     let payout_account_a = next_account_info(accounts_iter)?;
     let payout_account_b = next_account_info(accounts_iter)?;
-    let payer = next_account_info(accounts_iter)?;
+    let fluidity_data_account = next_account_info(accounts_iter)?;
+    let receipt_account = next_account_info(accounts_iter)?;
+    let system_program = next_account_info(accounts_iter)?;
+    let clock_info = next_account_info(accounts_iter)?;
 
-    // check payout authority
-    if !(payer.is_signer && payer.key ==
-         &Pubkey::from_str(AUTHORITY).unwrap()) {
-        panic!("bad payout authority!");
+    if token_program.key != &spl_token::id() {
+        msg!("bad token program");
+        return Err(FluidityError::InvalidTokenProgram.into());
+    }
+
+    // check data account and pda, getting the pda's bump seed
+    let fluidity_data = load_fluidity_data(program_id, &seed, fluidity_data_account, pda_account)?;
+    if &fluidity_data.fluid_mint != fluidity_mint.key {
+        msg!("bad fluid mint");
+        return Err(FluidityError::InvalidFluidMint.into());
     }
+    let bump = fluidity_data.bump;
 
-    // scale/clamp amount to be AT MOST 80% of the prize pool
+    check_not_paused(program_id, config_account, fluidity_data_account, false)?;
 
-    // get obligation and reserve structs
-    let obligation = Obligation::unpack(&obligation_info.data.borrow())?;
-    let reserve = Reserve::unpack(&reserve_info.data.borrow())?;
-    // get value of obligations
-    let deposited_amount = obligation.deposits[0].deposited_amount;
-    let deposited_value = reserve.collateral_exchange_rate()?
-        .collateral_to_liquidity(deposited_amount).unwrap();
-    // normalise
-    // get fluidity mint object
-    let fluid_mint = spl_token::state::Mint::unpack(&fluidity_mint.data.borrow())?;
-    let decimals = fluid_mint.decimals as u32;
-
-    // get amount of usdc deposited (has 6 decimals)
-    let deposited_tokens = fluid_mint.supply;
-    // get available prize pool (80% of pool)
-    let available_prize_pool = (deposited_value - deposited_tokens)
-        .checked_mul(8).unwrap()
-        .checked_div(10).unwrap();
-
-    // set new amount
-    let scaled_amount = if amount > available_prize_pool {
-        available_prize_pool
-    } else {
-        amount
+    // draws read the slot hashes sysvar, and logged transfers the transfer
+    // log, which follow the clock
+    let (amount, transfer_hash, transfer_slot, slot_logged) = match source {
+        PayoutSource::Amount(amount, transfer_hash, transfer_slot) => {
+            (amount, transfer_hash, transfer_slot, false)
+        }
+        PayoutSource::Draw(index) => {
+            let transfer_log = next_account_info(accounts_iter)?;
+            let slot_hashes = next_account_info(accounts_iter)?;
+            let entry = load_logged_transfer(
+                program_id, &fluidity_data, transfer_log, index, payout_account_a, payout_account_b,
+            )?;
+            let transfer_hash = logged_transfer_hash(fluidity_mint.key, index);
+
+            // the hash of the first slot after the transfer's wasn't known
+            // when the transfer was made
+            let slot_hash = find_next_slot_hash(slot_hashes, entry.slot)?;
+            let prize = draw_prize(
+                &slot_hash,
+                &transfer_hash,
+                &entry.sender,
+                &entry.receiver,
+                &fluidity_data.draw_tiers,
+            );
+            if prize == 0 {
+                msg!("transfer did not win");
+                return Err(FluidityError::TransferDidNotWin.into());
+            }
+            (prize, transfer_hash, entry.slot, true)
+        }
+        PayoutSource::Logged(amount, index) => {
+            let transfer_log = next_account_info(accounts_iter)?;
+            let entry = load_logged_transfer(
+                program_id, &fluidity_data, transfer_log, index, payout_account_a, payout_account_b,
+            )?;
+            (amount, logged_transfer_hash(fluidity_mint.key, index), entry.slot, true)
+        }
     };
 
-    // separate pool into 8:2 split between sender and receiver
-    let sender_prize = scaled_amount
-        .checked_mul(8).unwrap()
-        .checked_div(10).unwrap();
-    let receiver_prize = scaled_amount
-        .checked_mul(2).unwrap()
-        .checked_div(10).unwrap();
-
-    let pda_seed =  format!("FLU:{}_OBLIGATION", seed);
+    // the rest are passed to the token's lending adapter to value the pool
+    let adapter_accounts = accounts_iter.as_slice();
 
-    // mint fluid tokens to both receivers
+    let deposited = adapter(fluidity_data.adapter).deposited(&fluidity_data, adapter_accounts)?;
+    let fluid_mint = spl_token::state::Mint::unpack(&fluidity_mint.data.borrow())?;
 
-    invoke_signed(
-        &spl_token::instruction::mint_to(
-            &token_program.key,
-            &fluidity_mint.key,
-            &payout_account_a.key,
-            &pda_account.key,
-            &[&pda_account.key],
-            sender_prize,
-        ).unwrap(),
-        &[fluidity_mint.clone(), payout_account_a.clone(), pda_account.clone(), token_program.clone()],
-        &[&[&pda_seed.as_bytes(), &[bump]]],
+    // clamp the amount to the token's share of the prize pool, and split it
+    // between sender and receiver
+    let available_prize_pool = apply_bps(
+        prize_pool(&deposited, &fluid_mint)?,
+        fluidity_data.prize_pool_cap_bps,
+    )?;
+    let (sender_prize, receiver_prize) = split_prize(
+        amount.min(available_prize_pool),
+        fluidity_data.sender_share_bps,
     )?;
 
-    invoke_signed(
-        &spl_token::instruction::mint_to(
-            &token_program.key,
-            &fluidity_mint.key,
-            &payout_account_b.key,
-            &pda_account.key,
-            &[&pda_account.key],
+    // fail if the transfer has already been paid
+    let clock = Clock::from_account_info(clock_info)?;
+    create_receipt(
+        program_id,
+        payer,
+        system_program,
+        receipt_account,
+        clock.slot,
+        &transfer_hash,
+        &PayoutReceipt {
+            fluid_mint: *fluidity_mint.key,
+            transfer_slot,
+            sender_prize,
             receiver_prize,
-        ).unwrap(),
-        &[fluidity_mint.clone(), payout_account_b.clone(), pda_account.clone(), token_program.clone()],
-        &[&[&pda_seed.as_bytes(), &[bump]]],
+            slot_logged,
+        },
     )?;
 
+    let pda_seed = obligation_authority_seed(&seed);
+    let pda_seeds: &[&[u8]] = &[pda_seed.as_bytes(), &[bump]];
+
+    // mint fluid tokens to both receivers
+    mint_prize(token_program, fluidity_mint, payout_account_a, pda_account, pda_seeds, sender_prize)?;
+    mint_prize(token_program, fluidity_mint, payout_account_b, pda_account, pda_seeds, receiver_prize)?;
+
+    FluidityEvent::PaidOut {
+        fluid_mint: *fluidity_mint.key,
+        sender: *payout_account_a.key,
+        receiver: *payout_account_b.key,
+        sender_prize,
+        receiver_prize,
+        transfer_hash,
+        transfer_slot,
+        deposited_value: deposited.value,
+        fluid_supply: fluid_mint.supply,
+    }.emit();
+
     Ok(())
 }
 
-// initialise obligation account controlled by PDA - must be run by authority
-fn init_solend_obligation(
+// pays out many winning transfers, each an amount and the indices of its
+// sender and receiver among the payout accounts, valuing the prize pool once
+// and capping the whole batch at the token's share of it - must be run by
+// the payout operator
+fn batch_payout(
     accounts: &[AccountInfo],
-    obligation_lamports: u64,
-    obligation_size: u64,
+    program_id: &Pubkey,
+    winners: Vec<Winner>,
     seed: String,
-    bump: u8,
 ) -> ProgramResult {
     let accounts_iter = &mut accounts.iter();
+
+    // checked against the payout operator role in process
+    let config_account = next_account_info(accounts_iter)?;
     let payer = next_account_info(accounts_iter)?;
-    let solend_program = next_account_info(accounts_iter)?;
+    let token_program = next_account_info(accounts_iter)?;
+    let fluidity_mint = next_account_info(accounts_iter)?;
+    let pda_account = next_account_info(accounts_iter)?;
+    let fluidity_data_account = next_account_info(accounts_iter)?;
     let system_program = next_account_info(accounts_iter)?;
-    // init obligation infos
-    let obligation_info = next_account_info(accounts_iter)?;
-    let lending_market_info = next_account_info(accounts_iter)?;
-    let obligation_owner_info = next_account_info(accounts_iter)?;
     let clock_info = next_account_info(accounts_iter)?;
-    let rent_info = next_account_info(accounts_iter)?;
-    let token_program = next_account_info(accounts_iter)?;
 
-    // check init authority
-    if !(payer.is_signer && payer.key ==
-         &Pubkey::from_str(AUTHORITY).unwrap()) {
-        panic!("bad init authority!");
+    // the payout accounts are the ones referenced by the winners, followed
+    // by a receipt for each winner, and the rest are passed to the token's
+    // lending adapter to value the pool
+    let payout_accounts_len = winners.iter()
+        .map(|winner| winner.sender.max(winner.receiver) as usize + 1)
+        .max()
+        .unwrap_or(0);
+    let remaining = accounts_iter.as_slice();
+    if remaining.len() < payout_accounts_len + winners.len() {
+        return Err(ProgramError::NotEnoughAccountKeys);
     }
+    let (payout_accounts, remaining) = remaining.split_at(payout_accounts_len);
+    let (receipt_accounts, adapter_accounts) = remaining.split_at(winners.len());
 
-    let pda_seed =  format!("FLU:{}_OBLIGATION", seed);
+    if token_program.key != &spl_token::id() {
+        msg!("bad token program");
+        return Err(FluidityError::InvalidTokenProgram.into());
+    }
 
-    invoke_signed(
-        &system_instruction::create_account_with_seed(
-            &payer.key,
-            &obligation_info.key,
-            &obligation_owner_info.key,
-            &lending_market_info.key.to_string()[0..32],
-            obligation_lamports,
+    // check data account and pda, getting the pda's bump seed
+    let fluidity_data = load_fluidity_data(program_id, &seed, fluidity_data_account, pda_account)?;
+    if &fluidity_data.fluid_mint != fluidity_mint.key {
+        msg!("bad fluid mint");
+        return Err(FluidityError::InvalidFluidMint.into());
+    }
+    let bump = fluidity_data.bump;
+
+    check_not_paused(program_id, config_account, fluidity_data_account, false)?;
+
+    // value the prize pool once for the whole batch
+    let deposited = adapter(fluidity_data.adapter).deposited(&fluidity_data, adapter_accounts)?;
+    let fluid_mint = spl_token::state::Mint::unpack(&fluidity_mint.data.borrow())?;
+    let mut available_prize_pool = apply_bps(
+        prize_pool(&deposited, &fluid_mint)?,
+        fluidity_data.prize_pool_cap_bps,
+    )?;
+
+    let pda_seed = obligation_authority_seed(&seed);
+    let pda_seeds: &[&[u8]] = &[pda_seed.as_bytes(), &[bump]];
+
+    let clock = Clock::from_account_info(clock_info)?;
+
+    // pay winners in order until the pool share runs out
+    for (winner, receipt_account) in winners.iter().zip(receipt_accounts) {
+        let scaled_amount = winner.amount.min(available_prize_pool);
+        available_prize_pool -= scaled_amount;
+
+        let (sender_prize, receiver_prize) = split_prize(scaled_amount, fluidity_data.sender_share_bps)?;
+
+        create_receipt(
+            program_id,
+            payer,
+            system_program,
+            receipt_account,
+            clock.slot,
+            &winner.transfer_hash,
+            &PayoutReceipt {
+                fluid_mint: *fluidity_mint.key,
+                transfer_slot: winner.transfer_slot,
+                sender_prize,
+                receiver_prize,
+                slot_logged: false,
+            },
+        )?;
+
+        let sender = &payout_accounts[winner.sender as usize];
+        let receiver = &payout_accounts[winner.receiver as usize];
+        mint_prize(token_program, fluidity_mint, sender, pda_account, pda_seeds, sender_prize)?;
+        mint_prize(token_program, fluidity_mint, receiver, pda_account, pda_seeds, receiver_prize)?;
+
+        FluidityEvent::PaidOut {
+            fluid_mint: *fluidity_mint.key,
+            sender: *sender.key,
+            receiver: *receiver.key,
+            sender_prize,
+            receiver_prize,
+            transfer_hash: winner.transfer_hash,
+            transfer_slot: winner.transfer_slot,
+            deposited_value: deposited.value,
+            fluid_supply: fluid_mint.supply,
+        }.emit();
+    }
+
+    Ok(())
+}
+
+// initialise obligation account controlled by PDA - must be run by authority
+fn init_solend_obligation(
+    accounts: &[AccountInfo],
+    program_id: &Pubkey,
+    obligation_lamports: u64,
+    obligation_size: u64,
+    seed: String,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    // checked against the registry admin role in process
+    let _config_account = next_account_info(accounts_iter)?;
+    let payer = next_account_info(accounts_iter)?;
+    let solend_program = next_account_info(accounts_iter)?;
+    let system_program = next_account_info(accounts_iter)?;
+    // init obligation infos
+    let obligation_info = next_account_info(accounts_iter)?;
+    let lending_market_info = next_account_info(accounts_iter)?;
+    let obligation_owner_info = next_account_info(accounts_iter)?;
+    let clock_info = next_account_info(accounts_iter)?;
+    let rent_info = next_account_info(accounts_iter)?;
+    let token_program = next_account_info(accounts_iter)?;
+    let fluidity_data_account = next_account_info(accounts_iter)?;
+
+    // check solend contract
+    solend::check_program(solend_program)?;
+
+    // check data account and pda, getting the pda's bump seed
+    let fluidity_data = load_fluidity_data(program_id, &seed, fluidity_data_account, obligation_owner_info)?;
+    let bump = fluidity_data.bump;
+    let pda_seed = obligation_authority_seed(&seed);
+
+    check_recorded(obligation_info, &fluidity_data.obligation, FluidityError::InvalidObligation)?;
+    check_recorded(lending_market_info, &fluidity_data.lending_market, FluidityError::InvalidLendingMarket)?;
+
+    create_obligation(
+        &ObligationAccounts {
+            payer,
+            solend_program,
+            system_program,
+            obligation: obligation_info,
+            lending_market: lending_market_info,
+            pda: obligation_owner_info,
+            clock: clock_info,
+            rent: rent_info,
+            token_program,
+        },
+        &[pda_seed.as_bytes(), &[bump]],
+        obligation_lamports,
+        obligation_size,
+    )
+}
+
+// accounts used to create a token's solend obligation
+struct ObligationAccounts<'b, 'a> {
+    payer: &'b AccountInfo<'a>,
+    solend_program: &'b AccountInfo<'a>,
+    system_program: &'b AccountInfo<'a>,
+    obligation: &'b AccountInfo<'a>,
+    lending_market: &'b AccountInfo<'a>,
+    // the obligation authority, owning the obligation
+    pda: &'b AccountInfo<'a>,
+    clock: &'b AccountInfo<'a>,
+    rent: &'b AccountInfo<'a>,
+    token_program: &'b AccountInfo<'a>,
+}
+
+// create an obligation owned by the pda, at the address derived from the pda
+// and the lending market
+fn create_obligation(
+    accounts: &ObligationAccounts,
+    pda_seeds: &[&[u8]],
+    obligation_lamports: u64,
+    obligation_size: u64,
+) -> ProgramResult {
+    let ObligationAccounts {
+        payer, solend_program, system_program, obligation, lending_market, pda, clock, rent, token_program,
+    } = *accounts;
+
+    invoke_signed(
+        &system_instruction::create_account_with_seed(
+            payer.key,
+            obligation.key,
+            pda.key,
+            &lending_market.key.to_string()[0..32],
+            obligation_lamports,
             obligation_size,
-            &solend_program.key,
+            solend_program.key,
         ),
         &[
-            payer.clone(), obligation_info.clone(), obligation_owner_info.clone(),
-            lending_market_info.clone(), solend_program.clone(), system_program.clone()
+            payer.clone(), obligation.clone(), pda.clone(),
+            lending_market.clone(), solend_program.clone(), system_program.clone()
         ],
-        &[&[&pda_seed.as_bytes(), &[bump]]],
+        &[pda_seeds],
     )?;
 
     invoke_signed(
@@ -512,127 +1022,334 @@ fn init_solend_obligation(
             *solend_program.key,
             &LendingInstruction::InitObligation,
             vec![
-                AccountMeta::new(*obligation_info.key, false),
-                AccountMeta::new(*lending_market_info.key, false),
-                AccountMeta::new(*obligation_owner_info.key, true),
-                AccountMeta::new_readonly(*clock_info.key, false),
-                AccountMeta::new_readonly(*rent_info.key, false),
+                AccountMeta::new(*obligation.key, false),
+                AccountMeta::new(*lending_market.key, false),
+                AccountMeta::new(*pda.key, true),
+                AccountMeta::new_readonly(*clock.key, false),
+                AccountMeta::new_readonly(*rent.key, false),
                 AccountMeta::new_readonly(*token_program.key, false)
             ]
         ),
-        &[obligation_info.clone(), lending_market_info.clone(), obligation_owner_info.clone(),
-          clock_info.clone(), rent_info.clone(), token_program.clone(), solend_program.clone()],
-        &[&[&pda_seed.as_bytes(), &[bump]]],
-    )?;
-
-    Ok(())
+        &[obligation.clone(), lending_market.clone(), pda.clone(),
+          clock.clone(), rent.clone(), token_program.clone(), solend_program.clone()],
+        &[pda_seeds],
+    )
 }
 
-// takes a data account derived from a base account, and serialises the total value of obligations into it
-pub fn log_tvl(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+// takes a data account derived from a base account, and appends the total
+// value of each listed token's deposits to its TVL log, and their sum in the
+// lending markets' quote currency to the TVL log the data account holds
+pub fn log_tvl(accounts: &[AccountInfo], program_id: &Pubkey, seeds: Vec<String>) -> ProgramResult {
     let accounts_iter = &mut accounts.iter();
 
     let data_account = next_account_info(accounts_iter)?;
     let base = next_account_info(accounts_iter)?;
-    let solend_program = next_account_info(accounts_iter)?;
-    let obligation_info = next_account_info(accounts_iter)?;
-    let reserve_info = next_account_info(accounts_iter)?;
-    let pyth_price_info = next_account_info(accounts_iter)?;
-    let switchboard_feed_info = next_account_info(accounts_iter)?;
-    let clock_info = next_account_info(accounts_iter)?;
 
     // check that data account is derived from base
     if data_account.key !=
         &Pubkey::create_with_seed(
             base.key,
-            "FLU:TVL_DATA",
+            TVL_DATA_SEED,
             program_id,
-        ).unwrap() {
-            panic!("bad data account");
+        )? {
+            msg!("bad data account");
+            return Err(FluidityError::InvalidDataAccount.into());
     }
 
-    // refresh solend accounts
+    let clock = Clock::get()?;
+    let mut quote_currency = None;
+    let mut market_value: u128 = 0;
 
-    invoke(
-        &Instruction::new_with_borsh(
-            *solend_program.key,
-            &LendingInstruction::RefreshReserve,
-            vec![
-                AccountMeta::new(*reserve_info.key, false),
-                AccountMeta::new_readonly(*pyth_price_info.key, false),
-                AccountMeta::new_readonly(*switchboard_feed_info.key, false),
-                AccountMeta::new_readonly(*clock_info.key, false),
-            ],
-        ),
-        &[
-            reserve_info.clone(),
-            pyth_price_info.clone(),
-            switchboard_feed_info.clone(),
-            clock_info.clone(),
-            solend_program.clone(),
-        ],
-    )?;
+    // each token's accounts are its TVL log, data account and pda, followed
+    // by its lending adapter's
+    for seed in seeds {
+        let tvl_log = next_account_info(accounts_iter)?;
+        let fluidity_data_account = next_account_info(accounts_iter)?;
+        let pda_account = next_account_info(accounts_iter)?;
 
-    invoke(
-        &Instruction::new_with_borsh(
-            *solend_program.key,
-            &LendingInstruction::RefreshObligation,
-            vec![
-                AccountMeta::new(*obligation_info.key, false),
-                AccountMeta::new_readonly(*clock_info.key, false),
-                AccountMeta::new(*reserve_info.key, false),
-            ],
-        ),
-        &[
-            obligation_info.clone(),
-            clock_info.clone(),
-            reserve_info.clone(),
-            solend_program.clone(),
-        ],
-    )?;
+        if tvl_log.key != &Pubkey::create_with_seed(base.key, &tvl_data_seed(&seed), program_id)? {
+            msg!("bad TVL log for {}", seed);
+            return Err(FluidityError::InvalidDataAccount.into());
+        }
 
-    // deserialize obligation and reserve
-    let obligation = Obligation::unpack(&obligation_info.data.borrow())?;
-    let reserve = Reserve::unpack(&reserve_info.data.borrow())?;
+        let fluidity_data = load_fluidity_data(program_id, &seed, fluidity_data_account, pda_account)?;
+        let adapter = adapter(fluidity_data.adapter);
 
-    // get data
-    let mut data = data_account.try_borrow_mut_data()?;
+        let remaining = accounts_iter.as_slice();
+        let (adapter_accounts, remaining) = remaining.split_at(
+            adapter.tvl_accounts_len(&fluidity_data, remaining)?.min(remaining.len()),
+        );
+        *accounts_iter = remaining.iter();
 
-    // serialize value of obligations (incl. interest) into data account
-    // get scaled u128 val. it has 18 decimal places so divide by 1e18-n to get n decimals
-    let deposited_amount = obligation.deposits[0].deposited_amount;
-    let deposited_value = reserve.collateral_exchange_rate()?
-        .collateral_to_liquidity(deposited_amount).unwrap();
+        // refresh the lending backend so the value includes interest
+        adapter.refresh(&fluidity_data, adapter_accounts)?;
+
+        // value of deposits (incl. interest)
+        let deposited = adapter.deposited(&fluidity_data, adapter_accounts)?;
+        let deposited_value = deposited.value;
+
+        // only values in the same currency can be added up
+        let token_quote_currency = adapter.quote_currency(&fluidity_data, adapter_accounts)?;
+        if *quote_currency.get_or_insert(token_quote_currency) != token_quote_currency {
+            msg!("{} is quoted in a different currency", seed);
+            return Err(FluidityError::QuoteCurrencyMismatch.into());
+        }
+        market_value = market_value.checked_add(deposited.market_value)
+            .ok_or(LendingError::MathOverflow)?;
+
+        append_tvl_record(
+            &mut tvl_log.try_borrow_mut_data()?,
+            &TvlRecord {
+                slot: clock.slot,
+                unix_timestamp: clock.unix_timestamp,
+                deposited_value,
+                collateral_amount: deposited.collateral_amount,
+                exchange_rate: exchange_rate(deposited_value, deposited.collateral_amount),
+                market_value: deposited.market_value,
+            },
+        )?;
+
+        FluidityEvent::TvlLogged {
+            token_mint: fluidity_data.token_mint,
+            fluid_mint: fluidity_data.fluid_mint,
+            deposited_value,
+            market_value: deposited.market_value,
+        }.emit();
+    }
 
-    deposited_value.serialize(&mut &mut data[..])?;
+    append_tvl_record(
+        &mut data_account.try_borrow_mut_data()?,
+        &AggregateTvlRecord {
+            slot: clock.slot,
+            unix_timestamp: clock.unix_timestamp,
+            quote_currency: quote_currency.unwrap_or_default(),
+            market_value,
+        },
+    )?;
 
     Ok(())
 }
 
+// close the TVL data account derived from a base account that the
+// unversioned log_tvl serialised a single value into, which is too small for
+// the TVL log, so the base account can create it again with TVL_LOG_SPACE
+fn close_tvl_data(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    // checked against the admin role in process
+    let _config_account = next_account_info(accounts_iter)?;
+    let _admin = next_account_info(accounts_iter)?;
+    let data_account = next_account_info(accounts_iter)?;
+    let base = next_account_info(accounts_iter)?;
+    let destination = next_account_info(accounts_iter)?;
+
+    if data_account.key != &Pubkey::create_with_seed(base.key, TVL_DATA_SEED, program_id)? ||
+        data_account.owner != program_id {
+        msg!("bad data account");
+        return Err(FluidityError::InvalidDataAccount.into());
+    }
+
+    // a TVL log's history is kept
+    if data_account.data_len() >= TVL_LOG_SPACE as usize {
+        msg!("TVL data account already holds a TVL log");
+        return Err(FluidityError::InvalidTvlLog.into());
+    }
+
+    close_account(data_account, destination)
+}
+
 // initialise a data account derived from PDA that stores valid token pairs
 fn init_data(
     accounts: &[AccountInfo],
     program_id: &Pubkey,
     seed: String, lamports: u64,
-    space: u64, bump: u8
+    space: u64,
 ) -> ProgramResult {
     let accounts_iter = &mut accounts.iter();
 
+    // checked against the registry admin role in process
+    let _config_account = next_account_info(accounts_iter)?;
+    let payer = next_account_info(accounts_iter)?;
     let system_program = next_account_info(accounts_iter)?;
+    let data_account = next_account_info(accounts_iter)?;
+    let token_mint = next_account_info(accounts_iter)?;
+    let fluid_mint = next_account_info(accounts_iter)?;
+    let pda = next_account_info(accounts_iter)?;
+    let solend_program = next_account_info(accounts_iter)?;
+    let reserve_info = next_account_info(accounts_iter)?;
+    let obligation_info = next_account_info(accounts_iter)?;
+    let collateral_info = next_account_info(accounts_iter)?;
+
+    create_data(
+        program_id,
+        &DataAccounts {
+            payer,
+            system_program,
+            data_account,
+            token_mint,
+            fluid_mint,
+            pda,
+            solend_program,
+            reserve: reserve_info,
+            obligation: obligation_info,
+            collateral: collateral_info,
+        },
+        &seed,
+        lamports,
+        space,
+    )?;
+
+    // the fluid mint was made outside of register_token
+    check_fluid_mint(token_mint, fluid_mint, pda)
+}
+
+// create a token pair's fluid mint, data account and solend obligation, all
+// owned or controlled by its pda - must be run by the registry admin
+fn register_token(accounts: &[AccountInfo], program_id: &Pubkey, seed: String) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    // checked against the registry admin role in process
+    let _config_account = next_account_info(accounts_iter)?;
     let payer = next_account_info(accounts_iter)?;
+    let system_program = next_account_info(accounts_iter)?;
     let data_account = next_account_info(accounts_iter)?;
     let token_mint = next_account_info(accounts_iter)?;
     let fluid_mint = next_account_info(accounts_iter)?;
     let pda = next_account_info(accounts_iter)?;
+    let solend_program = next_account_info(accounts_iter)?;
+    let reserve_info = next_account_info(accounts_iter)?;
+    let obligation_info = next_account_info(accounts_iter)?;
+    let collateral_info = next_account_info(accounts_iter)?;
+    let lending_market_info = next_account_info(accounts_iter)?;
+    let token_program = next_account_info(accounts_iter)?;
+    let clock_info = next_account_info(accounts_iter)?;
+    let rent_info = next_account_info(accounts_iter)?;
 
-    // check payout authority
-    if !(payer.is_signer && payer.key ==
-         &Pubkey::from_str(AUTHORITY).unwrap()) {
-        panic!("bad init authority!");
+    if token_program.key != &spl_token::id() {
+        msg!("bad token program");
+        return Err(FluidityError::InvalidTokenProgram.into());
     }
 
-    let pda_seed = format!("FLU:{}_OBLIGATION", seed);
-    let data_seed = format!("FLU:{}_DATA", seed);
+    // the fluid mint is created at the address derived from the pda
+    let mint_seed = fluid_mint_seed(&seed);
+    if fluid_mint.key != &Pubkey::create_with_seed(pda.key, &mint_seed, token_program.key)? {
+        msg!("bad fluid mint");
+        return Err(FluidityError::InvalidFluidMint.into());
+    }
+
+    let rent = Rent::from_account_info(rent_info)?;
+
+    // checks the pda, reserve and obligation, and records them
+    let fluidity_data = create_data(
+        program_id,
+        &DataAccounts {
+            payer,
+            system_program,
+            data_account,
+            token_mint,
+            fluid_mint,
+            pda,
+            solend_program,
+            reserve: reserve_info,
+            obligation: obligation_info,
+            collateral: collateral_info,
+        },
+        &seed,
+        rent.minimum_balance(FluidityData::LEN),
+        FluidityData::LEN as u64,
+    )?;
+    check_recorded(lending_market_info, &fluidity_data.lending_market, FluidityError::InvalidLendingMarket)?;
+
+    let pda_seed = obligation_authority_seed(&seed);
+    let pda_seeds: &[&[u8]] = &[pda_seed.as_bytes(), &[fluidity_data.bump]];
+
+    // create the fluid mint with the base token's decimals, minted and
+    // frozen by the pda
+    let decimals = spl_token::state::Mint::unpack(&token_mint.data.borrow())?.decimals;
+    invoke_signed(
+        &system_instruction::create_account_with_seed(
+            payer.key,
+            fluid_mint.key,
+            pda.key,
+            &mint_seed,
+            rent.minimum_balance(spl_token::state::Mint::LEN),
+            spl_token::state::Mint::LEN as u64,
+            token_program.key,
+        ),
+        &[payer.clone(), fluid_mint.clone(), pda.clone(), system_program.clone()],
+        &[pda_seeds],
+    )?;
+    invoke(
+        &spl_token::instruction::initialize_mint(
+            token_program.key,
+            fluid_mint.key,
+            pda.key,
+            Some(pda.key),
+            decimals,
+        )?,
+        &[fluid_mint.clone(), rent_info.clone(), token_program.clone()],
+    )?;
+
+    create_obligation(
+        &ObligationAccounts {
+            payer,
+            solend_program,
+            system_program,
+            obligation: obligation_info,
+            lending_market: lending_market_info,
+            pda,
+            clock: clock_info,
+            rent: rent_info,
+            token_program,
+        },
+        pda_seeds,
+        rent.minimum_balance(Obligation::LEN),
+        Obligation::LEN as u64,
+    )
+}
+
+// accounts used to create a token's data account
+struct DataAccounts<'b, 'a> {
+    payer: &'b AccountInfo<'a>,
+    system_program: &'b AccountInfo<'a>,
+    data_account: &'b AccountInfo<'a>,
+    token_mint: &'b AccountInfo<'a>,
+    fluid_mint: &'b AccountInfo<'a>,
+    pda: &'b AccountInfo<'a>,
+    solend_program: &'b AccountInfo<'a>,
+    reserve: &'b AccountInfo<'a>,
+    obligation: &'b AccountInfo<'a>,
+    // the pda's collateral token account
+    collateral: &'b AccountInfo<'a>,
+}
+
+// check a token pair's pda and solend accounts, and create its data account
+// recording them, returning the record
+fn create_data(
+    program_id: &Pubkey,
+    accounts: &DataAccounts,
+    seed: &str,
+    lamports: u64,
+    space: u64,
+) -> Result<FluidityData, ProgramError> {
+    let DataAccounts {
+        payer, system_program, data_account, token_mint, fluid_mint, pda, reserve: reserve_info, ..
+    } = *accounts;
+
+    // refuse to write over a registered token
+    if data_account.data_len() == FluidityData::LEN &&
+        FluidityData::unpack_unchecked(&data_account.data.borrow())?.is_initialized() {
+        msg!("data account already initialised");
+        return Err(ProgramError::AccountAlreadyInitialized);
+    }
+
+    if space != FluidityData::LEN as u64 {
+        msg!("data account needs {} bytes", FluidityData::LEN);
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    let fluidity_data = registration_record(program_id, accounts, seed)?;
+    let pda_seed = obligation_authority_seed(seed);
 
     // create the acccount
     invoke_signed(
@@ -640,60 +1357,2731 @@ fn init_data(
             payer.key,
             data_account.key,
             pda.key,
-            &data_seed,
+            &data_seed(seed),
             lamports,
             space,
             program_id,
         ),
         &[payer.clone(), data_account.clone(), pda.clone(), system_program.clone()],
-        &[&[&pda_seed.as_bytes(), &[bump]]],
+        &[&[&pda_seed.as_bytes(), &[fluidity_data.bump]]],
     )?;
 
     // borrow the data and write
     let mut data = data_account.try_borrow_mut_data()?;
-    FluidityData{
+    FluidityData::pack(fluidity_data.clone(), &mut data)?;
+
+    FluidityEvent::TokenRegistered {
         token_mint: *token_mint.key,
         fluid_mint: *fluid_mint.key,
-        pda: *pda.key,
-    }.serialize(&mut &mut data[..])?;
+        reserve: *reserve_info.key,
+        collateral_mint: fluidity_data.collateral_mint,
+    }.emit();
 
-    Ok(())
+    Ok(fluidity_data)
 }
 
-// check that base mint, fluid mint, and pda match those specified in the data account
-// before doing this, check that the data account is valid!
-fn check_mints_and_pda(data_account: &AccountInfo, token_mint: Pubkey, fluid_mint: Pubkey, pda: Pubkey) {
-    // get fluidity data
-    let data = data_account.try_borrow_data().unwrap();
-    let fluidity_data = FluidityData::deserialize(&mut &data[..]).unwrap();
+// check a token pair's pda and solend accounts, returning the record of them
+// with the default settings
+fn registration_record(
+    program_id: &Pubkey,
+    accounts: &DataAccounts,
+    seed: &str,
+) -> Result<FluidityData, ProgramError> {
+    let DataAccounts {
+        token_mint, fluid_mint, pda, solend_program,
+        reserve: reserve_info, obligation: obligation_info, collateral: collateral_info, ..
+    } = *accounts;
+
+    // the pda has to be the canonical address, whose bump is stored for
+    // signing with it later
+    let (pda_address, bump) = find_obligation_authority_address(program_id, seed);
+    if pda.key != &pda_address {
+        msg!("bad pda");
+        return Err(FluidityError::InvalidPda.into());
+    }
 
-    // check that mints and pda are consistent
-    if (fluidity_data.token_mint, fluidity_data.fluid_mint, fluidity_data.pda) !=
-        (token_mint, fluid_mint, pda) {
-            panic!("bad mint or pda");
+    // check solend contract
+    solend::check_program(solend_program)?;
+
+    // the reserve has to belong to solend and hold the base token
+    if reserve_info.owner != solend_program.key {
+        msg!("bad reserve owner");
+        return Err(FluidityError::InvalidReserve.into());
+    }
+    let reserve = Reserve::unpack(&reserve_info.data.borrow())?;
+    if &reserve.liquidity.mint_pubkey != token_mint.key {
+        msg!("bad reserve liquidity mint");
+        return Err(FluidityError::InvalidTokenMint.into());
+    }
+
+    // the obligation is created by init_solend_obligation with its address
+    // derived from the pda and the lending market
+    if obligation_info.key != &Pubkey::create_with_seed(
+        pda.key,
+        &reserve.lending_market.to_string()[0..32],
+        solend_program.key,
+    )? {
+        msg!("bad obligation");
+        return Err(FluidityError::InvalidObligation.into());
+    }
+
+    // the collateral account has to be a token account of the reserve's
+    // collateral owned by the pda
+    if collateral_info.owner != &spl_token::id() {
+        msg!("bad collateral account");
+        return Err(FluidityError::InvalidCollateralAccount.into());
+    }
+    let collateral = spl_token::state::Account::unpack(&collateral_info.data.borrow())?;
+    if collateral.mint != reserve.collateral.mint_pubkey {
+        msg!("bad collateral mint");
+        return Err(FluidityError::InvalidCollateralMint.into());
+    }
+    if &collateral.owner != pda.key {
+        msg!("bad collateral ownership!");
+        return Err(FluidityError::InvalidCollateralOwner.into());
     }
+
+    Ok(FluidityData::new(InitFluidityDataParams {
+        token_mint: *token_mint.key,
+        fluid_mint: *fluid_mint.key,
+        pda: *pda.key,
+        bump,
+        reserve: *reserve_info.key,
+        lending_market: reserve.lending_market,
+        obligation: *obligation_info.key,
+        collateral_mint: reserve.collateral.mint_pubkey,
+        liquidity_supply: reserve.liquidity.supply_pubkey,
+        collateral_supply: reserve.collateral.supply_pubkey,
+        collateral: *collateral_info.key,
+    }))
 }
 
-pub fn process(program_id: &Pubkey, accounts: &[AccountInfo], input: &[u8]) -> ProgramResult {
-    let instruction = FluidityInstruction::try_from_slice(input)?;
-    match instruction {
-        FluidityInstruction::Wrap (amount, seed, bump) => {
-            wrap(&accounts, program_id, amount, seed, bump)
-        }
-        FluidityInstruction::Unwrap (amount, seed, bump) => {
-            unwrap(&accounts, program_id, amount, seed, bump)
-        }
-        FluidityInstruction::Payout (amount, seed, bump) => {
-            payout(&accounts, amount, seed, bump)
-        }
-        FluidityInstruction::InitSolendObligation(obligation_lamports, obligation_size, seed, bump) => {
-            init_solend_obligation(&accounts, obligation_lamports, obligation_size, seed, bump)
+// upgrade a token's data account from a legacy layout - must be run by the
+// admin. Without realloc an account too small for the packed layout can't
+// grow, so its upgraded record is staged and the account closed, and once
+// the closed account is gone running this again re-creates it from the stage
+fn migrate_data(
+    accounts: &[AccountInfo],
+    program_id: &Pubkey,
+    seed: String,
+    layout: LegacyLayout,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    // checked against the admin role in process
+    let _config_account = next_account_info(accounts_iter)?;
+    let payer = next_account_info(accounts_iter)?;
+    let system_program = next_account_info(accounts_iter)?;
+    let data_account = next_account_info(accounts_iter)?;
+    let staging_account = next_account_info(accounts_iter)?;
+    let token_mint = next_account_info(accounts_iter)?;
+    let fluid_mint = next_account_info(accounts_iter)?;
+    let pda = next_account_info(accounts_iter)?;
+    let solend_program = next_account_info(accounts_iter)?;
+    let reserve_info = next_account_info(accounts_iter)?;
+    let obligation_info = next_account_info(accounts_iter)?;
+    let collateral_info = next_account_info(accounts_iter)?;
+
+    if data_account.key != &find_data_address(program_id, &seed)? {
+        msg!("bad data account");
+        return Err(FluidityError::InvalidDataAccount.into());
+    }
+    if staging_account.key != &find_staging_address(program_id, &seed)? {
+        msg!("bad staging account");
+        return Err(FluidityError::InvalidDataAccount.into());
+    }
+
+    let rent = Rent::get()?;
+    let (_, bump) = find_obligation_authority_address(program_id, &seed);
+    let pda_seed = obligation_authority_seed(&seed);
+    let pda_seeds: &[&[u8]] = &[pda_seed.as_bytes(), &[bump]];
+
+    // the second run, re-creating the closed account from the stage
+    if data_account.lamports() == 0 {
+        if staging_account.owner != program_id {
+            msg!("nothing staged");
+            return Err(FluidityError::InvalidDataAccount.into());
         }
-        FluidityInstruction::LogTVL => {
-            log_tvl(&accounts, program_id)
+        let fluidity_data = FluidityData::unpack(&staging_account.data.borrow())?;
+
+        invoke_signed(
+            &system_instruction::create_account_with_seed(
+                payer.key,
+                data_account.key,
+                pda.key,
+                &data_seed(&seed),
+                rent.minimum_balance(FluidityData::LEN),
+                FluidityData::LEN as u64,
+                program_id,
+            ),
+            &[payer.clone(), data_account.clone(), pda.clone(), system_program.clone()],
+            &[pda_seeds],
+        )?;
+        FluidityData::pack(fluidity_data, &mut data_account.try_borrow_mut_data()?)?;
+
+        return close_account(staging_account, payer);
+    }
+
+    if data_account.owner != program_id {
+        msg!("bad data account");
+        return Err(FluidityError::InvalidDataAccount.into());
+    }
+
+    // the solend accounts are checked as init_data would
+    let legacy = read_legacy_data(layout, &data_account.data.borrow())?;
+    let fluidity_data = registration_record(
+        program_id,
+        &DataAccounts {
+            payer,
+            system_program,
+            data_account,
+            token_mint,
+            fluid_mint,
+            pda,
+            solend_program,
+            reserve: reserve_info,
+            obligation: obligation_info,
+            collateral: collateral_info,
+        },
+        &seed,
+    )?;
+
+    if legacy.token_mint != fluidity_data.token_mint {
+        msg!("bad token mint");
+        return Err(FluidityError::InvalidTokenMint.into());
+    }
+    if legacy.fluid_mint != fluidity_data.fluid_mint {
+        msg!("bad fluid mint");
+        return Err(FluidityError::InvalidFluidMint.into());
+    }
+    if legacy.pda != fluidity_data.pda {
+        msg!("bad pda");
+        return Err(FluidityError::InvalidPda.into());
+    }
+    check_fluid_mint(token_mint, fluid_mint, pda)?;
+
+    // an account of the packed layout's size is upgraded in place, topping
+    // up its rent
+    if data_account.data_len() == FluidityData::LEN {
+        let top_up = rent.minimum_balance(FluidityData::LEN).saturating_sub(data_account.lamports());
+        if top_up > 0 {
+            invoke(
+                &system_instruction::transfer(payer.key, data_account.key, top_up),
+                &[payer.clone(), data_account.clone(), system_program.clone()],
+            )?;
         }
-        FluidityInstruction::InitData(seed, lamports, space, bump) => {
-            init_data(&accounts, program_id, seed, lamports, space, bump)
+
+        return FluidityData::pack(fluidity_data, &mut data_account.try_borrow_mut_data()?);
+    }
+
+    // otherwise stage the record and close the account, for the second run
+    invoke_signed(
+        &system_instruction::create_account_with_seed(
+            payer.key,
+            staging_account.key,
+            pda.key,
+            &staging_seed(&seed),
+            rent.minimum_balance(FluidityData::LEN),
+            FluidityData::LEN as u64,
+            program_id,
+        ),
+        &[payer.clone(), staging_account.clone(), pda.clone(), system_program.clone()],
+        &[pda_seeds],
+    )?;
+    FluidityData::pack(fluidity_data, &mut staging_account.try_borrow_mut_data()?)?;
+
+    close_account(data_account, payer)
+}
+
+// retire a token pair once its fluid tokens are gone, redeeming what's left
+// of its deposits to a treasury and closing its data account, refunding the
+// admin - must be run by the admin
+fn deregister_token(accounts: &[AccountInfo], program_id: &Pubkey, seed: String) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    // checked against the admin role in process
+    let _config_account = next_account_info(accounts_iter)?;
+    let admin = next_account_info(accounts_iter)?;
+    let data_account = next_account_info(accounts_iter)?;
+    let token_program = next_account_info(accounts_iter)?;
+    let token_mint = next_account_info(accounts_iter)?;
+    let fluid_mint = next_account_info(accounts_iter)?;
+    let pda = next_account_info(accounts_iter)?;
+    let treasury = next_account_info(accounts_iter)?;
+
+    // the rest are passed through to the token's lending adapter
+    let adapter_accounts = accounts_iter.as_slice();
+
+    let fluidity_data = load_fluidity_data(program_id, &seed, data_account, pda)?;
+    check_mints_and_pda(data_account, *token_mint.key, *fluid_mint.key, *pda.key)?;
+
+    if token_program.key != &spl_token::id() {
+        msg!("bad token program");
+        return Err(FluidityError::InvalidTokenProgram.into());
+    }
+
+    // holders would be left with nothing to unwrap into
+    let fluid_supply = spl_token::state::Mint::unpack(&fluid_mint.data.borrow())?.supply;
+    if fluid_supply > DEREGISTER_DUST_AMOUNT {
+        msg!("{} fluid tokens are still held", fluid_supply);
+        return Err(FluidityError::FluidTokensOutstanding.into());
+    }
+
+    let pda_seed = obligation_authority_seed(&seed);
+    let collateral_amount = adapter(fluidity_data.adapter).withdraw_all(
+        &fluidity_data,
+        adapter_accounts,
+        &UserAccounts {
+            token_program,
+            pda,
+            pda_seeds: &[pda_seed.as_bytes(), &[fluidity_data.bump]],
+            token_account: treasury,
+            owner: pda,
+        },
+    )?;
+
+    FluidityEvent::TokenDeregistered {
+        token_mint: *token_mint.key,
+        fluid_mint: *fluid_mint.key,
+        collateral_amount,
+        fluid_supply,
+    }.emit();
+
+    close_account(data_account, admin)
+}
+
+// create the config account and set its admin - must be run by the deploy authority
+fn init_config(accounts: &[AccountInfo], program_id: &Pubkey, admin: Pubkey) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let config_account = next_account_info(accounts_iter)?;
+    let payer = next_account_info(accounts_iter)?;
+    let system_program = next_account_info(accounts_iter)?;
+
+    // check init authority
+    if !(payer.is_signer && payer.key ==
+         &Pubkey::from_str(AUTHORITY).unwrap()) {
+        msg!("bad init authority!");
+        return Err(FluidityError::InvalidAuthority.into());
+    }
+
+    // the config account lives at the canonical address only
+    let (config_address, bump) = find_config_address(program_id);
+    if config_account.key != &config_address {
+        msg!("bad config account");
+        return Err(FluidityError::InvalidConfigAccount.into());
+    }
+
+    // create the account, failing if it already exists
+    invoke_signed(
+        &system_instruction::create_account(
+            payer.key,
+            config_account.key,
+            Rent::get()?.minimum_balance(CONFIG_SPACE as usize),
+            CONFIG_SPACE,
+            program_id,
+        ),
+        &[payer.clone(), config_account.clone(), system_program.clone()],
+        &[&[CONFIG_SEED.as_bytes(), &[bump]]],
+    )?;
+
+    let mut data = config_account.try_borrow_mut_data()?;
+    FluidityConfig::new(admin, bump).serialize(&mut &mut data[..])?;
+
+    Ok(())
+}
+
+// nominate a new admin, who has to accept before taking over - must be run by admin
+fn propose_admin(accounts: &[AccountInfo], program_id: &Pubkey, new_admin: Pubkey) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let config_account = next_account_info(accounts_iter)?;
+
+    let mut config = load_config(program_id, config_account)?;
+    config.pending_admin = new_admin;
+
+    let mut data = config_account.try_borrow_mut_data()?;
+    config.serialize(&mut &mut data[..])?;
+
+    Ok(())
+}
+
+// give a key one of the roles, replacing the previous holder - must be run by admin
+fn grant_role(accounts: &[AccountInfo], program_id: &Pubkey, role: Role, key: Pubkey) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let config_account = next_account_info(accounts_iter)?;
+
+    let mut config = load_config(program_id, config_account)?;
+    *config.role_mut(role)? = key;
+
+    let mut data = config_account.try_borrow_mut_data()?;
+    config.serialize(&mut &mut data[..])?;
+
+    Ok(())
+}
+
+// take one of the roles away from its holder - must be run by admin
+fn revoke_role(accounts: &[AccountInfo], program_id: &Pubkey, role: Role) -> ProgramResult {
+    grant_role(accounts, program_id, role, Pubkey::default())
+}
+
+// take over as admin - must be run by the nominated admin
+fn accept_admin(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let config_account = next_account_info(accounts_iter)?;
+    let pending_admin = next_account_info(accounts_iter)?;
+
+    let mut config = load_config(program_id, config_account)?;
+
+    if config.pending_admin == Pubkey::default() ||
+        !(pending_admin.is_signer && pending_admin.key == &config.pending_admin) {
+        msg!("bad pending admin!");
+        return Err(FluidityError::InvalidPendingAdmin.into());
+    }
+
+    config.admin = config.pending_admin;
+    config.pending_admin = Pubkey::default();
+
+    let mut data = config_account.try_borrow_mut_data()?;
+    config.serialize(&mut &mut data[..])?;
+
+    Ok(())
+}
+
+// set the pause flags for every token, or for the token named by seed - the
+// guardian can only set flags, clearing them has to be done by the admin
+fn set_paused(
+    accounts: &[AccountInfo],
+    program_id: &Pubkey,
+    seed: Option<String>,
+    paused: bool,
+    unwrap_paused: bool,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let config_account = next_account_info(accounts_iter)?;
+    let _authority = next_account_info(accounts_iter)?;
+
+    let mut config = load_config(program_id, config_account)?;
+
+    // the named token's data account, checking it's derived from the
+    // token's pda
+    let data_account = match seed {
+        None => None,
+        Some(seed) => {
+            let data_account = next_account_info(accounts_iter)?;
+            if data_account.key != &find_data_address(program_id, &seed)? {
+                msg!("bad data account");
+                return Err(FluidityError::InvalidDataAccount.into());
+            }
+
+            Some(data_account)
+        }
+    };
+
+    let unpausing = match data_account {
+        None => (config.paused && !paused) || (config.unwrap_paused && !unwrap_paused),
+        Some(data_account) => {
+            let fluidity_data = FluidityData::unpack(&data_account.try_borrow_data()?)?;
+            (fluidity_data.paused && !paused) || (fluidity_data.unwrap_paused && !unwrap_paused)
+        }
+    };
+
+    // the admin can pause as well as unpause
+    if unpausing {
+        check_role(program_id, accounts, Role::Admin)?;
+    } else {
+        check_role(program_id, accounts, Role::Guardian)
+            .or_else(|_| check_role(program_id, accounts, Role::Admin))?;
+    }
+
+    match data_account {
+        None => {
+            config.paused = paused;
+            config.unwrap_paused = unwrap_paused;
+
+            let mut data = config_account.try_borrow_mut_data()?;
+            config.serialize(&mut &mut data[..])?;
+        }
+        Some(data_account) => {
+            let mut data = data_account.try_borrow_mut_data()?;
+            let mut fluidity_data = FluidityData::unpack(&data)?;
+
+            fluidity_data.paused = paused;
+            fluidity_data.unwrap_paused = unwrap_paused;
+            FluidityData::pack(fluidity_data, &mut data)?;
+        }
+    }
+
+    Ok(())
+}
+
+// set the share of the prize pool a token's payouts can take, and how they
+// are split between sender and receiver - must be run by admin
+fn set_payout_params(
+    accounts: &[AccountInfo],
+    program_id: &Pubkey,
+    seed: String,
+    prize_pool_cap_bps: u16,
+    sender_share_bps: u16,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    // checked against the admin role in process
+    let _config_account = next_account_info(accounts_iter)?;
+    let _admin = next_account_info(accounts_iter)?;
+    let data_account = next_account_info(accounts_iter)?;
+
+    if prize_pool_cap_bps > MAX_BPS || sender_share_bps > MAX_BPS {
+        msg!("basis points above {}", MAX_BPS);
+        return Err(FluidityError::InvalidBasisPoints.into());
+    }
+
+    // check that data account is derived from the token's pda
+    if data_account.key != &find_data_address(program_id, &seed)? {
+        msg!("bad data account");
+        return Err(FluidityError::InvalidDataAccount.into());
+    }
+
+    let mut data = data_account.try_borrow_mut_data()?;
+    let mut fluidity_data = FluidityData::unpack(&data)?;
+
+    fluidity_data.prize_pool_cap_bps = prize_pool_cap_bps;
+    fluidity_data.sender_share_bps = sender_share_bps;
+    FluidityData::pack(fluidity_data, &mut data)?;
+
+    Ok(())
+}
+
+// close the receipt of a transfer too old to be paid out again, returning its
+// rent - must be run by the payout operator
+fn close_receipt(accounts: &[AccountInfo], program_id: &Pubkey, transfer_hash: [u8; 32]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    // checked against the payout operator role in process
+    let _config_account = next_account_info(accounts_iter)?;
+    let _payer = next_account_info(accounts_iter)?;
+    let receipt_account = next_account_info(accounts_iter)?;
+    let destination = next_account_info(accounts_iter)?;
+    let clock_info = next_account_info(accounts_iter)?;
+
+    if receipt_account.key != &find_receipt_address(program_id, &transfer_hash).0 ||
+        receipt_account.owner != program_id {
+        msg!("bad receipt account");
+        return Err(FluidityError::InvalidReceipt.into());
+    }
+
+    let receipt = PayoutReceipt::deserialize(&mut &receipt_account.data.borrow()[..])?;
+
+    // payouts for the transfer are refused once the receipt can be closed,
+    // though a transfer whose slot was given by the operator could be paid
+    // again with a later one until its receipt is older than that
+    let lifetime = if receipt.slot_logged {
+        RECEIPT_LIFETIME_SLOTS
+    } else {
+        UNLOGGED_RECEIPT_LIFETIME_SLOTS
+    };
+    let clock = Clock::from_account_info(clock_info)?;
+    if clock.slot <= receipt.transfer_slot.saturating_add(lifetime) {
+        msg!("receipt still in use");
+        return Err(FluidityError::ReceiptNotExpired.into());
+    }
+
+    close_account(receipt_account, destination)
+}
+
+// close an account owned by the program, zeroing its data and moving its
+// lamports to the destination
+fn close_account(account: &AccountInfo, destination: &AccountInfo) -> ProgramResult {
+    let lamports = account.lamports();
+    **destination.try_borrow_mut_lamports()? = destination.lamports()
+        .checked_add(lamports)
+        .ok_or(LendingError::MathOverflow)?;
+    **account.try_borrow_mut_lamports()? = 0;
+    account.try_borrow_mut_data()?.fill(0);
+
+    Ok(())
+}
+
+// create the receipt of a winning transfer, failing if it has already been
+// paid out or is too old to be paid
+#[allow(clippy::too_many_arguments)]
+fn create_receipt<'a>(
+    program_id: &Pubkey,
+    payer: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    receipt_account: &AccountInfo<'a>,
+    current_slot: Slot,
+    transfer_hash: &[u8; 32],
+    receipt: &PayoutReceipt,
+) -> ProgramResult {
+    if receipt.transfer_slot > current_slot ||
+        current_slot - receipt.transfer_slot > RECEIPT_LIFETIME_SLOTS {
+        msg!("transfer at slot {} can't be paid out", receipt.transfer_slot);
+        return Err(FluidityError::PayoutExpired.into());
+    }
+
+    let (receipt_address, bump) = find_receipt_address(program_id, transfer_hash);
+    if receipt_account.key != &receipt_address {
+        msg!("bad receipt account");
+        return Err(FluidityError::InvalidReceipt.into());
+    }
+
+    if receipt_account.owner == program_id {
+        msg!("transfer already paid out");
+        return Err(FluidityError::PayoutAlreadyPaid.into());
+    }
+
+    let receipt_seeds: &[&[u8]] = &[RECEIPT_SEED.as_bytes(), transfer_hash, &[bump]];
+    let required_lamports = Rent::get()?.minimum_balance(RECEIPT_SPACE as usize);
+
+    if receipt_account.lamports() == 0 {
+        invoke_signed(
+            &system_instruction::create_account(
+                payer.key,
+                receipt_account.key,
+                required_lamports,
+                RECEIPT_SPACE,
+                program_id,
+            ),
+            &[payer.clone(), receipt_account.clone(), system_program.clone()],
+            &[receipt_seeds],
+        )?;
+    } else {
+        // someone sent lamports to the address first, so create_account would
+        // fail - top it up and take it over instead
+        let top_up = required_lamports.saturating_sub(receipt_account.lamports());
+        if top_up > 0 {
+            invoke(
+                &system_instruction::transfer(payer.key, receipt_account.key, top_up),
+                &[payer.clone(), receipt_account.clone(), system_program.clone()],
+            )?;
+        }
+        invoke_signed(
+            &system_instruction::allocate(receipt_account.key, RECEIPT_SPACE),
+            &[receipt_account.clone(), system_program.clone()],
+            &[receipt_seeds],
+        )?;
+        invoke_signed(
+            &system_instruction::assign(receipt_account.key, program_id),
+            &[receipt_account.clone(), system_program.clone()],
+            &[receipt_seeds],
+        )?;
+    }
+
+    let mut data = receipt_account.try_borrow_mut_data()?;
+    receipt.serialize(&mut &mut data[..])?;
+
+    Ok(())
+}
+
+// set the odds table of a token's drawn payouts - must be run by admin
+fn set_draw_tiers(
+    accounts: &[AccountInfo],
+    program_id: &Pubkey,
+    seed: String,
+    draw_tiers: [DrawTier; DRAW_TIERS],
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    // checked against the admin role in process
+    let _config_account = next_account_info(accounts_iter)?;
+    let _admin = next_account_info(accounts_iter)?;
+    let data_account = next_account_info(accounts_iter)?;
+
+    // check that data account is derived from the token's pda
+    if data_account.key != &find_data_address(program_id, &seed)? {
+        msg!("bad data account");
+        return Err(FluidityError::InvalidDataAccount.into());
+    }
+
+    let mut data = data_account.try_borrow_mut_data()?;
+    let mut fluidity_data = FluidityData::unpack(&data)?;
+
+    fluidity_data.draw_tiers = draw_tiers;
+    FluidityData::pack(fluidity_data, &mut data)?;
+
+    Ok(())
+}
+
+// set up a token's transfer log in an account the registry admin created
+// with TRANSFER_LOG_SPACE, owned by this program, and record it in the
+// token's data account - must be run by the registry admin
+fn init_transfer_log(accounts: &[AccountInfo], program_id: &Pubkey, seed: String) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    // checked against the registry admin role in process
+    let _config_account = next_account_info(accounts_iter)?;
+    let _payer = next_account_info(accounts_iter)?;
+    let transfer_log = next_account_info(accounts_iter)?;
+    let pda_account = next_account_info(accounts_iter)?;
+    let fluidity_data_account = next_account_info(accounts_iter)?;
+
+    // check data account and pda
+    let mut fluidity_data = load_fluidity_data(program_id, &seed, fluidity_data_account, pda_account)?;
+    if fluidity_data.transfer_log != Pubkey::default() {
+        msg!("token already has a transfer log");
+        return Err(ProgramError::AccountAlreadyInitialized);
+    }
+
+    if transfer_log.owner != program_id || transfer_log.data_len() != TRANSFER_LOG_SPACE as usize {
+        msg!("bad transfer log");
+        return Err(FluidityError::InvalidTransferLog.into());
+    }
+
+    // a log set up for another token has its fluid mint
+    let mut data = transfer_log.try_borrow_mut_data()?;
+    if TransferLog::deserialize(&mut &data[..])?.fluid_mint != Pubkey::default() {
+        msg!("transfer log already in use");
+        return Err(ProgramError::AccountAlreadyInitialized);
+    }
+    TransferLog {
+        fluid_mint: fluidity_data.fluid_mint,
+        next_index: 0,
+    }.serialize(&mut &mut data[..])?;
+
+    fluidity_data.transfer_log = *transfer_log.key;
+    FluidityData::pack(fluidity_data, &mut fluidity_data_account.try_borrow_mut_data()?)?;
+
+    Ok(())
+}
+
+// transfer fluid tokens, logging the transfer in the token's transfer log so
+// that payouts can reference it
+fn transfer_fluid(accounts: &[AccountInfo], program_id: &Pubkey, amount: u64, seed: String) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let fluidity_data_account = next_account_info(accounts_iter)?;
+    let pda_account = next_account_info(accounts_iter)?;
+    let token_program = next_account_info(accounts_iter)?;
+    let sender = next_account_info(accounts_iter)?;
+    let receiver = next_account_info(accounts_iter)?;
+    let owner = next_account_info(accounts_iter)?;
+    let transfer_log = next_account_info(accounts_iter)?;
+    let clock_info = next_account_info(accounts_iter)?;
+
+    let fluidity_data = load_fluidity_data(program_id, &seed, fluidity_data_account, pda_account)?;
+
+    // empty and self transfers would only fill the log
+    if amount == 0 || sender.key == receiver.key {
+        msg!("transfer is empty or to its sender");
+        return Err(FluidityError::InvalidTransfer.into());
+    }
+
+    // only a real transfer can be logged
+    if token_program.key != &spl_token::id() {
+        msg!("bad token program");
+        return Err(FluidityError::InvalidTokenProgram.into());
+    }
+
+    // the token program checks that the receiver has the same mint
+    let sender_account = spl_token::state::Account::unpack(&sender.data.borrow())?;
+    if sender_account.mint != fluidity_data.fluid_mint {
+        msg!("bad fluid mint");
+        return Err(FluidityError::InvalidFluidMint.into());
+    }
+
+    check_transfer_log(program_id, &fluidity_data, transfer_log)?;
+
+    invoke(
+        &spl_token::instruction::transfer(
+            token_program.key,
+            sender.key,
+            receiver.key,
+            owner.key,
+            &[],
+            amount,
+        )?,
+        &[sender.clone(), receiver.clone(), owner.clone(), token_program.clone()],
+    )?;
+
+    let clock = Clock::from_account_info(clock_info)?;
+    let index = append_transfer(
+        &mut transfer_log.try_borrow_mut_data()?,
+        &TransferLogEntry {
+            sender: *sender.key,
+            receiver: *receiver.key,
+            amount,
+            slot: clock.slot,
+        },
+    )?;
+
+    msg!("logged transfer {}", index);
+
+    Ok(())
+}
+
+// check that a transfer log is the one recorded for the token
+fn check_transfer_log(
+    program_id: &Pubkey,
+    fluidity_data: &FluidityData,
+    transfer_log: &AccountInfo,
+) -> ProgramResult {
+    if transfer_log.owner != program_id {
+        msg!("bad transfer log");
+        return Err(FluidityError::InvalidTransferLog.into());
+    }
+
+    check_recorded(transfer_log, &fluidity_data.transfer_log, FluidityError::InvalidTransferLog)
+}
+
+// read the transfer at an index of a token's transfer log, checking the
+// payout accounts are its sender and receiver
+fn load_logged_transfer(
+    program_id: &Pubkey,
+    fluidity_data: &FluidityData,
+    transfer_log: &AccountInfo,
+    index: u64,
+    sender: &AccountInfo,
+    receiver: &AccountInfo,
+) -> Result<TransferLogEntry, ProgramError> {
+    check_transfer_log(program_id, fluidity_data, transfer_log)?;
+    let entry = read_transfer(&transfer_log.data.borrow(), index)?;
+    if &entry.sender != sender.key || &entry.receiver != receiver.key {
+        msg!("payout accounts aren't the transfer's sender and receiver");
+        return Err(FluidityError::InvalidTransferAccounts.into());
+    }
+
+    Ok(entry)
+}
+
+// write a transfer over the oldest entry of a transfer log, returning its index.
+// Fails if the slot's limit of transfers has already been logged
+fn append_transfer(data: &mut [u8], entry: &TransferLogEntry) -> Result<u64, ProgramError> {
+    let mut header = TransferLog::deserialize(&mut &data[..])?;
+    let index = header.next_index;
+
+    if index >= TRANSFER_LOG_SLOT_LIMIT &&
+        read_transfer(data, index - TRANSFER_LOG_SLOT_LIMIT)?.slot >= entry.slot {
+        msg!("transfer log is full for slot {}", entry.slot);
+        return Err(FluidityError::TransferLogFull.into());
+    }
+
+    let offset = transfer_log_offset(index);
+    entry.serialize(&mut &mut data[offset..offset + TRANSFER_LOG_ENTRY_SPACE])?;
+
+    header.next_index = index.checked_add(1).ok_or(LendingError::MathOverflow)?;
+    header.serialize(&mut &mut *data)?;
+
+    Ok(index)
+}
+
+// read the transfer at an index of a transfer log, failing if it hasn't been
+// logged yet or has been overwritten
+fn read_transfer(data: &[u8], index: u64) -> Result<TransferLogEntry, ProgramError> {
+    let header = TransferLog::deserialize(&mut &*data)?;
+    if index >= header.next_index || header.next_index - index > TRANSFER_LOG_CAPACITY {
+        msg!("transfer {} is not in the log", index);
+        return Err(FluidityError::TransferNotLogged.into());
+    }
+
+    let offset = transfer_log_offset(index);
+    Ok(TransferLogEntry::deserialize(&mut &data[offset..offset + TRANSFER_LOG_ENTRY_SPACE])?)
+}
+
+// offset of the entry an index is written to in a transfer log
+fn transfer_log_offset(index: u64) -> usize {
+    TRANSFER_LOG_HEADER_SPACE + (index % TRANSFER_LOG_CAPACITY) as usize * TRANSFER_LOG_ENTRY_SPACE
+}
+
+// find the hash of the first slot after a slot in the slot hashes sysvar,
+// read in place as it's too large to deserialise - a u64 length followed by
+// (slot, hash) entries with the newest slot first. Fails if no slot after it
+// has been hashed yet, or if the sysvar no longer goes back to the slot, as
+// the first slot after it can't be told apart from a later one
+fn find_next_slot_hash(slot_hashes: &AccountInfo, slot: Slot) -> Result<[u8; 32], ProgramError> {
+    if slot_hashes.key != &sysvar::slot_hashes::id() {
+        msg!("bad slot hashes sysvar");
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    const ENTRY_LEN: usize = 40;
+
+    let data = slot_hashes.try_borrow_data()?;
+    let len = data.get(..8)
+        .map(|len| u64::from_le_bytes(len.try_into().unwrap()) as usize)
+        .ok_or(ProgramError::InvalidAccountData)?;
+    let entries = data.get(8..8 + len * ENTRY_LEN).ok_or(ProgramError::InvalidAccountData)?;
+    let entry_slot = |i: usize| {
+        u64::from_le_bytes(entries[i * ENTRY_LEN..i * ENTRY_LEN + 8].try_into().unwrap())
+    };
+
+    // binary search for the number of slots after it, with slots in
+    // descending order
+    let (mut low, mut high) = (0, len);
+    while low < high {
+        let mid = (low + high) / 2;
+        if entry_slot(mid) > slot {
+            low = mid + 1;
+        } else {
+            high = mid;
+        }
+    }
+
+    if low == 0 {
+        msg!("no slot after {} has been hashed yet", slot);
+        return Err(FluidityError::SlotHashNotFound.into());
+    }
+    if low == len {
+        msg!("slot {} is too old to find the slot after it", slot);
+        return Err(FluidityError::SlotHashNotFound.into());
+    }
+
+    let next = low - 1;
+    Ok(entries[next * ENTRY_LEN + 8..(next + 1) * ENTRY_LEN].try_into().unwrap())
+}
+
+// draw the prize of a transfer from its slot's hash and the transfer itself,
+// each tier taking its own eight bytes of the draw and the largest winning
+// prize being paid, so anyone can check a prize from the chain
+fn draw_prize(
+    slot_hash: &[u8; 32],
+    transfer_hash: &[u8; 32],
+    sender: &Pubkey,
+    receiver: &Pubkey,
+    draw_tiers: &[DrawTier; DRAW_TIERS],
+) -> u64 {
+    let draw = hashv(&[slot_hash, transfer_hash, sender.as_ref(), receiver.as_ref()]).to_bytes();
+
+    draw_tiers.iter()
+        .zip(draw.chunks_exact(8))
+        .filter(|(tier, _)| tier.odds != 0)
+        .filter(|(tier, bytes)| {
+            u64::from_le_bytes((*bytes).try_into().unwrap()) % tier.odds as u64 == 0
+        })
+        .map(|(tier, _)| tier.prize)
+        .max()
+        .unwrap_or(0)
+}
+
+// what a token's deposits are worth beyond its fluid supply, in fluid tokens,
+// failing if they're worth less
+pub(crate) fn prize_pool(deposited: &Deposited, fluid_mint: &spl_token::state::Mint) -> Result<u64, ProgramError> {
+    // the base and fluid tokens can have different decimals
+    let scale = |decimals: u8| 10u64.checked_pow(decimals as u32).ok_or(LendingError::MathOverflow);
+    let value = Decimal::from(deposited.value)
+        .try_mul(scale(fluid_mint.decimals)?)?
+        .try_div(scale(deposited.decimals)?)?;
+
+    let supply = Decimal::from(fluid_mint.supply);
+    if value < supply {
+        msg!("deposits worth {} are short of the fluid supply {}", value, supply);
+        return Err(FluidityError::PrizePoolEmpty.into());
+    }
+
+    value.try_sub(supply)?.try_floor_u64()
+}
+
+// take a share of a value given in basis points
+fn apply_bps(value: u64, bps: u16) -> Result<u64, ProgramError> {
+    value
+        .checked_mul(bps as u64)
+        .map(|value| value / MAX_BPS as u64)
+        .ok_or_else(|| LendingError::MathOverflow.into())
+}
+
+// split a payout between sender and receiver, returning the sender's and the
+// receiver's prizes
+fn split_prize(amount: u64, sender_share_bps: u16) -> Result<(u64, u64), ProgramError> {
+    let sender_prize = apply_bps(amount, sender_share_bps)?;
+    let receiver_share_bps = MAX_BPS.checked_sub(sender_share_bps).ok_or(FluidityError::InvalidBasisPoints)?;
+    let receiver_prize = apply_bps(amount, receiver_share_bps)?;
+
+    Ok((sender_prize, receiver_prize))
+}
+
+// mint a prize of fluid tokens to a winner, signed by the obligation authority
+fn mint_prize<'a>(
+    token_program: &AccountInfo<'a>,
+    fluidity_mint: &AccountInfo<'a>,
+    destination: &AccountInfo<'a>,
+    pda_account: &AccountInfo<'a>,
+    pda_seeds: &[&[u8]],
+    amount: u64,
+) -> ProgramResult {
+    if amount == 0 {
+        return Ok(());
+    }
+
+    invoke_signed(
+        &spl_token::instruction::mint_to(
+            token_program.key,
+            fluidity_mint.key,
+            destination.key,
+            pda_account.key,
+            &[pda_account.key],
+            amount,
+        )?,
+        &[fluidity_mint.clone(), destination.clone(), pda_account.clone(), token_program.clone()],
+        &[pda_seeds],
+    )
+}
+
+// check that an account passed through to solend is the one recorded for the token
+pub(crate) fn check_recorded(account: &AccountInfo, recorded: &Pubkey, error: FluidityError) -> ProgramResult {
+    if account.key != recorded {
+        msg!("{}", error);
+        return Err(error.into());
+    }
+
+    Ok(())
+}
+
+// read a token's data account, checking that it and the pda are derived from
+// the token name
+fn load_fluidity_data(
+    program_id: &Pubkey,
+    seed: &str,
+    data_account: &AccountInfo,
+    pda_account: &AccountInfo,
+) -> Result<FluidityData, ProgramError> {
+    if data_account.owner != program_id ||
+        data_account.key != &Pubkey::create_with_seed(pda_account.key, &data_seed(seed), program_id)? {
+        msg!("bad data account");
+        return Err(FluidityError::InvalidDataAccount.into());
+    }
+
+    let data = data_account.try_borrow_data()?;
+    let fluidity_data = FluidityData::unpack(&data)?;
+
+    // the bump was found by init_data, so this only matches the canonical pda
+    if pda_account.key != &Pubkey::create_program_address(
+        &[obligation_authority_seed(seed).as_bytes(), &[fluidity_data.bump]],
+        program_id,
+    )? {
+        msg!("bad pda");
+        return Err(FluidityError::InvalidPda.into());
+    }
+
+    Ok(fluidity_data)
+}
+
+// check that wrapping and payouts, or unwrapping, aren't paused for every
+// token or for the token stored in the data account
+fn check_not_paused(
+    program_id: &Pubkey,
+    config_account: &AccountInfo,
+    data_account: &AccountInfo,
+    unwrapping: bool,
+) -> ProgramResult {
+    let config = load_config(program_id, config_account)?;
+
+    let data = data_account.try_borrow_data()?;
+    let fluidity_data = FluidityData::unpack(&data)?;
+
+    if unwrapping {
+        if config.unwrap_paused || fluidity_data.unwrap_paused {
+            msg!("unwrapping is paused");
+            return Err(FluidityError::UnwrapPaused.into());
+        }
+    } else if config.paused || fluidity_data.paused {
+        msg!("program is paused");
+        return Err(FluidityError::Paused.into());
+    }
+
+    Ok(())
+}
+
+// read the config account, checking that it is the one created by init_config
+fn load_config(program_id: &Pubkey, config_account: &AccountInfo) -> Result<FluidityConfig, ProgramError> {
+    if config_account.owner != program_id {
+        msg!("bad config account");
+        return Err(FluidityError::InvalidConfigAccount.into());
+    }
+
+    let data = config_account.try_borrow_data()?;
+    let config = FluidityConfig::deserialize(&mut &data[..])?;
+
+    if config_account.key !=
+        &Pubkey::create_program_address(&[CONFIG_SEED.as_bytes(), &[config.bump]], program_id)? {
+        msg!("bad config account");
+        return Err(FluidityError::InvalidConfigAccount.into());
+    }
+
+    Ok(config)
+}
+
+// check that the holder of a role signed the transaction, expects the config
+// account and the signer as the first two accounts
+fn check_role(program_id: &Pubkey, accounts: &[AccountInfo], role: Role) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let config_account = next_account_info(accounts_iter)?;
+    let authority = next_account_info(accounts_iter)?;
+
+    let config = load_config(program_id, config_account)?;
+    let holder = config.role(role);
+
+    if holder == Pubkey::default() || !(authority.is_signer && authority.key == &holder) {
+        msg!("bad {:?} authority!", role);
+        return Err(FluidityError::InvalidAuthority.into());
+    }
+
+    Ok(())
+}
+
+// the role needed to run an instruction, if any
+fn required_role(instruction: &FluidityInstruction) -> Option<Role> {
+    match instruction {
+        FluidityInstruction::Payout(..) |
+        FluidityInstruction::DrawPayout(..) |
+        FluidityInstruction::BatchPayout(..) |
+        FluidityInstruction::CloseReceipt(..) |
+        FluidityInstruction::PayoutTransfer(..) => Some(Role::PayoutOperator),
+        FluidityInstruction::InitSolendObligation(..) |
+        FluidityInstruction::InitData(..) |
+        FluidityInstruction::RegisterToken(..) |
+        FluidityInstruction::InitTransferLog(..) => Some(Role::RegistryAdmin),
+        FluidityInstruction::ProposeAdmin(..) |
+        FluidityInstruction::SetPayoutParams(..) |
+        FluidityInstruction::SetDrawTiers(..) |
+        FluidityInstruction::MigrateData(..) |
+        FluidityInstruction::DeregisterToken(..) |
+        FluidityInstruction::CloseTvlData |
+        FluidityInstruction::GrantRole(..) |
+        FluidityInstruction::RevokeRole(..) => Some(Role::Admin),
+        // the guardian can pause, but unpausing needs the admin
+        FluidityInstruction::SetPaused(..) |
+        FluidityInstruction::Wrap(..) |
+        FluidityInstruction::Unwrap(..) |
+        FluidityInstruction::WrapMinOut(..) |
+        FluidityInstruction::UnwrapMinOut(..) |
+        FluidityInstruction::LogTVL(..) |
+        FluidityInstruction::TransferFluid(..) |
+        FluidityInstruction::InitConfig(..) |
+        FluidityInstruction::AcceptAdmin => None,
+    }
+}
+
+// check that base mint, fluid mint, and pda match those specified in the data account
+// before doing this, check that the data account is valid!
+fn check_mints_and_pda(
+    data_account: &AccountInfo,
+    token_mint: Pubkey,
+    fluid_mint: Pubkey,
+    pda: Pubkey,
+) -> ProgramResult {
+    // get fluidity data
+    let data = data_account.try_borrow_data()?;
+    let fluidity_data = FluidityData::unpack(&data)?;
+
+    // check that mints and pda are consistent
+    if fluidity_data.token_mint != token_mint {
+        msg!("bad token mint");
+        return Err(FluidityError::InvalidTokenMint.into());
+    }
+    if fluidity_data.fluid_mint != fluid_mint {
+        msg!("bad fluid mint");
+        return Err(FluidityError::InvalidFluidMint.into());
+    }
+    if fluidity_data.pda != pda {
+        msg!("bad pda");
+        return Err(FluidityError::InvalidPda.into());
+    }
+
+    Ok(())
+}
+
+// check a fluid mint created outside register_token is one wrap and unwrap
+// can use, minted by the pda with the base token's decimals - they mint and
+// burn 1:1 in base units, while the prize pool is scaled between the two
+fn check_fluid_mint(token_mint: &AccountInfo, fluid_mint: &AccountInfo, pda: &AccountInfo) -> ProgramResult {
+    if token_mint.owner != &spl_token::id() {
+        msg!("bad token mint");
+        return Err(FluidityError::InvalidTokenMint.into());
+    }
+    if fluid_mint.owner != &spl_token::id() {
+        msg!("bad fluid mint");
+        return Err(FluidityError::InvalidFluidMint.into());
+    }
+    let decimals = spl_token::state::Mint::unpack(&token_mint.data.borrow())?.decimals;
+    let mint = spl_token::state::Mint::unpack(&fluid_mint.data.borrow())?;
+
+    if mint.decimals != decimals {
+        msg!("fluid mint has {} decimals, the token {}", mint.decimals, decimals);
+        return Err(FluidityError::InvalidFluidMint.into());
+    }
+    if mint.mint_authority != COption::Some(*pda.key) {
+        msg!("fluid mint isn't minted by the pda");
+        return Err(FluidityError::InvalidFluidMint.into());
+    }
+
+    Ok(())
+}
+
+// get the amount held by a token account
+fn token_balance(token_account: &AccountInfo) -> Result<u64, ProgramError> {
+    Ok(spl_token::state::Account::unpack(&token_account.data.borrow())?.amount)
+}
+
+// check a token account's balance grew by at least min_out
+fn check_min_out(before: u64, after: u64, min_out: u64) -> ProgramResult {
+    let realised = after.saturating_sub(before);
+    if realised < min_out {
+        msg!("realised {}, below the minimum {}", realised, min_out);
+        return Err(FluidityError::SlippageExceeded.into());
+    }
+
+    Ok(())
+}
+
+pub fn process(program_id: &Pubkey, accounts: &[AccountInfo], input: &[u8]) -> ProgramResult {
+    let instruction = FluidityInstruction::try_from_slice(input)?;
+
+    // check the role needed to run the instruction
+    if let Some(role) = required_role(&instruction) {
+        check_role(program_id, accounts, role)?;
+    }
+
+    match instruction {
+        FluidityInstruction::Wrap (amount, seed) => {
+            wrap(&accounts, program_id, amount, 0, seed)
+        }
+        FluidityInstruction::Unwrap (amount, seed) => {
+            unwrap(&accounts, program_id, amount, 0, seed)
+        }
+        FluidityInstruction::WrapMinOut(amount, min_out, seed) => {
+            wrap(&accounts, program_id, amount, min_out, seed)
+        }
+        FluidityInstruction::UnwrapMinOut(amount, min_out, seed) => {
+            unwrap(&accounts, program_id, amount, min_out, seed)
+        }
+        FluidityInstruction::Payout (amount, seed, transfer_hash, transfer_slot) => {
+            payout(&accounts, program_id, PayoutSource::Amount(amount, transfer_hash, transfer_slot), seed)
+        }
+        FluidityInstruction::DrawPayout(seed, index) => {
+            payout(&accounts, program_id, PayoutSource::Draw(index), seed)
+        }
+        FluidityInstruction::InitSolendObligation(obligation_lamports, obligation_size, seed) => {
+            init_solend_obligation(&accounts, program_id, obligation_lamports, obligation_size, seed)
+        }
+        FluidityInstruction::LogTVL(seeds) => {
+            log_tvl(&accounts, program_id, seeds)
+        }
+        FluidityInstruction::InitData(seed, lamports, space) => {
+            init_data(&accounts, program_id, seed, lamports, space)
+        }
+        FluidityInstruction::InitConfig(admin) => {
+            init_config(&accounts, program_id, admin)
+        }
+        FluidityInstruction::ProposeAdmin(admin) => {
+            propose_admin(&accounts, program_id, admin)
+        }
+        FluidityInstruction::AcceptAdmin => {
+            accept_admin(&accounts, program_id)
+        }
+        FluidityInstruction::GrantRole(role, key) => {
+            grant_role(&accounts, program_id, role, key)
+        }
+        FluidityInstruction::RevokeRole(role) => {
+            revoke_role(&accounts, program_id, role)
+        }
+        FluidityInstruction::SetPaused(seed, paused, unwrap_paused) => {
+            set_paused(&accounts, program_id, seed, paused, unwrap_paused)
+        }
+        FluidityInstruction::SetPayoutParams(seed, prize_pool_cap_bps, sender_share_bps) => {
+            set_payout_params(&accounts, program_id, seed, prize_pool_cap_bps, sender_share_bps)
+        }
+        FluidityInstruction::BatchPayout(winners, seed) => {
+            batch_payout(&accounts, program_id, winners, seed)
+        }
+        FluidityInstruction::CloseReceipt(transfer_hash) => {
+            close_receipt(&accounts, program_id, transfer_hash)
+        }
+        FluidityInstruction::SetDrawTiers(seed, draw_tiers) => {
+            set_draw_tiers(&accounts, program_id, seed, draw_tiers)
+        }
+        FluidityInstruction::RegisterToken(seed) => {
+            register_token(&accounts, program_id, seed)
+        }
+        FluidityInstruction::InitTransferLog(seed) => {
+            init_transfer_log(&accounts, program_id, seed)
+        }
+        FluidityInstruction::TransferFluid(amount, seed) => {
+            transfer_fluid(&accounts, program_id, amount, seed)
+        }
+        FluidityInstruction::PayoutTransfer(amount, seed, index) => {
+            payout(&accounts, program_id, PayoutSource::Logged(amount, index), seed)
+        }
+        FluidityInstruction::MigrateData(seed, layout) => {
+            migrate_data(&accounts, program_id, seed, layout)
+        }
+        FluidityInstruction::DeregisterToken(seed) => {
+            deregister_token(&accounts, program_id, seed)
+        }
+        FluidityInstruction::CloseTvlData => {
+            close_tvl_data(&accounts, program_id)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        math::Decimal,
+        state::{ReserveCollateral, ReserveConfig, ReserveLiquidity, SLOTS_PER_YEAR},
+    };
+    use proptest::prelude::*;
+
+    fn config_data(program_id: &Pubkey, admin: Pubkey) -> (Pubkey, Vec<u8>) {
+        let (config_address, bump) = find_config_address(program_id);
+        let mut data = vec![0; CONFIG_SPACE as usize];
+        FluidityConfig::new(admin, bump).serialize(&mut &mut data[..]).unwrap();
+        (config_address, data)
+    }
+
+    // a token's packed data account, with the default settings
+    fn token_data(pda: Pubkey, bump: u8) -> Vec<u8> {
+        let mut data = vec![0; FluidityData::LEN];
+        FluidityData::pack(
+            FluidityData{
+                version: FLUIDITY_DATA_VERSION,
+                token_mint: Pubkey::new_unique(),
+                fluid_mint: Pubkey::new_unique(),
+                pda,
+                bump,
+                adapter: AdapterKind::Solend,
+                reserve: Pubkey::new_unique(),
+                lending_market: Pubkey::new_unique(),
+                obligation: Pubkey::new_unique(),
+                collateral_mint: Pubkey::new_unique(),
+                liquidity_supply: Pubkey::new_unique(),
+                collateral_supply: Pubkey::new_unique(),
+                collateral: Pubkey::new_unique(),
+                paused: false,
+                unwrap_paused: false,
+                prize_pool_cap_bps: DEFAULT_PRIZE_POOL_CAP_BPS,
+                sender_share_bps: DEFAULT_SENDER_SHARE_BPS,
+                draw_tiers: [DrawTier::default(); DRAW_TIERS],
+                transfer_log: Pubkey::default(),
+            },
+            &mut data,
+        ).unwrap();
+        data
+    }
+
+    fn run(program_id: &Pubkey, accounts: &[AccountInfo], instruction: FluidityInstruction) -> ProgramResult {
+        process(program_id, accounts, &instruction.try_to_vec().unwrap())
+    }
+
+    #[test]
+    fn test_admin_handover() {
+        let program_id = Pubkey::new_unique();
+        let admin_key = Pubkey::new_unique();
+        let new_admin_key = Pubkey::new_unique();
+        let (config_key, mut data) = config_data(&program_id, admin_key);
+        let mut lamports = 0;
+        let config_account = AccountInfo::new(
+            &config_key, false, true, &mut lamports, &mut data, &program_id, false, 0,
+        );
+
+        let mut admin_lamports = 0;
+        let admin = AccountInfo::new(
+            &admin_key, true, false, &mut admin_lamports, &mut [], &program_id, false, 0,
+        );
+        let mut new_admin_lamports = 0;
+        let new_admin = AccountInfo::new(
+            &new_admin_key, true, false, &mut new_admin_lamports, &mut [], &program_id, false, 0,
+        );
+
+        // only the current admin can nominate
+        assert_eq!(
+            run(&program_id, &[config_account.clone(), new_admin.clone()], FluidityInstruction::ProposeAdmin(new_admin_key)),
+            Err(FluidityError::InvalidAuthority.into())
+        );
+        run(&program_id, &[config_account.clone(), admin.clone()], FluidityInstruction::ProposeAdmin(new_admin_key)).unwrap();
+
+        // nomination doesn't change the admin until accepted
+        let config = load_config(&program_id, &config_account).unwrap();
+        assert_eq!(config.admin, admin_key);
+        assert_eq!(config.pending_admin, new_admin_key);
+
+        // only the nominated key can accept
+        assert_eq!(
+            run(&program_id, &[config_account.clone(), admin.clone()], FluidityInstruction::AcceptAdmin),
+            Err(FluidityError::InvalidPendingAdmin.into())
+        );
+        run(&program_id, &[config_account.clone(), new_admin.clone()], FluidityInstruction::AcceptAdmin).unwrap();
+
+        let config = load_config(&program_id, &config_account).unwrap();
+        assert_eq!(config.admin, new_admin_key);
+        assert_eq!(config.pending_admin, Pubkey::default());
+
+        // the old admin has lost its rights
+        let accounts = [config_account.clone(), admin.clone()];
+        assert_eq!(check_role(&program_id, &accounts, Role::Admin), Err(FluidityError::InvalidAuthority.into()));
+        let accounts = [config_account.clone(), new_admin.clone()];
+        assert_eq!(check_role(&program_id, &accounts, Role::Admin), Ok(()));
+
+        // the nomination cannot be accepted twice
+        assert_eq!(
+            run(&program_id, &[config_account, new_admin], FluidityInstruction::AcceptAdmin),
+            Err(FluidityError::InvalidPendingAdmin.into())
+        );
+    }
+
+    #[test]
+    fn test_grant_and_revoke_roles() {
+        let program_id = Pubkey::new_unique();
+        let admin_key = Pubkey::new_unique();
+        let operator_key = Pubkey::new_unique();
+        let (config_key, mut data) = config_data(&program_id, admin_key);
+        let mut lamports = 0;
+        let config_account = AccountInfo::new(
+            &config_key, false, true, &mut lamports, &mut data, &program_id, false, 0,
+        );
+
+        let mut admin_lamports = 0;
+        let admin = AccountInfo::new(
+            &admin_key, true, false, &mut admin_lamports, &mut [], &program_id, false, 0,
+        );
+        let mut operator_lamports = 0;
+        let operator = AccountInfo::new(
+            &operator_key, true, false, &mut operator_lamports, &mut [], &program_id, false, 0,
+        );
+        let operator_accounts = [config_account.clone(), operator.clone()];
+        let admin_accounts = [config_account.clone(), admin.clone()];
+
+        // nobody holds a role until it is granted, not even the admin
+        for role in &[Role::PayoutOperator, Role::RegistryAdmin, Role::Guardian] {
+            assert_eq!(check_role(&program_id, &admin_accounts, *role), Err(FluidityError::InvalidAuthority.into()));
+        }
+
+        // only the admin can grant roles
+        assert_eq!(
+            run(&program_id, &operator_accounts, FluidityInstruction::GrantRole(Role::PayoutOperator, operator_key)),
+            Err(FluidityError::InvalidAuthority.into())
+        );
+        run(&program_id, &admin_accounts, FluidityInstruction::GrantRole(Role::PayoutOperator, operator_key)).unwrap();
+
+        // the operator holds exactly the granted role
+        assert_eq!(check_role(&program_id, &operator_accounts, Role::PayoutOperator), Ok(()));
+        assert_eq!(check_role(&program_id, &operator_accounts, Role::RegistryAdmin), Err(FluidityError::InvalidAuthority.into()));
+        assert_eq!(check_role(&program_id, &operator_accounts, Role::Guardian), Err(FluidityError::InvalidAuthority.into()));
+        assert_eq!(check_role(&program_id, &operator_accounts, Role::Admin), Err(FluidityError::InvalidAuthority.into()));
+
+        // the role is checked before the instruction runs
+        assert_eq!(
+            run(&program_id, &operator_accounts, FluidityInstruction::InitData("USDC".to_string(), 0, 0)),
+            Err(FluidityError::InvalidAuthority.into())
+        );
+
+        // the admin role cannot be granted
+        assert_eq!(
+            run(&program_id, &admin_accounts, FluidityInstruction::GrantRole(Role::Admin, operator_key)),
+            Err(FluidityError::AdminNotGrantable.into())
+        );
+
+        run(&program_id, &admin_accounts, FluidityInstruction::RevokeRole(Role::PayoutOperator)).unwrap();
+        assert_eq!(check_role(&program_id, &operator_accounts, Role::PayoutOperator), Err(FluidityError::InvalidAuthority.into()));
+    }
+
+    #[test]
+    fn test_pause_and_unpause() {
+        let program_id = Pubkey::new_unique();
+        let admin_key = Pubkey::new_unique();
+        let guardian_key = Pubkey::new_unique();
+        let (config_key, mut data) = config_data(&program_id, admin_key);
+        let mut lamports = 0;
+        let config_account = AccountInfo::new(
+            &config_key, false, true, &mut lamports, &mut data, &program_id, false, 0,
+        );
+
+        let mut admin_lamports = 0;
+        let admin = AccountInfo::new(
+            &admin_key, true, false, &mut admin_lamports, &mut [], &program_id, false, 0,
+        );
+        let mut guardian_lamports = 0;
+        let guardian = AccountInfo::new(
+            &guardian_key, true, false, &mut guardian_lamports, &mut [], &program_id, false, 0,
+        );
+        let admin_accounts = [config_account.clone(), admin.clone()];
+        run(&program_id, &admin_accounts, FluidityInstruction::GrantRole(Role::Guardian, guardian_key)).unwrap();
+
+        // token data account derived from the token's pda
+        let seed = "USDC";
+        let data_key = find_data_address(&program_id, seed).unwrap();
+        let (pda, bump) = find_obligation_authority_address(&program_id, seed);
+        let mut token_data = token_data(pda, bump);
+        let mut data_lamports = 0;
+        let data_account = AccountInfo::new(
+            &data_key, false, true, &mut data_lamports, &mut token_data, &program_id, false, 0,
+        );
+
+        let guardian_accounts = [config_account.clone(), guardian.clone(), data_account.clone()];
+        let admin_accounts = [config_account.clone(), admin.clone(), data_account.clone()];
+
+        assert_eq!(check_not_paused(&program_id, &config_account, &data_account, false), Ok(()));
+
+        // the guardian blocks deposits but keeps exits open
+        run(&program_id, &guardian_accounts, FluidityInstruction::SetPaused(None, true, false)).unwrap();
+        assert_eq!(
+            check_not_paused(&program_id, &config_account, &data_account, false),
+            Err(FluidityError::Paused.into())
+        );
+        assert_eq!(check_not_paused(&program_id, &config_account, &data_account, true), Ok(()));
+
+        // but cannot unpause
+        assert_eq!(
+            run(&program_id, &guardian_accounts, FluidityInstruction::SetPaused(None, false, false)),
+            Err(FluidityError::InvalidAuthority.into())
+        );
+        run(&program_id, &admin_accounts, FluidityInstruction::SetPaused(None, false, false)).unwrap();
+        assert_eq!(check_not_paused(&program_id, &config_account, &data_account, false), Ok(()));
+
+        // pausing a single token
+        run(
+            &program_id,
+            &guardian_accounts,
+            FluidityInstruction::SetPaused(Some(seed.to_string()), false, true),
+        ).unwrap();
+        assert_eq!(check_not_paused(&program_id, &config_account, &data_account, false), Ok(()));
+        assert_eq!(
+            check_not_paused(&program_id, &config_account, &data_account, true),
+            Err(FluidityError::UnwrapPaused.into())
+        );
+
+        // a refused unpause leaves the flags as they were
+        assert_eq!(
+            run(
+                &program_id,
+                &guardian_accounts,
+                FluidityInstruction::SetPaused(Some(seed.to_string()), false, false),
+            ),
+            Err(FluidityError::InvalidAuthority.into())
+        );
+        assert_eq!(
+            check_not_paused(&program_id, &config_account, &data_account, true),
+            Err(FluidityError::UnwrapPaused.into())
+        );
+
+        // the data account has to belong to the named token
+        assert_eq!(
+            run(
+                &program_id,
+                &admin_accounts,
+                FluidityInstruction::SetPaused(Some("USDT".to_string()), false, false),
+            ),
+            Err(FluidityError::InvalidDataAccount.into())
+        );
+    }
+
+    #[test]
+    fn test_set_payout_params() {
+        let program_id = Pubkey::new_unique();
+        let admin_key = Pubkey::new_unique();
+        let (config_key, mut config) = config_data(&program_id, admin_key);
+        let mut config_lamports = 0;
+        let mut admin_lamports = 0;
+        let mut admin_data = vec![];
+        let config_account = AccountInfo::new(
+            &config_key, false, true, &mut config_lamports, &mut config, &program_id, false, 0,
+        );
+        let admin = AccountInfo::new(
+            &admin_key, true, false, &mut admin_lamports, &mut admin_data, &program_id, false, 0,
+        );
+
+        let seed = "USDC";
+        let data_key = find_data_address(&program_id, seed).unwrap();
+        let (pda, bump) = find_obligation_authority_address(&program_id, seed);
+        let mut token_data = token_data(pda, bump);
+        let mut data_lamports = 0;
+        let data_account = AccountInfo::new(
+            &data_key, false, true, &mut data_lamports, &mut token_data, &program_id, false, 0,
+        );
+        let accounts = [config_account.clone(), admin.clone(), data_account.clone()];
+
+        run(&program_id, &accounts, FluidityInstruction::SetPayoutParams(seed.to_string(), 7_000, 7_000))
+            .unwrap();
+        let fluidity_data = FluidityData::unpack(&data_account.data.borrow()).unwrap();
+        assert_eq!(fluidity_data.prize_pool_cap_bps, 7_000);
+        assert_eq!(fluidity_data.sender_share_bps, 7_000);
+
+        let draw_tiers = [DrawTier { odds: 1_000, prize: 50 }; DRAW_TIERS];
+        run(&program_id, &accounts, FluidityInstruction::SetDrawTiers(seed.to_string(), draw_tiers)).unwrap();
+        let fluidity_data = FluidityData::unpack(&data_account.data.borrow()).unwrap();
+        assert_eq!(fluidity_data.draw_tiers, draw_tiers);
+        assert_eq!(fluidity_data.prize_pool_cap_bps, 7_000);
+
+        assert_eq!(
+            run(&program_id, &accounts, FluidityInstruction::SetPayoutParams(seed.to_string(), 10_001, 7_000)),
+            Err(FluidityError::InvalidBasisPoints.into())
+        );
+        assert_eq!(
+            run(&program_id, &accounts, FluidityInstruction::SetPayoutParams("USDT".to_string(), 7_000, 7_000)),
+            Err(FluidityError::InvalidDataAccount.into())
+        );
+
+        // only the admin can change them
+        let other_key = Pubkey::new_unique();
+        let mut other_lamports = 0;
+        let mut other_data = vec![];
+        let other = AccountInfo::new(
+            &other_key, true, false, &mut other_lamports, &mut other_data, &program_id, false, 0,
+        );
+        assert_eq!(
+            run(
+                &program_id,
+                &[config_account, other, data_account],
+                FluidityInstruction::SetPayoutParams(seed.to_string(), 7_000, 7_000),
+            ),
+            Err(FluidityError::InvalidAuthority.into())
+        );
+    }
+
+    #[test]
+    fn test_create_receipt_checks() {
+        let program_id = Pubkey::new_unique();
+        let transfer_hash = [7; 32];
+        let receipt_key = find_receipt_address(&program_id, &transfer_hash).0;
+
+        let payer_key = Pubkey::new_unique();
+        let mut lamports = 0;
+        let payer = AccountInfo::new(
+            &payer_key, true, true, &mut lamports, &mut [], &program_id, false, 0,
+        );
+        let mut receipt_lamports = 1;
+        let mut receipt_data = vec![0; RECEIPT_SPACE as usize];
+        let receipt_account = AccountInfo::new(
+            &receipt_key, false, true, &mut receipt_lamports, &mut receipt_data, &program_id, false, 0,
+        );
+
+        let receipt = |transfer_slot| PayoutReceipt {
+            fluid_mint: Pubkey::new_unique(),
+            transfer_slot,
+            sender_prize: 80,
+            receiver_prize: 20,
+            slot_logged: false,
+        };
+        let create = |current_slot, transfer_hash: &[u8; 32], receipt: &PayoutReceipt| create_receipt(
+            &program_id, &payer, &payer, &receipt_account, current_slot, transfer_hash, receipt,
+        );
+
+        // transfers outside the payout window are refused
+        assert_eq!(
+            create(100, &transfer_hash, &receipt(101)),
+            Err(FluidityError::PayoutExpired.into())
+        );
+        assert_eq!(
+            create(RECEIPT_LIFETIME_SLOTS + 101, &transfer_hash, &receipt(100)),
+            Err(FluidityError::PayoutExpired.into())
+        );
+
+        // the receipt has to be derived from the transfer's hash
+        assert_eq!(
+            create(100, &[8; 32], &receipt(100)),
+            Err(FluidityError::InvalidReceipt.into())
+        );
+
+        // a receipt owned by the program means the transfer was paid
+        assert_eq!(
+            create(100, &transfer_hash, &receipt(100)),
+            Err(FluidityError::PayoutAlreadyPaid.into())
+        );
+    }
+
+    #[test]
+    fn test_close_receipt() {
+        let program_id = Pubkey::new_unique();
+        let admin_key = Pubkey::new_unique();
+        let operator_key = Pubkey::new_unique();
+        let (config_key, mut data) = config_data(&program_id, admin_key);
+        let mut lamports = 0;
+        let config_account = AccountInfo::new(
+            &config_key, false, true, &mut lamports, &mut data, &program_id, false, 0,
+        );
+        let mut admin_lamports = 0;
+        let admin = AccountInfo::new(
+            &admin_key, true, false, &mut admin_lamports, &mut [], &program_id, false, 0,
+        );
+        let mut operator_lamports = 0;
+        let operator = AccountInfo::new(
+            &operator_key, true, true, &mut operator_lamports, &mut [], &program_id, false, 0,
+        );
+        run(
+            &program_id,
+            &[config_account.clone(), admin],
+            FluidityInstruction::GrantRole(Role::PayoutOperator, operator_key),
+        ).unwrap();
+
+        let transfer_hash = [7; 32];
+        let receipt_key = find_receipt_address(&program_id, &transfer_hash).0;
+        let mut receipt_lamports = 500;
+        let mut receipt_data = vec![0; RECEIPT_SPACE as usize];
+        let receipt = |slot_logged| PayoutReceipt {
+            fluid_mint: Pubkey::new_unique(),
+            transfer_slot: 100,
+            sender_prize: 80,
+            receiver_prize: 20,
+            slot_logged,
+        };
+        receipt(true).serialize(&mut &mut receipt_data[..]).unwrap();
+        let receipt_account = AccountInfo::new(
+            &receipt_key, false, true, &mut receipt_lamports, &mut receipt_data, &program_id, false, 0,
+        );
+
+        let clock_key = sysvar::clock::id();
+        let mut clock_lamports = 0;
+        let mut clock_data = vec![0; Clock::size_of()];
+        let sysvar_owner = sysvar::id();
+        let clock_account = AccountInfo::new(
+            &clock_key, false, false, &mut clock_lamports, &mut clock_data, &sysvar_owner, false, 0,
+        );
+        let set_slot = |slot| Clock { slot, ..Clock::default() }.to_account_info(&mut clock_account.clone()).unwrap();
+
+        let accounts = [
+            config_account.clone(), operator.clone(), receipt_account.clone(),
+            operator.clone(), clock_account.clone(),
+        ];
+
+        // the receipt can't be closed while the transfer could be paid again
+        set_slot(100 + RECEIPT_LIFETIME_SLOTS);
+        assert_eq!(
+            run(&program_id, &accounts, FluidityInstruction::CloseReceipt(transfer_hash)),
+            Err(FluidityError::ReceiptNotExpired.into())
+        );
+
+        set_slot(101 + RECEIPT_LIFETIME_SLOTS);
+        assert_eq!(
+            run(&program_id, &accounts, FluidityInstruction::CloseReceipt([8; 32])),
+            Err(FluidityError::InvalidReceipt.into())
+        );
+
+        // nor for another lifetime, if the operator gave the transfer's slot
+        receipt(false).serialize(&mut &mut receipt_account.data.borrow_mut()[..]).unwrap();
+        assert_eq!(
+            run(&program_id, &accounts, FluidityInstruction::CloseReceipt(transfer_hash)),
+            Err(FluidityError::ReceiptNotExpired.into())
+        );
+        receipt(true).serialize(&mut &mut receipt_account.data.borrow_mut()[..]).unwrap();
+        run(&program_id, &accounts, FluidityInstruction::CloseReceipt(transfer_hash)).unwrap();
+        assert_eq!(operator.lamports(), 500);
+        assert_eq!(receipt_account.lamports(), 0);
+        assert!(receipt_account.data.borrow().iter().all(|byte| *byte == 0));
+
+        // a receipt as BatchPayout writes it, with the operator's slot
+        let batch_hash = [9; 32];
+        let batch_key = find_receipt_address(&program_id, &batch_hash).0;
+        let mut batch_lamports = 300;
+        let mut batch_data = vec![0; RECEIPT_SPACE as usize];
+        receipt(false).serialize(&mut &mut batch_data[..]).unwrap();
+        let batch_receipt = AccountInfo::new(
+            &batch_key, false, true, &mut batch_lamports, &mut batch_data, &program_id, false, 0,
+        );
+        let accounts = [
+            config_account.clone(), operator.clone(), batch_receipt.clone(),
+            operator.clone(), clock_account.clone(),
+        ];
+
+        set_slot(100 + UNLOGGED_RECEIPT_LIFETIME_SLOTS);
+        assert_eq!(
+            run(&program_id, &accounts, FluidityInstruction::CloseReceipt(batch_hash)),
+            Err(FluidityError::ReceiptNotExpired.into())
+        );
+        set_slot(101 + UNLOGGED_RECEIPT_LIFETIME_SLOTS);
+        run(&program_id, &accounts, FluidityInstruction::CloseReceipt(batch_hash)).unwrap();
+        assert_eq!(operator.lamports(), 800);
+        assert_eq!(batch_receipt.lamports(), 0);
+    }
+
+    #[test]
+    fn test_close_tvl_data() {
+        let program_id = Pubkey::new_unique();
+        let admin_key = Pubkey::new_unique();
+        let (config_key, mut data) = config_data(&program_id, admin_key);
+        let mut lamports = 0;
+        let config_account = AccountInfo::new(
+            &config_key, false, true, &mut lamports, &mut data, &program_id, false, 0,
+        );
+        let mut admin_lamports = 0;
+        let admin = AccountInfo::new(
+            &admin_key, true, true, &mut admin_lamports, &mut [], &program_id, false, 0,
+        );
+
+        let base_key = Pubkey::new_unique();
+        let mut base_lamports = 0;
+        let base = AccountInfo::new(
+            &base_key, false, false, &mut base_lamports, &mut [], &program_id, false, 0,
+        );
+
+        // the unversioned log_tvl's account, holding a single value
+        let data_key = Pubkey::create_with_seed(&base_key, TVL_DATA_SEED, &program_id).unwrap();
+        let mut data_lamports = 400;
+        let mut legacy_data = vec![7; 8];
+        let data_account = AccountInfo::new(
+            &data_key, false, true, &mut data_lamports, &mut legacy_data, &program_id, false, 0,
+        );
+
+        // a TVL log's history isn't closed
+        let log_key = Pubkey::create_with_seed(&admin_key, TVL_DATA_SEED, &program_id).unwrap();
+        let mut log_lamports = 400;
+        let mut log_data = vec![0; TVL_LOG_SPACE as usize];
+        let log = AccountInfo::new(
+            &log_key, false, true, &mut log_lamports, &mut log_data, &program_id, false, 0,
+        );
+        assert_eq!(
+            run(
+                &program_id,
+                &[config_account.clone(), admin.clone(), log, admin.clone(), admin.clone()],
+                FluidityInstruction::CloseTvlData,
+            ),
+            Err(FluidityError::InvalidTvlLog.into())
+        );
+
+        // nor is an account derived from another base
+        assert_eq!(
+            run(
+                &program_id,
+                &[config_account.clone(), admin.clone(), data_account.clone(), admin.clone(), admin.clone()],
+                FluidityInstruction::CloseTvlData,
+            ),
+            Err(FluidityError::InvalidDataAccount.into())
+        );
+
+        run(
+            &program_id,
+            &[config_account, admin.clone(), data_account.clone(), base, admin.clone()],
+            FluidityInstruction::CloseTvlData,
+        ).unwrap();
+        assert_eq!(admin.lamports(), 400);
+        assert_eq!(data_account.lamports(), 0);
+        assert!(data_account.data.borrow().iter().all(|byte| *byte == 0));
+    }
+
+    #[test]
+    fn test_find_next_slot_hash() {
+        let program_id = Pubkey::new_unique();
+
+        // newest slot first, with a gap at slot 7
+        let slots = [10u64, 9, 8, 6, 5];
+        let mut data = (slots.len() as u64).to_le_bytes().to_vec();
+        for slot in slots.iter() {
+            data.extend_from_slice(&slot.to_le_bytes());
+            data.extend_from_slice(&[*slot as u8; 32]);
+        }
+
+        let key = sysvar::slot_hashes::id();
+        let mut lamports = 0;
+        let slot_hashes = AccountInfo::new(
+            &key, false, false, &mut lamports, &mut data, &program_id, false, 0,
+        );
+        // the hash of the first slot after, skipping the gap
+        assert_eq!(find_next_slot_hash(&slot_hashes, 5), Ok([6; 32]));
+        assert_eq!(find_next_slot_hash(&slot_hashes, 6), Ok([8; 32]));
+        assert_eq!(find_next_slot_hash(&slot_hashes, 7), Ok([8; 32]));
+        assert_eq!(find_next_slot_hash(&slot_hashes, 9), Ok([10; 32]));
+
+        // nothing after the newest slot yet, and the slot after one older
+        // than the sysvar goes back can't be known
+        for slot in [10, 11, 4].iter() {
+            assert_eq!(
+                find_next_slot_hash(&slot_hashes, *slot),
+                Err(FluidityError::SlotHashNotFound.into())
+            );
+        }
+
+        // only the sysvar is read
+        let other_key = Pubkey::new_unique();
+        let mut other_lamports = 0;
+        let mut other_data = vec![0; 8];
+        let other = AccountInfo::new(
+            &other_key, false, false, &mut other_lamports, &mut other_data, &program_id, false, 0,
+        );
+        assert_eq!(find_next_slot_hash(&other, 5), Err(ProgramError::InvalidArgument));
+    }
+
+    #[test]
+    fn test_draw_prize() {
+        let slot_hash = [1; 32];
+        let transfer_hash = [2; 32];
+        // fixed keys, as unique keys depend on the order the tests run in
+        let sender = Pubkey::new_from_array([3; 32]);
+        let receiver = Pubkey::new_from_array([4; 32]);
+        let draw = |draw_tiers| draw_prize(&slot_hash, &transfer_hash, &sender, &receiver, &draw_tiers);
+
+        // disabled tiers never win, and one in one always does
+        assert_eq!(draw([DrawTier::default(); DRAW_TIERS]), 0);
+        let mut draw_tiers = [DrawTier::default(); DRAW_TIERS];
+        draw_tiers[0] = DrawTier { odds: 1, prize: 10 };
+        draw_tiers[3] = DrawTier { odds: 1, prize: 30 };
+        assert_eq!(draw(draw_tiers), 30);
+
+        // a rare tier almost never wins
+        draw_tiers[3] = DrawTier { odds: u32::MAX, prize: 30 };
+        assert_eq!(draw(draw_tiers), 10);
+
+        // the draw is deterministic, but depends on the transfer
+        let draw_tiers = [DrawTier { odds: 2, prize: 10 }; DRAW_TIERS];
+        assert_eq!(draw(draw_tiers), draw(draw_tiers));
+        let prizes: Vec<u64> = (0..32u8)
+            .map(|i| draw_prize(&slot_hash, &[i; 32], &sender, &receiver, &draw_tiers))
+            .collect();
+        assert!(prizes.contains(&0));
+        assert!(prizes.contains(&10));
+    }
+
+    #[test]
+    fn test_transfer_log() {
+        let fluid_mint = Pubkey::new_unique();
+        let mut data = vec![0; TRANSFER_LOG_SPACE as usize];
+        TransferLog { fluid_mint, next_index: 0 }.serialize(&mut &mut data[..]).unwrap();
+
+        let entry = |slot| TransferLogEntry {
+            sender: Pubkey::new_unique(),
+            receiver: Pubkey::new_unique(),
+            amount: 100,
+            slot,
+        };
+
+        // nothing has been logged yet
+        assert_eq!(read_transfer(&data, 0), Err(FluidityError::TransferNotLogged.into()));
+
+        let first = entry(1);
+        assert_eq!(append_transfer(&mut data, &first), Ok(0));
+        assert_eq!(read_transfer(&data, 0), Ok(first.clone()));
+        assert_eq!(read_transfer(&data, 1), Err(FluidityError::TransferNotLogged.into()));
+
+        // fill the log, the first entry is still there
+        for slot in 2..=TRANSFER_LOG_CAPACITY {
+            append_transfer(&mut data, &entry(slot)).unwrap();
+        }
+        assert_eq!(read_transfer(&data, 0), Ok(first));
+
+        // and is overwritten by the next
+        let next = entry(TRANSFER_LOG_CAPACITY + 1);
+        assert_eq!(append_transfer(&mut data, &next), Ok(TRANSFER_LOG_CAPACITY));
+        assert_eq!(read_transfer(&data, 0), Err(FluidityError::TransferNotLogged.into()));
+        assert_eq!(read_transfer(&data, TRANSFER_LOG_CAPACITY), Ok(next));
+        assert_eq!(read_transfer(&data, 1).unwrap().slot, 2);
+
+        // the header is untouched by the entries
+        let header = TransferLog::deserialize(&mut &data[..]).unwrap();
+        assert_eq!(header, TransferLog { fluid_mint, next_index: TRANSFER_LOG_CAPACITY + 1 });
+
+        // a slot only takes its limit of transfers
+        let slot = TRANSFER_LOG_CAPACITY + 2;
+        for _ in 0..TRANSFER_LOG_SLOT_LIMIT {
+            append_transfer(&mut data, &entry(slot)).unwrap();
+        }
+        assert_eq!(append_transfer(&mut data, &entry(slot)), Err(FluidityError::TransferLogFull.into()));
+        assert!(append_transfer(&mut data, &entry(slot + 1)).is_ok());
+    }
+
+    #[test]
+    fn test_init_transfer_log() {
+        let program_id = Pubkey::new_unique();
+        let admin_key = Pubkey::new_unique();
+        let (config_key, mut config) = config_data(&program_id, admin_key);
+        let mut config_lamports = 0;
+        let mut admin_lamports = 0;
+        let config_account = AccountInfo::new(
+            &config_key, false, true, &mut config_lamports, &mut config, &program_id, false, 0,
+        );
+        let admin = AccountInfo::new(
+            &admin_key, true, true, &mut admin_lamports, &mut [], &program_id, false, 0,
+        );
+        run(
+            &program_id,
+            &[config_account.clone(), admin.clone()],
+            FluidityInstruction::GrantRole(Role::RegistryAdmin, admin_key),
+        ).unwrap();
+
+        let seed = "USDC";
+        let data_key = find_data_address(&program_id, seed).unwrap();
+        let (pda_key, bump) = find_obligation_authority_address(&program_id, seed);
+        let mut data = token_data(pda_key, bump);
+        let mut data_lamports = 0;
+        let data_account = AccountInfo::new(
+            &data_key, false, true, &mut data_lamports, &mut data, &program_id, false, 0,
+        );
+        let mut pda_lamports = 0;
+        let pda = AccountInfo::new(
+            &pda_key, false, false, &mut pda_lamports, &mut [], &program_id, false, 0,
+        );
+
+        // the log is created by the registry admin, and has to be its size
+        let short_key = Pubkey::new_unique();
+        let mut short_data = vec![0; TRANSFER_LOG_SPACE as usize - 1];
+        let mut short_lamports = 0;
+        let short_log = AccountInfo::new(
+            &short_key, false, true, &mut short_lamports, &mut short_data, &program_id, false, 0,
+        );
+        let instruction = FluidityInstruction::InitTransferLog(seed.to_string());
+        let accounts = [config_account.clone(), admin.clone(), short_log.clone(), pda.clone(), data_account.clone()];
+        assert_eq!(run(&program_id, &accounts, instruction.clone()), Err(FluidityError::InvalidTransferLog.into()));
+
+        let log_key = Pubkey::new_unique();
+        let mut log_data = vec![0; TRANSFER_LOG_SPACE as usize];
+        let mut log_lamports = 0;
+        let transfer_log = AccountInfo::new(
+            &log_key, false, true, &mut log_lamports, &mut log_data, &program_id, false, 0,
+        );
+        let accounts = [config_account, admin, transfer_log.clone(), pda, data_account.clone()];
+        run(&program_id, &accounts, instruction.clone()).unwrap();
+
+        let fluidity_data = FluidityData::unpack(&data_account.data.borrow()).unwrap();
+        assert_eq!(fluidity_data.transfer_log, log_key);
+        let header = TransferLog::deserialize(&mut &transfer_log.data.borrow()[..]).unwrap();
+        assert_eq!(header, TransferLog { fluid_mint: fluidity_data.fluid_mint, next_index: 0 });
+
+        // only the recorded log can be written to, and it can't be replaced
+        assert_eq!(check_transfer_log(&program_id, &fluidity_data, &transfer_log), Ok(()));
+        assert_eq!(
+            check_transfer_log(&program_id, &fluidity_data, &short_log),
+            Err(FluidityError::InvalidTransferLog.into())
+        );
+        assert_eq!(run(&program_id, &accounts, instruction), Err(ProgramError::AccountAlreadyInitialized));
+    }
+
+    #[test]
+    fn test_split_prize() {
+        // the defaults are an 8:2 split of at most 80% of the pool
+        let available_prize_pool = apply_bps(1_000, DEFAULT_PRIZE_POOL_CAP_BPS).unwrap();
+        assert_eq!(available_prize_pool, 800);
+        assert_eq!(split_prize(100, DEFAULT_SENDER_SHARE_BPS), Ok((80, 20)));
+        assert_eq!(split_prize(available_prize_pool, DEFAULT_SENDER_SHARE_BPS), Ok((640, 160)));
+
+        assert_eq!(split_prize(1_000, 7_000), Ok((700, 300)));
+        assert_eq!(split_prize(1_000, 10_000), Ok((1_000, 0)));
+        assert_eq!(split_prize(1_000, 10_001), Err(FluidityError::InvalidBasisPoints.into()));
+        assert_eq!(apply_bps(1_000, 0), Ok(0));
+
+        // rounding never pays out more than the amount
+        let (sender_prize, receiver_prize) = split_prize(999, 3_333).unwrap();
+        assert!(sender_prize + receiver_prize <= 999);
+
+        assert_eq!(apply_bps(u64::MAX, 7_000), Err(LendingError::MathOverflow.into()));
+    }
+
+    #[test]
+    fn test_check_min_out() {
+        assert_eq!(check_min_out(100, 200, 100), Ok(()));
+        assert_eq!(check_min_out(100, 200, 0), Ok(()));
+        assert_eq!(check_min_out(100, 199, 100), Err(FluidityError::SlippageExceeded.into()));
+
+        // a balance that shrank realised nothing
+        assert_eq!(check_min_out(200, 100, 0), Ok(()));
+        assert_eq!(check_min_out(200, 100, 1), Err(FluidityError::SlippageExceeded.into()));
+    }
+
+    #[test]
+    fn test_prize_pool() {
+        let deposited = |value, decimals| Deposited { collateral_amount: 0, value, market_value: 0, decimals };
+        let fluid_mint = |supply, decimals| spl_token::state::Mint {
+            supply,
+            decimals,
+            is_initialized: true,
+            ..spl_token::state::Mint::default()
+        };
+
+        assert_eq!(prize_pool(&deposited(1_500, 6), &fluid_mint(1_000, 6)), Ok(500));
+        assert_eq!(prize_pool(&deposited(1_000, 6), &fluid_mint(1_000, 6)), Ok(0));
+
+        // a deficit leaves nothing to pay out, rather than underflowing
+        assert_eq!(
+            prize_pool(&deposited(999, 6), &fluid_mint(1_000, 6)),
+            Err(FluidityError::PrizePoolEmpty.into())
+        );
+
+        // the value is scaled to the fluid mint's decimals, rounding down
+        assert_eq!(prize_pool(&deposited(1_500_000_999, 9), &fluid_mint(1_000_000, 6)), Ok(500_000));
+        assert_eq!(prize_pool(&deposited(1_500, 6), &fluid_mint(1_000_000, 9)), Ok(500_000));
+        assert_eq!(
+            prize_pool(&deposited(1_000_000_000, 9), &fluid_mint(1_000_001, 6)),
+            Err(FluidityError::PrizePoolEmpty.into())
+        );
+
+        assert_eq!(
+            prize_pool(&deposited(1, 20), &fluid_mint(0, 6)),
+            Err(LendingError::MathOverflow.into())
+        );
+    }
+
+    #[test]
+    fn test_check_mints_and_pda() {
+        let program_id = Pubkey::new_unique();
+        let token_mint = Pubkey::new_unique();
+        let fluid_mint = Pubkey::new_unique();
+        let pda = Pubkey::new_unique();
+        let data_key = Pubkey::new_unique();
+
+        let mut data = vec![0; FluidityData::LEN];
+        let fluidity_data = FluidityData{
+            version: FLUIDITY_DATA_VERSION,
+            token_mint,
+            fluid_mint,
+            pda,
+            bump: 0,
+            adapter: AdapterKind::Solend,
+            reserve: Pubkey::new_unique(),
+            lending_market: Pubkey::new_unique(),
+            obligation: Pubkey::new_unique(),
+            collateral_mint: Pubkey::new_unique(),
+            liquidity_supply: Pubkey::new_unique(),
+            collateral_supply: Pubkey::new_unique(),
+            collateral: Pubkey::new_unique(),
+            paused: false,
+            unwrap_paused: false,
+            prize_pool_cap_bps: DEFAULT_PRIZE_POOL_CAP_BPS,
+            sender_share_bps: DEFAULT_SENDER_SHARE_BPS,
+            draw_tiers: [DrawTier::default(); DRAW_TIERS],
+            transfer_log: Pubkey::default(),
+        };
+        FluidityData::pack(fluidity_data, &mut data).unwrap();
+        let mut lamports = 0;
+        let data_account = AccountInfo::new(
+            &data_key, false, false, &mut lamports, &mut data, &program_id, false, 0,
+        );
+
+        let other = Pubkey::new_unique();
+        assert_eq!(check_mints_and_pda(&data_account, token_mint, fluid_mint, pda), Ok(()));
+        assert_eq!(
+            check_mints_and_pda(&data_account, other, fluid_mint, pda),
+            Err(FluidityError::InvalidTokenMint.into())
+        );
+        assert_eq!(
+            check_mints_and_pda(&data_account, token_mint, other, pda),
+            Err(FluidityError::InvalidFluidMint.into())
+        );
+        assert_eq!(
+            check_mints_and_pda(&data_account, token_mint, fluid_mint, other),
+            Err(FluidityError::InvalidPda.into())
+        );
+    }
+
+    #[test]
+    fn test_fluidity_data_layout() {
+        let program_id = Pubkey::new_unique();
+        let (pda, bump) = find_obligation_authority_address(&program_id, "USDC");
+        let mut data = token_data(pda, bump);
+
+        // packing what was unpacked gives back the same bytes
+        let mut fluidity_data = FluidityData::unpack(&data).unwrap();
+        fluidity_data.draw_tiers[3] = DrawTier { odds: 10, prize: 20 };
+        fluidity_data.unwrap_paused = true;
+        fluidity_data.transfer_log = Pubkey::new_unique();
+        let mut repacked = vec![0; FluidityData::LEN];
+        FluidityData::pack(fluidity_data.clone(), &mut repacked).unwrap();
+        assert_eq!(FluidityData::unpack(&repacked), Ok(fluidity_data.clone()));
+        FluidityData::pack(fluidity_data, &mut data).unwrap();
+        assert_eq!(repacked, data);
+
+        // a zeroed account hasn't been initialised
+        let key = Pubkey::new_unique();
+        let mut zeroed = vec![0; FluidityData::LEN];
+        let mut lamports = 0;
+        let zeroed_account = AccountInfo::new(
+            &key, false, false, &mut lamports, &mut zeroed, &program_id, false, 0,
+        );
+        assert_eq!(
+            check_mints_and_pda(&zeroed_account, key, key, pda),
+            Err(ProgramError::UninitializedAccount)
+        );
+
+        // layouts newer than the program and unknown adapters are refused
+        data[0] = FLUIDITY_DATA_VERSION + 1;
+        assert_eq!(FluidityData::unpack(&data), Err(ProgramError::InvalidAccountData));
+        data[0] = FLUIDITY_DATA_VERSION;
+        // the adapter follows the version, mints, pda and bump
+        data[1 + 32 * 3 + 1] = 1;
+        assert_eq!(FluidityData::unpack(&data), Err(ProgramError::InvalidAccountData));
+    }
+
+    #[test]
+    fn test_init_data_refuses_initialised() {
+        let program_id = Pubkey::new_unique();
+        let admin_key = Pubkey::new_unique();
+        let (config_key, mut config) = config_data(&program_id, admin_key);
+        let mut config_lamports = 0;
+        let mut admin_lamports = 0;
+        let config_account = AccountInfo::new(
+            &config_key, false, true, &mut config_lamports, &mut config, &program_id, false, 0,
+        );
+        let admin = AccountInfo::new(
+            &admin_key, true, true, &mut admin_lamports, &mut [], &program_id, false, 0,
+        );
+        run(
+            &program_id,
+            &[config_account.clone(), admin.clone()],
+            FluidityInstruction::GrantRole(Role::RegistryAdmin, admin_key),
+        ).unwrap();
+
+        let seed = "USDC";
+        let data_key = find_data_address(&program_id, seed).unwrap();
+        let (pda, bump) = find_obligation_authority_address(&program_id, seed);
+        let mut data = token_data(pda, bump);
+        let mut data_lamports = 0;
+        let data_account = AccountInfo::new(
+            &data_key, false, true, &mut data_lamports, &mut data, &program_id, false, 0,
+        );
+
+        // the rest of the accounts aren't read before the data account is
+        let mut accounts = vec![config_account, admin.clone(), admin.clone(), data_account];
+        accounts.extend(std::iter::repeat(admin).take(7));
+        let space = FluidityData::LEN as u64;
+
+        assert_eq!(
+            run(&program_id, &accounts, FluidityInstruction::InitData(seed.to_string(), 0, space)),
+            Err(ProgramError::AccountAlreadyInitialized)
+        );
+    }
+
+    #[test]
+    fn test_deregister_token_refuses_held_fluid_tokens() {
+        let program_id = Pubkey::new_unique();
+        let admin_key = Pubkey::new_unique();
+        let (config_key, mut config) = config_data(&program_id, admin_key);
+        let mut config_lamports = 0;
+        let mut admin_lamports = 0;
+        let config_account = AccountInfo::new(
+            &config_key, false, true, &mut config_lamports, &mut config, &program_id, false, 0,
+        );
+        let admin = AccountInfo::new(
+            &admin_key, true, true, &mut admin_lamports, &mut [], &program_id, false, 0,
+        );
+
+        let seed = "USDC";
+        let data_key = find_data_address(&program_id, seed).unwrap();
+        let (pda_key, bump) = find_obligation_authority_address(&program_id, seed);
+        let mut data = token_data(pda_key, bump);
+        let fluidity_data = FluidityData::unpack(&data).unwrap();
+        let mut data_lamports = 0;
+        let data_account = AccountInfo::new(
+            &data_key, false, true, &mut data_lamports, &mut data, &program_id, false, 0,
+        );
+
+        let token_program_key = spl_token::id();
+        let mut token_program_lamports = 0;
+        let token_program = AccountInfo::new(
+            &token_program_key, false, false, &mut token_program_lamports, &mut [], &program_id, true, 0,
+        );
+        let mut token_mint_lamports = 0;
+        let token_mint = AccountInfo::new(
+            &fluidity_data.token_mint, false, false, &mut token_mint_lamports, &mut [], &token_program_key, false, 0,
+        );
+        let mut fluid_mint_data = vec![0; spl_token::state::Mint::LEN];
+        let mut fluid_mint_lamports = 0;
+        let fluid_mint = AccountInfo::new(
+            &fluidity_data.fluid_mint, false, false, &mut fluid_mint_lamports, &mut fluid_mint_data,
+            &token_program_key, false, 0,
+        );
+        let mut pda_lamports = 0;
+        let pda = AccountInfo::new(
+            &pda_key, false, true, &mut pda_lamports, &mut [], &program_id, false, 0,
+        );
+
+        let accounts = [
+            config_account, admin.clone(), data_account, token_program, token_mint, fluid_mint.clone(), pda,
+            admin,
+        ];
+        let set_supply = |supply| spl_token::state::Mint::pack(
+            spl_token::state::Mint { supply, is_initialized: true, ..Default::default() },
+            &mut fluid_mint.data.borrow_mut(),
+        ).unwrap();
+
+        set_supply(DEREGISTER_DUST_AMOUNT + 1);
+        assert_eq!(
+            run(&program_id, &accounts, FluidityInstruction::DeregisterToken(seed.to_string())),
+            Err(FluidityError::FluidTokensOutstanding.into())
+        );
+
+        // dust is left behind, going on to the adapter's accounts
+        set_supply(DEREGISTER_DUST_AMOUNT);
+        assert_eq!(
+            run(&program_id, &accounts, FluidityInstruction::DeregisterToken(seed.to_string())),
+            Err(ProgramError::NotEnoughAccountKeys)
+        );
+    }
+
+    // runs a handler with placeholder accounts, a program that isn't
+    // spl-token standing in for the token program
+    fn run_with_bad_token_program(
+        program_id: &Pubkey,
+        len: usize,
+        token_program_index: usize,
+        handler: impl FnOnce(&[AccountInfo]) -> ProgramResult,
+    ) -> ProgramResult {
+        let key = Pubkey::new_unique();
+        let mut lamports = 0;
+        let mut data = vec![];
+        let account = AccountInfo::new(&key, true, true, &mut lamports, &mut data, program_id, false, 0);
+
+        let bad_program_key = Pubkey::new_unique();
+        let mut bad_program_lamports = 0;
+        let mut bad_program_data = vec![];
+        let bad_program = AccountInfo::new(
+            &bad_program_key, false, false, &mut bad_program_lamports, &mut bad_program_data, program_id, true, 0,
+        );
+
+        let mut accounts = vec![account; len];
+        accounts[token_program_index] = bad_program;
+        handler(&accounts)
+    }
+
+    #[test]
+    fn test_wrap_checks_token_program() {
+        let program_id = Pubkey::new_unique();
+        assert_eq!(
+            run_with_bad_token_program(&program_id, 10, 1, |accounts| {
+                wrap(accounts, &program_id, 100, 0, "USDC".to_string())
+            }),
+            Err(FluidityError::InvalidTokenProgram.into())
+        );
+    }
+
+    #[test]
+    fn test_unwrap_checks_token_program() {
+        let program_id = Pubkey::new_unique();
+        assert_eq!(
+            run_with_bad_token_program(&program_id, 10, 1, |accounts| {
+                unwrap(accounts, &program_id, 100, 0, "USDC".to_string())
+            }),
+            Err(FluidityError::InvalidTokenProgram.into())
+        );
+    }
+
+    #[test]
+    fn test_payout_checks_token_program() {
+        let program_id = Pubkey::new_unique();
+        assert_eq!(
+            run_with_bad_token_program(&program_id, 11, 2, |accounts| {
+                payout(accounts, &program_id, PayoutSource::Amount(100, [1; 32], 0), "USDC".to_string())
+            }),
+            Err(FluidityError::InvalidTokenProgram.into())
+        );
+    }
+
+    #[test]
+    fn test_batch_payout_checks_token_program() {
+        let program_id = Pubkey::new_unique();
+        assert_eq!(
+            run_with_bad_token_program(&program_id, 8, 2, |accounts| {
+                batch_payout(accounts, &program_id, vec![], "USDC".to_string())
+            }),
+            Err(FluidityError::InvalidTokenProgram.into())
+        );
+    }
+
+    #[test]
+    fn test_check_fluid_mint() {
+        let pda_key = Pubkey::new_unique();
+        let mut pda_lamports = 0;
+        let pda = AccountInfo::new(
+            &pda_key, false, false, &mut pda_lamports, &mut [], &pda_key, false, 0,
+        );
+
+        let token_program_key = spl_token::id();
+        let mint_data = |decimals, authority| {
+            let mut data = vec![0; spl_token::state::Mint::LEN];
+            spl_token::state::Mint::pack(
+                spl_token::state::Mint {
+                    mint_authority: COption::Some(authority),
+                    decimals,
+                    is_initialized: true,
+                    ..Default::default()
+                },
+                &mut data,
+            ).unwrap();
+            data
+        };
+
+        let token_mint_key = Pubkey::new_unique();
+        let mut token_mint_data = mint_data(6, Pubkey::new_unique());
+        let mut token_mint_lamports = 0;
+        let token_mint = AccountInfo::new(
+            &token_mint_key, false, false, &mut token_mint_lamports, &mut token_mint_data,
+            &token_program_key, false, 0,
+        );
+
+        let fluid_mint_key = Pubkey::new_unique();
+        let mut fluid_mint_data = mint_data(6, pda_key);
+        let mut fluid_mint_lamports = 0;
+        let fluid_mint = AccountInfo::new(
+            &fluid_mint_key, false, false, &mut fluid_mint_lamports, &mut fluid_mint_data,
+            &token_program_key, false, 0,
+        );
+        assert_eq!(check_fluid_mint(&token_mint, &fluid_mint, &pda), Ok(()));
+
+        // a fluid token with other decimals would be minted at the wrong scale
+        fluid_mint.data.borrow_mut().copy_from_slice(&mint_data(9, pda_key));
+        assert_eq!(
+            check_fluid_mint(&token_mint, &fluid_mint, &pda),
+            Err(FluidityError::InvalidFluidMint.into())
+        );
+
+        fluid_mint.data.borrow_mut().copy_from_slice(&mint_data(6, Pubkey::new_unique()));
+        assert_eq!(
+            check_fluid_mint(&token_mint, &fluid_mint, &pda),
+            Err(FluidityError::InvalidFluidMint.into())
+        );
+    }
+
+    #[test]
+    fn test_check_recorded() {
+        let program_id = Pubkey::new_unique();
+        let recorded = Pubkey::new_unique();
+        let other = Pubkey::new_unique();
+
+        let mut lamports = 0;
+        let mut data = vec![];
+        let account = AccountInfo::new(
+            &recorded, false, false, &mut lamports, &mut data, &program_id, false, 0,
+        );
+        assert_eq!(check_recorded(&account, &recorded, FluidityError::InvalidReserve), Ok(()));
+        assert_eq!(
+            check_recorded(&account, &other, FluidityError::InvalidReserve),
+            Err(FluidityError::InvalidReserve.into())
+        );
+    }
+
+    #[test]
+    fn test_load_fluidity_data_checks_pda() {
+        let program_id = Pubkey::new_unique();
+        let seed = "USDC";
+        let (pda, bump) = find_obligation_authority_address(&program_id, seed);
+
+        let fluidity_data = |pda| FluidityData{
+            version: FLUIDITY_DATA_VERSION,
+            token_mint: Pubkey::new_unique(),
+            fluid_mint: Pubkey::new_unique(),
+            pda,
+            bump,
+            adapter: AdapterKind::Solend,
+            reserve: Pubkey::new_unique(),
+            lending_market: Pubkey::new_unique(),
+            obligation: Pubkey::new_unique(),
+            collateral_mint: Pubkey::new_unique(),
+            liquidity_supply: Pubkey::new_unique(),
+            collateral_supply: Pubkey::new_unique(),
+            collateral: Pubkey::new_unique(),
+            paused: false,
+            unwrap_paused: false,
+            prize_pool_cap_bps: DEFAULT_PRIZE_POOL_CAP_BPS,
+            sender_share_bps: DEFAULT_SENDER_SHARE_BPS,
+            draw_tiers: [DrawTier::default(); DRAW_TIERS],
+            transfer_log: Pubkey::default(),
+        };
+
+        // the canonical pda and its data account
+        let data_key = find_data_address(&program_id, seed).unwrap();
+        let mut data = vec![0; FluidityData::LEN];
+        FluidityData::pack(fluidity_data(pda), &mut data).unwrap();
+        let mut lamports = 0;
+        let data_account = AccountInfo::new(
+            &data_key, false, false, &mut lamports, &mut data, &program_id, false, 0,
+        );
+        let mut pda_lamports = 0;
+        let pda_account = AccountInfo::new(
+            &pda, false, false, &mut pda_lamports, &mut [], &program_id, false, 0,
+        );
+        assert_eq!(
+            load_fluidity_data(&program_id, seed, &data_account, &pda_account).unwrap().bump,
+            bump
+        );
+
+        // the data account must match the token name
+        assert_eq!(
+            load_fluidity_data(&program_id, "USDT", &data_account, &pda_account),
+            Err(FluidityError::InvalidDataAccount.into())
+        );
+
+        // a data account derived from some other pda is rejected
+        let other_pda = Pubkey::new_unique();
+        let other_data_key = Pubkey::create_with_seed(&other_pda, &data_seed(seed), &program_id).unwrap();
+        let mut other_data = vec![0; FluidityData::LEN];
+        FluidityData::pack(fluidity_data(other_pda), &mut other_data).unwrap();
+        let mut other_lamports = 0;
+        let other_data_account = AccountInfo::new(
+            &other_data_key, false, false, &mut other_lamports, &mut other_data, &program_id, false, 0,
+        );
+        let mut other_pda_lamports = 0;
+        let other_pda_account = AccountInfo::new(
+            &other_pda, false, false, &mut other_pda_lamports, &mut [], &program_id, false, 0,
+        );
+        assert_eq!(
+            load_fluidity_data(&program_id, seed, &other_data_account, &other_pda_account),
+            Err(FluidityError::InvalidPda.into())
+        );
+    }
+
+    #[test]
+    fn test_load_config_checks_account() {
+        let program_id = Pubkey::new_unique();
+        let (config_key, mut data) = config_data(&program_id, Pubkey::new_unique());
+
+        // the config must be owned by this program
+        let other_program = Pubkey::new_unique();
+        let mut lamports = 0;
+        let config_account = AccountInfo::new(
+            &config_key, false, true, &mut lamports, &mut data, &other_program, false, 0,
+        );
+        assert_eq!(
+            load_config(&program_id, &config_account),
+            Err(FluidityError::InvalidConfigAccount.into())
+        );
+
+        // and live at the derived address
+        let other_key = Pubkey::new_unique();
+        let mut lamports = 0;
+        let config_account = AccountInfo::new(
+            &other_key, false, true, &mut lamports, &mut data, &program_id, false, 0,
+        );
+        assert_eq!(
+            load_config(&program_id, &config_account),
+            Err(FluidityError::InvalidConfigAccount.into())
+        );
+    }
+
+    // largest amount a simulated operation moves
+    const MAX_AMOUNT: u64 = 10_000_000_000;
+
+    // a token's deposits in a reserve, moved by wraps, unwraps and payouts
+    // the way they're moved through solend, alongside other depositors and
+    // borrowers accruing interest
+    #[derive(Clone)]
+    struct Simulation {
+        reserve: Reserve,
+        slot: Slot,
+        // collateral in the obligation
+        deposited: u64,
+        // collateral minted to the obligation authority and left out of the
+        // obligation by rounding
+        idle: u64,
+        // collateral held by other depositors
+        others: u64,
+        fluid_supply: u64,
+    }
+
+    #[derive(Clone, Debug)]
+    enum Operation {
+        Wrap(u64),
+        // unwrap a share of the fluid supply, in basis points
+        Unwrap(u16),
+        Payout { amount: u64, prize_pool_cap_bps: u16, sender_share_bps: u16 },
+        // another depositor deposits liquidity, or redeems a share of their
+        // collateral in basis points
+        Deposit(u64),
+        Redeem(u16),
+        // slots pass, accruing interest at the next refresh
+        Advance(u64),
+    }
+
+    fn operation() -> impl Strategy<Value = Operation> {
+        prop_oneof![
+            (2..=MAX_AMOUNT).prop_map(Operation::Wrap),
+            (0..=MAX_BPS).prop_map(Operation::Unwrap),
+            (0..=MAX_AMOUNT, 0..=MAX_BPS, 0..=MAX_BPS).prop_map(
+                |(amount, prize_pool_cap_bps, sender_share_bps)| Operation::Payout {
+                    amount,
+                    prize_pool_cap_bps,
+                    sender_share_bps,
+                },
+            ),
+            (1..=MAX_AMOUNT).prop_map(Operation::Deposit),
+            // leave some collateral, as an empty reserve resets its rate
+            (0..MAX_BPS).prop_map(Operation::Redeem),
+            (0..=SLOTS_PER_YEAR / 100).prop_map(Operation::Advance),
+        ]
+    }
+
+    impl Simulation {
+        // what the obligation's collateral is worth, as payouts value it
+        fn value(&self) -> Result<u64, ProgramError> {
+            self.reserve.collateral_exchange_rate()?.collateral_to_liquidity(self.deposited)
+        }
+
+        // what the obligation's collateral is worth beyond the fluid supply
+        fn prize_pool(&self) -> Result<u64, ProgramError> {
+            let deposited = Deposited {
+                collateral_amount: self.deposited,
+                value: self.value()?,
+                market_value: 0,
+                decimals: 6,
+            };
+            let fluid_mint = spl_token::state::Mint {
+                supply: self.fluid_supply,
+                decimals: 6,
+                is_initialized: true,
+                ..spl_token::state::Mint::default()
+            };
+
+            prize_pool(&deposited, &fluid_mint)
+        }
+
+        // accrue interest, as solend's RefreshReserve does
+        fn refresh(&mut self) -> ProgramResult {
+            let value = self.value()?;
+            self.reserve.accrue_interest(self.slot)?;
+            self.reserve.last_update.update_slot(self.slot);
+
+            // interest only makes collateral worth more
+            assert!(self.value()? >= value);
+
+            Ok(())
+        }
+
+        // apply an operation, rolling it back if it fails like the
+        // instruction running it would
+        fn apply(&mut self, operation: &Operation) {
+            let before = self.clone();
+            if self.try_apply(operation).is_err() {
+                *self = before;
+            }
+        }
+
+        fn try_apply(&mut self, operation: &Operation) -> ProgramResult {
+            match *operation {
+                Operation::Wrap(amount) => {
+                    self.refresh()?;
+                    let minted = self.reserve.deposit_liquidity(amount)?;
+
+                    // the adapter deposits the collateral the amount is worth
+                    // after the deposit
+                    let exchange_rate = self.reserve.collateral_exchange_rate()?;
+                    let collateral = exchange_rate.liquidity_to_collateral(amount)?;
+                    let value = exchange_rate.collateral_to_liquidity(collateral)?;
+
+                    // rounding keeps the deposit within what was minted, and
+                    // worth no more than was wrapped
+                    assert!(collateral <= minted);
+                    assert!(value <= amount);
+
+                    self.idle += minted - collateral;
+                    self.deposited += collateral;
+
+                    // and mints what the adapter would
+                    self.fluid_supply += solend::deposit_fluid_amount(
+                        &exchange_rate,
+                        amount,
+                        collateral,
+                        self.prize_pool().unwrap_or(0),
+                    )?;
+                }
+                Operation::Unwrap(share_bps) => {
+                    let amount = apply_bps(self.fluid_supply, share_bps)?;
+
+                    self.refresh()?;
+                    self.fluid_supply -= amount;
+
+                    // the adapter redeems the collateral it would
+                    let exchange_rate = self.reserve.collateral_exchange_rate()?;
+                    let collateral = solend::withdraw_collateral_amount(
+                        &exchange_rate,
+                        amount,
+                        self.prize_pool().unwrap_or(0),
+                        self.deposited,
+                    )?;
+                    let rounded_up = exchange_rate.decimal_liquidity_to_collateral(amount.into())?.try_ceil_u64()?;
+                    let withdrawn = self.reserve.redeem_collateral(collateral)?;
+
+                    // rounding never pays out less than was unwrapped when
+                    // the collateral's rounded up
+                    assert!(withdrawn >= amount || collateral < rounded_up);
+
+                    self.deposited -= collateral;
+                }
+                Operation::Payout { amount, prize_pool_cap_bps, sender_share_bps } => {
+                    // payouts value the pool without refreshing the reserve,
+                    // and have nothing to pay out of a pool in deficit
+                    let available_prize_pool = apply_bps(self.prize_pool()?, prize_pool_cap_bps)?;
+                    let (sender_prize, receiver_prize) = split_prize(
+                        amount.min(available_prize_pool),
+                        sender_share_bps,
+                    )?;
+
+                    // rounding never pays out more than the pool's share
+                    assert!(sender_prize + receiver_prize <= amount.min(available_prize_pool));
+
+                    self.fluid_supply += sender_prize + receiver_prize;
+                }
+                Operation::Deposit(amount) => {
+                    self.refresh()?;
+                    let exchange_rate = self.reserve.collateral_exchange_rate()?;
+                    let minted = self.reserve.deposit_liquidity(amount)?;
+
+                    // rounding never mints collateral worth more than was
+                    // deposited
+                    assert!(exchange_rate.collateral_to_liquidity(minted)? <= amount);
+
+                    self.others += minted;
+                }
+                Operation::Redeem(share_bps) => {
+                    let collateral = apply_bps(self.others, share_bps)?;
+
+                    self.refresh()?;
+                    self.reserve.redeem_collateral(collateral)?;
+                    self.others -= collateral;
+                }
+                Operation::Advance(slots) => {
+                    self.slot += slots;
+                }
+            }
+
+            Ok(())
+        }
+    }
+
+    proptest! {
+        #[test]
+        #[cfg_attr(miri, ignore)] // Miri: unsupported operation: `getcwd` not available when isolation is enabled
+        fn test_fluid_supply_stays_backed(
+            available in 1_000_000..=1_000_000_000_000u64,
+            borrowed in 0..=1_000_000_000_000u64,
+            collateral_percent in 50..=200u64,
+            borrow_rate in 0..=u8::MAX,
+            operations in prop::collection::vec(operation(), 1..50),
+        ) {
+            // others have deposited everything in the reserve, at a rate
+            // between a half and two collateral tokens per token
+            let collateral_supply = (available + borrowed) * collateral_percent / 100;
+            let mut simulation = Simulation {
+                reserve: Reserve {
+                    liquidity: ReserveLiquidity {
+                        available_amount: available,
+                        borrowed_amount_wads: Decimal::from(borrowed),
+                        ..ReserveLiquidity::default()
+                    },
+                    collateral: ReserveCollateral {
+                        mint_total_supply: collateral_supply,
+                        ..ReserveCollateral::default()
+                    },
+                    config: ReserveConfig {
+                        optimal_utilization_rate: 100,
+                        min_borrow_rate: borrow_rate,
+                        optimal_borrow_rate: borrow_rate,
+                        max_borrow_rate: borrow_rate,
+                        ..ReserveConfig::default()
+                    },
+                    ..Reserve::default()
+                },
+                slot: 0,
+                deposited: 0,
+                idle: 0,
+                others: collateral_supply,
+                fluid_supply: 0,
+            };
+
+            for operation in &operations {
+                simulation.apply(operation);
+
+                // the fluid supply is always backed by the deposits
+                assert!(
+                    simulation.fluid_supply <= simulation.value()?,
+                    "fluid supply {} exceeds the deposits' value {} after {:?}",
+                    simulation.fluid_supply,
+                    simulation.value()?,
+                    operation,
+                );
+
+                // and every collateral token minted is someone's
+                assert_eq!(
+                    simulation.deposited + simulation.idle + simulation.others,
+                    simulation.reserve.collateral.mint_total_supply,
+                );
+            }
         }
     }
 }
@@ -42,11 +42,11 @@ fn unpack_decimal(src: &[u8; 16]) -> Decimal {
     Decimal::from_scaled_val(u128::from_le_bytes(*src))
 }
 
-fn pack_bool(boolean: bool, dst: &mut [u8; 1]) {
+pub(crate) fn pack_bool(boolean: bool, dst: &mut [u8; 1]) {
     *dst = (boolean as u8).to_le_bytes()
 }
 
-fn unpack_bool(src: &[u8; 1]) -> Result<bool, ProgramError> {
+pub(crate) fn unpack_bool(src: &[u8; 1]) -> Result<bool, ProgramError> {
     match u8::from_le_bytes(*src) {
         0 => Ok(false),
         1 => Ok(true),
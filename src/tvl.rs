@@ -0,0 +1,221 @@
+// History of a token's deposits written by LogTVL, and reading it back
+
+use crate::error::FluidityError;
+
+use {
+    borsh::{BorshDeserialize, BorshSerialize},
+    solana_program::{
+        clock::{Slot, UnixTimestamp},
+        msg,
+        program_error::ProgramError,
+    },
+};
+
+// seed of the TVL log of every token logged, derived from the base account
+// passed to LogTVL
+pub const TVL_DATA_SEED: &str = "FLU:TVL_DATA";
+
+// seed of a token's TVL log, derived from the same base account
+pub fn tvl_data_seed(seed: &str) -> String {
+    format!("FLU:{}_TVL", seed)
+}
+
+// version of the TVL log layout, zero being an account not yet written
+pub const TVL_LOG_VERSION: u8 = 1;
+
+// number of records kept in a TVL log, after which the oldest are overwritten
+pub const TVL_LOG_CAPACITY: u64 = 256;
+
+// space taken by the TVL log's header and by each of its records
+const TVL_LOG_HEADER_SPACE: usize = 9;
+const TVL_RECORD_SPACE: usize = 64;
+
+// space a TVL log needs to be created with
+pub const TVL_LOG_SPACE: u64 =
+    TVL_LOG_HEADER_SPACE as u64 + TVL_LOG_CAPACITY * TVL_RECORD_SPACE as u64;
+
+// seconds in a year, for annualising returns
+const SECONDS_PER_YEAR: f64 = 365.0 * 24.0 * 60.0 * 60.0;
+
+// header of a TVL log, followed by a ring buffer of a token's TvlRecords, or
+// of AggregateTvlRecords
+#[derive(BorshDeserialize, BorshSerialize, Debug, PartialEq, Clone)]
+struct TvlLog {
+    // layout version of the log
+    version: u8,
+    // index of the next record written, its entry is at this modulo the
+    // capacity
+    next_index: u64,
+}
+
+// the value of a token's deposits at a point in time
+#[derive(BorshDeserialize, BorshSerialize, Debug, PartialEq, Clone)]
+pub struct TvlRecord {
+    pub slot: Slot,
+    pub unix_timestamp: UnixTimestamp,
+    // value in base tokens of the deposits, with interest
+    pub deposited_value: u64,
+    // collateral held for the deposits
+    pub collateral_amount: u64,
+    // base tokens per collateral token, scaled by EXCHANGE_RATE_SCALE
+    pub exchange_rate: u128,
+    // value of the deposits in the lending market's quote currency, scaled
+    // by 10^18
+    pub market_value: u128,
+}
+
+// the value of the deposits of every token logged at a point in time
+#[derive(BorshDeserialize, BorshSerialize, Debug, PartialEq, Clone)]
+pub struct AggregateTvlRecord {
+    pub slot: Slot,
+    pub unix_timestamp: UnixTimestamp,
+    // the currency shared by the tokens' lending markets, such as "USD" null
+    // padded or a mint
+    pub quote_currency: [u8; 32],
+    // value of the deposits in the quote currency, scaled by 10^18
+    pub market_value: u128,
+}
+
+// read a TVL log's header, which is zeroed until the first record is written
+fn read_header(data: &[u8]) -> Result<TvlLog, ProgramError> {
+    // the unversioned LogTVL wrote a single value to an account too small for
+    // a log, which CloseTvlData closes so it can be created again
+    if data.len() < TVL_LOG_SPACE as usize {
+        msg!("TVL log needs {} bytes", TVL_LOG_SPACE);
+        return Err(FluidityError::InvalidTvlLog.into());
+    }
+
+    let header = TvlLog::deserialize(&mut &data[..TVL_LOG_HEADER_SPACE])?;
+    match header.version {
+        0 => Ok(TvlLog { version: TVL_LOG_VERSION, next_index: 0 }),
+        TVL_LOG_VERSION => Ok(header),
+        version => {
+            msg!("unknown TVL log version {}", version);
+            Err(FluidityError::InvalidTvlLog.into())
+        }
+    }
+}
+
+// offset of the record an index is written to in a TVL log
+fn record_offset(index: u64) -> usize {
+    TVL_LOG_HEADER_SPACE + (index % TVL_LOG_CAPACITY) as usize * TVL_RECORD_SPACE
+}
+
+// write a record over the oldest in a TVL log, returning its index
+pub(crate) fn append_tvl_record<T: BorshSerialize>(data: &mut [u8], record: &T) -> Result<u64, ProgramError> {
+    let mut header = read_header(data)?;
+    let index = header.next_index;
+
+    let offset = record_offset(index);
+    record.serialize(&mut &mut data[offset..offset + TVL_RECORD_SPACE])?;
+
+    header.next_index = index + 1;
+    header.serialize(&mut &mut data[..TVL_LOG_HEADER_SPACE])?;
+
+    Ok(index)
+}
+
+// the records kept in a TVL log, oldest first
+pub fn read_tvl_log<T: BorshDeserialize>(data: &[u8]) -> Result<Vec<T>, ProgramError> {
+    let header = read_header(data)?;
+    let first = header.next_index.saturating_sub(TVL_LOG_CAPACITY);
+
+    (first..header.next_index)
+        .map(|index| {
+            let offset = record_offset(index);
+            Ok(T::deserialize(&mut &data[offset..offset + TVL_RECORD_SPACE])?)
+        })
+        .collect()
+}
+
+// the annual yield realised between two records, from the growth of the
+// exchange rate so wraps and unwraps in between don't count - None when the
+// later record isn't later or either rate is zero
+pub fn realised_apy(earlier: &TvlRecord, later: &TvlRecord) -> Option<f64> {
+    let seconds = later.unix_timestamp.checked_sub(earlier.unix_timestamp)?;
+    if seconds <= 0 || earlier.exchange_rate == 0 || later.exchange_rate == 0 {
+        return None;
+    }
+
+    let growth = later.exchange_rate as f64 / earlier.exchange_rate as f64;
+    Some(growth.powf(SECONDS_PER_YEAR / seconds as f64) - 1.0)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::event::EXCHANGE_RATE_SCALE;
+
+    // the exchange rate of a collateral token worth one base token
+    const PAR: u128 = EXCHANGE_RATE_SCALE;
+
+    fn record(index: u64, unix_timestamp: UnixTimestamp, exchange_rate: u128) -> TvlRecord {
+        TvlRecord {
+            slot: index,
+            unix_timestamp,
+            deposited_value: 1_000 + index,
+            collateral_amount: 1_000,
+            exchange_rate,
+            market_value: u128::MAX,
+        }
+    }
+
+    #[test]
+    fn test_tvl_log() {
+        let mut data = vec![0; TVL_LOG_SPACE as usize];
+
+        // a new account reads as empty
+        assert_eq!(read_tvl_log::<TvlRecord>(&data), Ok(vec![]));
+
+        assert_eq!(append_tvl_record(&mut data, &record(0, 0, PAR)), Ok(0));
+        assert_eq!(data[0], TVL_LOG_VERSION);
+        assert_eq!(read_tvl_log(&data), Ok(vec![record(0, 0, PAR)]));
+
+        // wrap around, dropping the oldest
+        for index in 1..=TVL_LOG_CAPACITY {
+            append_tvl_record(&mut data, &record(index, 0, PAR)).unwrap();
+        }
+        let records = read_tvl_log::<TvlRecord>(&data).unwrap();
+        assert_eq!(records.len(), TVL_LOG_CAPACITY as usize);
+        assert_eq!(records[0].slot, 1);
+        assert_eq!(records.last().unwrap().slot, TVL_LOG_CAPACITY);
+
+        // unknown versions and short accounts are refused
+        data[0] = TVL_LOG_VERSION + 1;
+        assert_eq!(read_tvl_log::<TvlRecord>(&data), Err(FluidityError::InvalidTvlLog.into()));
+        let mut short = vec![0; 8];
+        assert_eq!(
+            append_tvl_record(&mut short, &record(0, 0, PAR)),
+            Err(FluidityError::InvalidTvlLog.into())
+        );
+    }
+
+    #[test]
+    fn test_record_space() {
+        // both kinds of record fill their entry exactly
+        let aggregate = AggregateTvlRecord {
+            slot: 0,
+            unix_timestamp: 0,
+            quote_currency: [0; 32],
+            market_value: 0,
+        };
+        assert_eq!(record(0, 0, PAR).try_to_vec().unwrap().len(), TVL_RECORD_SPACE);
+        assert_eq!(aggregate.try_to_vec().unwrap().len(), TVL_RECORD_SPACE);
+    }
+
+    #[test]
+    fn test_realised_apy() {
+        let year = SECONDS_PER_YEAR as UnixTimestamp;
+
+        // 5% over a year
+        let apy = realised_apy(&record(0, 0, PAR), &record(1, year, PAR / 100 * 105)).unwrap();
+        assert!((apy - 0.05).abs() < 1e-9);
+
+        // 1% over half a year compounds to a bit over 2%
+        let apy = realised_apy(&record(0, 0, PAR), &record(1, year / 2, PAR / 100 * 101)).unwrap();
+        assert!((apy - 0.0201).abs() < 1e-6);
+
+        assert_eq!(realised_apy(&record(0, year, PAR), &record(1, 0, PAR)), None);
+        assert_eq!(realised_apy(&record(0, 0, 0), &record(1, year, PAR)), None);
+    }
+}
@@ -0,0 +1,14 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use solana_fluidity_fuzz::{run, FuzzInstruction, Seed, Step, TokenState};
+
+// data accounts created for any token, with any lamports and space
+fuzz_target!(|input: (TokenState, Vec<(Seed, u64, u64)>)| {
+    let (state, inits) = input;
+    let steps: Vec<Step> = inits
+        .into_iter()
+        .map(|(seed, lamports, space)| Step::new(FuzzInstruction::InitData { seed, lamports, space }))
+        .collect();
+
+    run(&state, &steps);
+});
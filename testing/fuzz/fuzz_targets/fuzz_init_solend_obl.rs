@@ -0,0 +1,23 @@
+#![no_main]
+use fluidity::processor::FluidityData;
+use libfuzzer_sys::fuzz_target;
+use solana_fluidity_fuzz::{run, FuzzInstruction, Seed, Step, TokenState};
+use solana_program::{program_pack::Pack, rent::Rent};
+
+// obligations created for any token, with any lamports and size, after the
+// listed token's data account so it has one to create
+fuzz_target!(|input: (TokenState, Vec<(Seed, u64, u64)>)| {
+    let (state, inits) = input;
+    let init_data = Step::new(FuzzInstruction::InitData {
+        seed: Seed::Listed,
+        lamports: Rent::default().minimum_balance(FluidityData::LEN),
+        space: FluidityData::LEN as u64,
+    });
+    let steps: Vec<Step> = std::iter::once(init_data)
+        .chain(inits.into_iter().map(|(seed, lamports, size)| {
+            Step::new(FuzzInstruction::InitSolendObligation { seed, lamports, size })
+        }))
+        .collect();
+
+    run(&state, &steps);
+});
@@ -0,0 +1,9 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use solana_fluidity_fuzz::{run, Step, TokenState};
+
+// any instructions, signed by anyone, with any account substituted
+fuzz_target!(|input: (TokenState, Vec<Step>)| {
+    let (state, steps) = input;
+    run(&state, &steps);
+});
@@ -1,14 +1,14 @@
 #![no_main]
-use borsh::ser::BorshSerialize;
-use bumpalo::Bump;
-use fluidity::instruction::FluidityInstruction;
 use libfuzzer_sys::fuzz_target;
-use solana_fluidity_fuzz::*;
-use std::str;
+use solana_fluidity_fuzz::{run, FuzzInstruction, Seed, Step, TokenState};
 
-fuzz_target!(|data: &[u8]| {
-    let bump = Bump::new();
-    let fun = FluidityInstruction::LogTVL;
-    let program_id = random_pubkey(&bump);
-    process_instruction(program_id, &[], &fun.try_to_vec().unwrap());
+// TVL logged for any tokens, repeatedly
+fuzz_target!(|input: (TokenState, Vec<Vec<Seed>>)| {
+    let (state, logs) = input;
+    let steps: Vec<Step> = logs
+        .into_iter()
+        .map(|seeds| Step::new(FuzzInstruction::LogTvl { seeds }))
+        .collect();
+
+    run(&state, &steps);
 });
@@ -1,21 +1,16 @@
 #![no_main]
 use libfuzzer_sys::fuzz_target;
-use solana_fluidity_fuzz::{
-    process_instruction, setup_payout_keys
-};
-use bumpalo::Bump;
-use fluidity::instruction::FluidityInstruction;
-use std::str;
-use borsh::ser::BorshSerialize;
+use solana_fluidity_fuzz::{run, FuzzInstruction, Seed, Step, TokenState, Transfer};
 
+// payouts of the registered token's prize pool by the payout operator
+fuzz_target!(|input: (TokenState, Vec<(u64, Transfer)>)| {
+    let (state, payouts) = input;
+    let steps: Vec<Step> = payouts
+        .into_iter()
+        .map(|(amount, transfer)| {
+            Step::new(FuzzInstruction::Payout { seed: Seed::Registered, amount, transfer })
+        })
+        .collect();
 
-fuzz_target!(|data: &[u8]| {
-    if let Ok(blah) = str::from_utf8(data) {
-        let bump = Bump::new();
-        let accs = setup_payout_keys(&bump);
-        let fun = FluidityInstruction::(blah.to_string());
-        process_instruction(accs.token_program.owner, &[], &fun.try_to_vec().unwrap());
-    }
-    
-    
+    run(&state, &steps);
 });
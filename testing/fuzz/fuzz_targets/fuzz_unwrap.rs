@@ -0,0 +1,22 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use solana_fluidity_fuzz::{run, FuzzInstruction, Seed, Step, TokenState, User};
+
+// wraps and unwraps of the registered token by its users, with or without
+// a minimum
+fuzz_target!(|input: (TokenState, Vec<(bool, User, u64, Option<u64>)>)| {
+    let (state, moves) = input;
+    let steps: Vec<Step> = moves
+        .into_iter()
+        .map(|(wrap, user, amount, min_out)| {
+            let seed = Seed::Registered;
+            Step::new(if wrap {
+                FuzzInstruction::Wrap { seed, user, amount, min_out }
+            } else {
+                FuzzInstruction::Unwrap { seed, user, amount, min_out }
+            })
+        })
+        .collect();
+
+    run(&state, &steps);
+});
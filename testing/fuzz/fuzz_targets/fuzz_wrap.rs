@@ -1,18 +1,16 @@
 #![no_main]
-use borsh::ser::BorshSerialize;
-use bumpalo::Bump;
-use fluidity::instruction::FluidityInstruction;
 use libfuzzer_sys::fuzz_target;
-use solana_fluidity_fuzz::*;
-use std::str;
+use solana_fluidity_fuzz::{run, FuzzInstruction, Seed, Step, TokenState, User};
 
-fuzz_target!(|data: &[u8]| {
-    if Ok(seed) = data.to_string() {
-        let bump_lifetime = Bump::new();
-        let amount = 100;
-        let bump = data[0];
-        let fun = FluidityInstruction::Wrap(amount, seed, bump);
-        let program_id = random_pubkey(&bump_lifetime);
-        process_instruction(program_id, &[], &fun.try_to_vec().unwrap());
-    }
+// wraps of the registered token by its users, with or without a minimum
+fuzz_target!(|input: (TokenState, Vec<(User, u64, Option<u64>)>)| {
+    let (state, wraps) = input;
+    let steps: Vec<Step> = wraps
+        .into_iter()
+        .map(|(user, amount, min_out)| {
+            Step::new(FuzzInstruction::Wrap { seed: Seed::Registered, user, amount, min_out })
+        })
+        .collect();
+
+    run(&state, &steps);
 });
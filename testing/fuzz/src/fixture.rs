@@ -0,0 +1,972 @@
+// A bank of accounts holding a registered token and a listed one against the
+// mock Solend, which runs fuzzed steps the way the runtime would: resolving
+// their accounts, rolling failed instructions back, and checking what the
+// ones that succeed did
+
+use crate::{input::*, solend_mock, stubs, system};
+
+use fluidity::{
+    instruction::{
+        self, DepositReserve, DrawTier, FluidityInstruction, LegacyLayout, SolendAccounts, TvlAccounts, Winner,
+        DRAW_TIERS,
+    },
+    math::Decimal,
+    processor::{
+        logged_transfer_hash, FluidityData, InitFluidityDataParams, CONFIG_SPACE, RECEIPT_SPACE,
+        TRANSFER_LOG_SPACE,
+    },
+    state::{
+        InitLendingMarketParams, InitObligationParams, InitReserveParams, LendingMarket,
+        NewReserveCollateralParams, NewReserveLiquidityParams, Obligation, ObligationCollateral, Reserve,
+        ReserveCollateral, ReserveConfig, ReserveLiquidity,
+    },
+    tvl::{tvl_data_seed, TVL_DATA_SEED, TVL_LOG_SPACE},
+};
+
+use {
+    borsh::BorshSerialize,
+    bumpalo::Bump,
+    solana_program::{
+        account_info::AccountInfo,
+        bpf_loader,
+        clock::{Clock, Epoch, Slot},
+        entrypoint::ProgramResult,
+        hash::{hashv, Hash},
+        instruction::Instruction,
+        program_option::COption,
+        program_pack::{IsInitialized, Pack},
+        pubkey::Pubkey,
+        rent::Rent,
+        system_program,
+        sysvar,
+    },
+    spl_token::state::{Account, AccountState, Mint},
+    std::collections::HashSet,
+};
+
+// the slot the fixture's clock is at
+const CURRENT_SLOT: Slot = 100_000_000;
+
+// recent slots in the slot hashes sysvar
+const SLOT_HASHES: u64 = 32;
+
+// lamports each wallet starts with
+const WALLET_LAMPORTS: u64 = 1_000_000_000_000;
+
+// the config account InitConfig would create, with every role held by the
+// admin
+#[derive(BorshSerialize)]
+struct Config {
+    admin: Pubkey,
+    pending_admin: Pubkey,
+    bump: u8,
+    payout_operator: Pubkey,
+    registry_admin: Pubkey,
+    guardian: Pubkey,
+    paused: bool,
+    unwrap_paused: bool,
+}
+
+// the accounts of one of the fixture's tokens
+#[derive(Clone)]
+pub struct Token {
+    pub seed: &'static str,
+    pub token_mint: Pubkey,
+    pub fluid_mint: Pubkey,
+    pub pda: Pubkey,
+    pub data_account: Pubkey,
+    pub transfer_log: Pubkey,
+    // the admin's base token account deregistering redeems to
+    pub treasury: Pubkey,
+    pub user_tokens: [Pubkey; USERS],
+    pub user_fluid: [Pubkey; USERS],
+    pub solend: SolendAccounts,
+}
+
+// an account as it was before an instruction ran
+#[derive(Clone, PartialEq)]
+struct Snapshot {
+    lamports: u64,
+    data: Vec<u8>,
+    owner: Pubkey,
+}
+
+pub struct Fixture<'bump> {
+    bump: &'bump Bump,
+    pub program_id: Pubkey,
+    pub clock: Clock,
+    pub admin: Pubkey,
+    pub stranger: Pubkey,
+    pub users: [Pubkey; USERS],
+    // the base the TVL logs' addresses are derived from
+    pub tvl_base: Pubkey,
+    pub lending_market: Pubkey,
+    // the registered token, then the listed one
+    pub tokens: Vec<Token>,
+    accounts: Vec<AccountInfo<'bump>>,
+}
+
+// a key that's the same every run, so crashes reproduce
+fn address(name: &str) -> Pubkey {
+    Pubkey::new_from_array(hashv(&[name.as_bytes()]).to_bytes())
+}
+
+fn transfer_slot(transfer: &Transfer) -> Slot {
+    CURRENT_SLOT.saturating_sub(transfer.slots_ago as Slot)
+}
+
+impl<'bump> Fixture<'bump> {
+    pub fn new(bump: &'bump Bump, state: &TokenState) -> Self {
+        let clock = Clock { slot: CURRENT_SLOT, ..Clock::default() };
+        stubs::install(&clock);
+
+        let mut fixture = Self {
+            bump,
+            program_id: address("fluidity"),
+            clock,
+            admin: address("admin"),
+            stranger: address("stranger"),
+            users: [address("first user"), address("second user")],
+            tvl_base: address("tvl base"),
+            lending_market: address("lending market"),
+            tokens: vec![],
+            accounts: vec![],
+        };
+
+        for program in &[fixture.program_id, spl_token::id(), system_program::id(), solend_mock::id()] {
+            fixture.add_account(*program, 1, &[], &bpf_loader::id(), true);
+        }
+
+        let clock = bincode::serialize(&fixture.clock).unwrap();
+        fixture.add_sysvar(sysvar::clock::id(), clock);
+        fixture.add_sysvar(sysvar::rent::id(), bincode::serialize(&Rent::default()).unwrap());
+        // the most recent slot first
+        let slot_hashes: Vec<(Slot, Hash)> = (CURRENT_SLOT - SLOT_HASHES..CURRENT_SLOT)
+            .rev()
+            .map(|slot| (slot, hashv(&[&slot.to_le_bytes()[..]])))
+            .collect();
+        fixture.add_sysvar(sysvar::slot_hashes::id(), bincode::serialize(&slot_hashes).unwrap());
+
+        for wallet in [fixture.admin, fixture.stranger, fixture.tvl_base].iter().chain(&fixture.users.clone()) {
+            fixture.add_account(*wallet, WALLET_LAMPORTS, &[], &system_program::id(), false);
+        }
+
+        let (config_address, config_bump) = instruction::find_config_address(&fixture.program_id);
+        let mut config = vec![0; CONFIG_SPACE as usize];
+        Config {
+            admin: fixture.admin,
+            pending_admin: Pubkey::default(),
+            bump: config_bump,
+            payout_operator: fixture.admin,
+            registry_admin: fixture.admin,
+            guardian: fixture.admin,
+            paused: false,
+            unwrap_paused: false,
+        }.serialize(&mut &mut config[..]).unwrap();
+        let program_id = fixture.program_id;
+        fixture.add_account(
+            config_address,
+            Rent::default().minimum_balance(config.len()),
+            &config,
+            &program_id,
+            false,
+        );
+
+        let (_, lending_market_bump) = solend_mock::find_lending_market_authority(&fixture.lending_market);
+        fixture.add_packed(
+            fixture.lending_market,
+            LendingMarket::new(InitLendingMarketParams {
+                bump_seed: lending_market_bump,
+                owner: address("lending market owner"),
+                quote_currency: *b"USD\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0",
+                token_program_id: spl_token::id(),
+                oracle_program_id: address("pyth"),
+                switchboard_oracle_program_id: address("switchboard"),
+            }),
+            &solend_mock::id(),
+        );
+
+        let tvl_data = Pubkey::create_with_seed(&fixture.tvl_base, TVL_DATA_SEED, &program_id).unwrap();
+        fixture.add_account(
+            tvl_data,
+            Rent::default().minimum_balance(TVL_LOG_SPACE as usize),
+            &vec![0; TVL_LOG_SPACE as usize],
+            &program_id,
+            false,
+        );
+
+        let registered = fixture.add_token("USDC", state, true);
+        let listed = fixture.add_token("USDT", state, false);
+        fixture.tokens = vec![registered, listed];
+
+        fixture
+    }
+
+    // add a token's reserve and the users' token accounts, and if it's
+    // registered its data account, fluid mint and obligation
+    fn add_token(&mut self, seed: &'static str, state: &TokenState, registered: bool) -> Token {
+        let program_id = self.program_id;
+        let named = |name: &str| address(&format!("{} {}", seed, name));
+
+        let (pda, bump) = instruction::find_obligation_authority_address(&program_id, seed);
+        let fluid_mint = instruction::find_fluid_mint_address(&program_id, seed).unwrap();
+        let data_account = instruction::find_data_address(&program_id, seed).unwrap();
+        let staging = instruction::find_staging_address(&program_id, seed).unwrap();
+        let (lending_market_authority, _) = solend_mock::find_lending_market_authority(&self.lending_market);
+
+        let token = Token {
+            seed,
+            token_mint: named("mint"),
+            fluid_mint,
+            pda,
+            data_account,
+            transfer_log: named("transfer log"),
+            treasury: named("treasury"),
+            user_tokens: [named("first user tokens"), named("second user tokens")],
+            user_fluid: [named("first user fluid"), named("second user fluid")],
+            solend: SolendAccounts {
+                solend_program: solend_mock::id(),
+                collateral: named("collateral"),
+                reserve: named("reserve"),
+                reserve_liquidity_supply: named("liquidity supply"),
+                reserve_collateral_mint: named("collateral mint"),
+                lending_market: self.lending_market,
+                lending_market_authority,
+                reserve_collateral_supply: named("collateral supply"),
+                obligation: Pubkey::create_with_seed(
+                    &pda,
+                    &self.lending_market.to_string()[0..32],
+                    &solend_mock::id(),
+                ).unwrap(),
+                pyth_price: named("pyth price"),
+                switchboard_feed: named("switchboard feed"),
+            },
+        };
+        let solend = &token.solend;
+
+        // a token that isn't registered has nothing deposited or wrapped
+        let (deposited, user_fluid) = if registered {
+            let first = state.user_fluid[0];
+            (
+                state.deposited_collateral.min(state.collateral_supply),
+                [first, state.user_fluid[1].min(u64::MAX - first)],
+            )
+        } else {
+            (0, [0; USERS])
+        };
+
+        let users = self.users;
+        let token_supply = state.user_tokens
+            .iter()
+            .fold(state.available_liquidity, |supply, amount| supply.saturating_add(*amount));
+        self.add_mint(token.token_mint, &named("mint authority"), None, token_supply, state.decimals);
+        for (index, account) in token.user_tokens.iter().enumerate() {
+            self.add_token_account(*account, &token.token_mint, &users[index], state.user_tokens[index]);
+        }
+        let admin = self.admin;
+        self.add_token_account(token.treasury, &token.token_mint, &admin, 0);
+
+        let mut reserve = Reserve::new(InitReserveParams {
+            current_slot: CURRENT_SLOT - state.elapsed_slots as Slot,
+            lending_market: self.lending_market,
+            liquidity: ReserveLiquidity::new(NewReserveLiquidityParams {
+                mint_pubkey: token.token_mint,
+                mint_decimals: state.decimals,
+                supply_pubkey: solend.reserve_liquidity_supply,
+                pyth_oracle_pubkey: solend.pyth_price,
+                switchboard_oracle_pubkey: solend.switchboard_feed,
+                market_price: Decimal::from(state.market_price),
+            }),
+            collateral: ReserveCollateral::new(NewReserveCollateralParams {
+                mint_pubkey: solend.reserve_collateral_mint,
+                supply_pubkey: solend.reserve_collateral_supply,
+            }),
+            config: ReserveConfig {
+                optimal_utilization_rate: 100,
+                min_borrow_rate: state.borrow_rate,
+                optimal_borrow_rate: state.borrow_rate,
+                max_borrow_rate: state.borrow_rate,
+                deposit_limit: u64::MAX,
+                ..Default::default()
+            },
+        });
+        reserve.liquidity.available_amount = state.available_liquidity;
+        reserve.liquidity.borrowed_amount_wads = Decimal::from(state.borrowed);
+        reserve.collateral.mint_total_supply = state.collateral_supply;
+        self.add_packed(solend.reserve, reserve, &solend_mock::id());
+
+        let oracle_owner = address("oracle");
+        self.add_account(solend.pyth_price, 1, &[], &oracle_owner, false);
+        self.add_account(solend.switchboard_feed, 1, &[], &oracle_owner, false);
+
+        self.add_token_account(
+            solend.reserve_liquidity_supply,
+            &token.token_mint,
+            &lending_market_authority,
+            state.available_liquidity,
+        );
+        self.add_mint(
+            solend.reserve_collateral_mint,
+            &lending_market_authority,
+            None,
+            state.collateral_supply,
+            state.decimals,
+        );
+        self.add_token_account(
+            solend.reserve_collateral_supply,
+            &solend.reserve_collateral_mint,
+            &lending_market_authority,
+            state.collateral_supply - deposited,
+        );
+        self.add_token_account(solend.collateral, &solend.reserve_collateral_mint, &pda, 0);
+
+        if registered {
+            self.add_mint(fluid_mint, &pda, Some(&pda), user_fluid[0] + user_fluid[1], state.decimals);
+            for (index, account) in token.user_fluid.iter().enumerate() {
+                self.add_token_account(*account, &fluid_mint, &users[index], user_fluid[index]);
+            }
+
+            let data = FluidityData::new(InitFluidityDataParams {
+                token_mint: token.token_mint,
+                fluid_mint,
+                pda,
+                bump,
+                reserve: solend.reserve,
+                lending_market: self.lending_market,
+                obligation: solend.obligation,
+                collateral_mint: solend.reserve_collateral_mint,
+                liquidity_supply: solend.reserve_liquidity_supply,
+                collateral_supply: solend.reserve_collateral_supply,
+                collateral: solend.collateral,
+            });
+            self.add_packed(data_account, data, &program_id);
+
+            // the collateral deposited is held by the reserve's supply
+            let mut deposit = ObligationCollateral::new(solend.reserve);
+            deposit.deposited_amount = deposited;
+            let deposits = if deposited > 0 { vec![deposit] } else { vec![] };
+            self.add_packed(
+                solend.obligation,
+                Obligation::new(InitObligationParams {
+                    current_slot: CURRENT_SLOT,
+                    lending_market: self.lending_market,
+                    owner: pda,
+                    deposits,
+                    borrows: vec![],
+                }),
+                &solend_mock::id(),
+            );
+        } else {
+            self.add_uncreated(fluid_mint, Mint::LEN as u64);
+            self.add_uncreated(data_account, FluidityData::LEN as u64);
+            self.add_uncreated(solend.obligation, Obligation::LEN as u64);
+        }
+
+        // created by the registry admin, for InitTransferLog
+        self.add_account(
+            token.transfer_log,
+            Rent::default().minimum_balance(TRANSFER_LOG_SPACE as usize),
+            &vec![0; TRANSFER_LOG_SPACE as usize],
+            &program_id,
+            false,
+        );
+        self.add_uncreated(staging, FluidityData::LEN as u64);
+
+        let tvl_log = Pubkey::create_with_seed(&self.tvl_base, &tvl_data_seed(seed), &program_id).unwrap();
+        self.add_account(
+            tvl_log,
+            Rent::default().minimum_balance(TVL_LOG_SPACE as usize),
+            &vec![0; TVL_LOG_SPACE as usize],
+            &program_id,
+            false,
+        );
+
+        token
+    }
+
+    // add an account, allocating everything it borrows so the stubs can
+    // write over it
+    fn add_account(&mut self, key: Pubkey, lamports: u64, data: &[u8], owner: &Pubkey, executable: bool) {
+        let bump = self.bump;
+        self.accounts.push(AccountInfo::new(
+            bump.alloc(key),
+            false,
+            false,
+            bump.alloc(lamports),
+            bump.alloc_slice_copy(data),
+            bump.alloc(*owner),
+            executable,
+            Epoch::default(),
+        ));
+    }
+
+    fn add_packed<T: Pack>(&mut self, key: Pubkey, state: T, owner: &Pubkey) {
+        let mut data = vec![0; T::LEN];
+        T::pack(state, &mut data).unwrap();
+        self.add_account(key, Rent::default().minimum_balance(T::LEN), &data, owner, false);
+    }
+
+    fn add_sysvar(&mut self, key: Pubkey, data: Vec<u8>) {
+        self.add_account(key, 1, &data, &sysvar::id(), false);
+    }
+
+    fn add_mint(&mut self, key: Pubkey, authority: &Pubkey, freeze_authority: Option<&Pubkey>, supply: u64, decimals: u8) {
+        let mint = Mint {
+            mint_authority: COption::Some(*authority),
+            supply,
+            decimals,
+            is_initialized: true,
+            freeze_authority: match freeze_authority {
+                Some(key) => COption::Some(*key),
+                None => COption::None,
+            },
+        };
+        self.add_packed(key, mint, &spl_token::id());
+    }
+
+    fn add_token_account(&mut self, key: Pubkey, mint: &Pubkey, owner: &Pubkey, amount: u64) {
+        let account = Account {
+            mint: *mint,
+            owner: *owner,
+            amount,
+            state: AccountState::Initialized,
+            ..Default::default()
+        };
+        self.add_packed(key, account, &spl_token::id());
+    }
+
+    // add an account yet to be created, with the space it'll be allocated
+    // as the stubs can't resize accounts
+    fn add_uncreated(&mut self, key: Pubkey, space: u64) {
+        if self.account(&key).is_none() {
+            self.add_account(key, 0, &vec![0; space as usize], &system_program::id(), false);
+        }
+    }
+
+    fn account(&self, key: &Pubkey) -> Option<&AccountInfo<'bump>> {
+        self.accounts.iter().find(|account| account.key == key)
+    }
+
+    fn unpack<T: Pack + IsInitialized>(&self, key: &Pubkey) -> Option<T> {
+        T::unpack(&self.account(key)?.data.borrow()).ok()
+    }
+
+    fn key(&self, key: Key) -> Pubkey {
+        match key {
+            Key::Admin => self.admin,
+            Key::User(user) => self.users[user.index()],
+            Key::Stranger => self.stranger,
+        }
+    }
+
+    // the token a seed picks, and the name the instruction passes
+    fn token(&self, seed: &Seed) -> (Token, String) {
+        match seed {
+            Seed::Registered => (self.tokens[0].clone(), self.tokens[0].seed.to_string()),
+            Seed::Listed => (self.tokens[1].clone(), self.tokens[1].seed.to_string()),
+            Seed::Other(name) => (self.tokens[0].clone(), name.clone()),
+        }
+    }
+
+    // add the receipt a payout of a transfer creates
+    fn add_receipt(&mut self, transfer_hash: &[u8; 32]) {
+        let (receipt, _) = instruction::find_receipt_address(&self.program_id, transfer_hash);
+        self.add_uncreated(receipt, RECEIPT_SPACE);
+    }
+
+    // build a step's instruction from the fixture's accounts
+    fn instruction(&mut self, step: &Step) -> Instruction {
+        let program_id = self.program_id;
+        let signer = self.key(step.signer);
+
+        let mut instruction = match &step.instruction {
+            FuzzInstruction::Wrap { seed, user, amount, min_out } => {
+                let (token, name) = self.token(seed);
+                let index = user.index();
+                match min_out {
+                    Some(min_out) => instruction::wrap_min_out(
+                        &program_id, &token.data_account, &token.token_mint, &token.fluid_mint, &token.pda,
+                        &self.users[index], &token.user_tokens[index], &token.user_fluid[index], &token.solend,
+                        *amount, *min_out, name,
+                    ),
+                    None => instruction::wrap(
+                        &program_id, &token.data_account, &token.token_mint, &token.fluid_mint, &token.pda,
+                        &self.users[index], &token.user_tokens[index], &token.user_fluid[index], &token.solend,
+                        *amount, name,
+                    ),
+                }
+            }
+            FuzzInstruction::Unwrap { seed, user, amount, min_out } => {
+                let (token, name) = self.token(seed);
+                let index = user.index();
+                match min_out {
+                    Some(min_out) => instruction::unwrap_min_out(
+                        &program_id, &token.data_account, &token.token_mint, &token.fluid_mint, &token.pda,
+                        &self.users[index], &token.user_tokens[index], &token.user_fluid[index], &token.solend,
+                        *amount, *min_out, name,
+                    ),
+                    None => instruction::unwrap(
+                        &program_id, &token.data_account, &token.token_mint, &token.fluid_mint, &token.pda,
+                        &self.users[index], &token.user_tokens[index], &token.user_fluid[index], &token.solend,
+                        *amount, name,
+                    ),
+                }
+            }
+            FuzzInstruction::Payout { seed, amount, transfer } => {
+                let (token, name) = self.token(seed);
+                self.add_receipt(&transfer.hash);
+                instruction::payout(
+                    &program_id, &signer, &token.fluid_mint, &token.pda, &token.solend.obligation,
+                    &token.solend.reserve, &token.user_fluid[transfer.sender.index()],
+                    &token.user_fluid[transfer.receiver.index()], &token.data_account, *amount, name,
+                    transfer.hash, transfer_slot(transfer),
+                )
+            }
+            FuzzInstruction::InitSolendObligation { seed, lamports, size } => {
+                let (token, name) = self.token(seed);
+                instruction::init_solend_obligation(
+                    &program_id, &signer, &solend_mock::id(), &token.solend.obligation, &self.lending_market,
+                    &token.pda, &token.data_account, *lamports, *size, name,
+                )
+            }
+            FuzzInstruction::LogTvl { seeds } => {
+                // the logs are derived from the fixture's names, the
+                // instruction naming the tokens as fuzzed
+                let (tokens, names): (Vec<_>, Vec<_>) = seeds.iter().map(|seed| self.token(seed)).unzip();
+                let accounts: Vec<TvlAccounts> = tokens
+                    .iter()
+                    .map(|token| TvlAccounts {
+                        seed: token.seed.to_string(),
+                        fluidity_data_account: token.data_account,
+                        pda_account: token.pda,
+                        solend_program: solend_mock::id(),
+                        obligation: token.solend.obligation,
+                        lending_market: self.lending_market,
+                        reserves: vec![DepositReserve {
+                            reserve: token.solend.reserve,
+                            pyth_price: token.solend.pyth_price,
+                            switchboard_feed: token.solend.switchboard_feed,
+                        }],
+                    })
+                    .collect();
+                let tvl_data = Pubkey::create_with_seed(&self.tvl_base, TVL_DATA_SEED, &program_id).unwrap();
+                let mut instruction = instruction::log_tvl(&program_id, &tvl_data, &self.tvl_base, &accounts).unwrap();
+                instruction.data = FluidityInstruction::LogTVL(names).try_to_vec().unwrap();
+                instruction
+            }
+            FuzzInstruction::InitData { seed, lamports, space } => {
+                let (token, name) = self.token(seed);
+                instruction::init_data(
+                    &program_id, &signer, &token.data_account, &token.token_mint, &token.fluid_mint, &token.pda,
+                    &solend_mock::id(), &token.solend.reserve, &token.solend.obligation, &token.solend.collateral,
+                    name, *lamports, *space,
+                )
+            }
+            FuzzInstruction::InitConfig { admin } => {
+                instruction::init_config(&program_id, &signer, &self.key(*admin))
+            }
+            FuzzInstruction::ProposeAdmin { new_admin } => {
+                instruction::propose_admin(&program_id, &signer, &self.key(*new_admin))
+            }
+            FuzzInstruction::AcceptAdmin => instruction::accept_admin(&program_id, &signer),
+            FuzzInstruction::GrantRole { role, key } => {
+                instruction::grant_role(&program_id, &signer, (*role).into(), &self.key(*key))
+            }
+            FuzzInstruction::RevokeRole { role } => instruction::revoke_role(&program_id, &signer, (*role).into()),
+            FuzzInstruction::SetPaused { seed, paused, unwrap_paused } => {
+                let token = seed.as_ref().map(|seed| self.token(seed));
+                instruction::set_paused(
+                    &program_id,
+                    &signer,
+                    token.as_ref().map(|(token, name)| (name.clone(), &token.data_account)),
+                    *paused,
+                    *unwrap_paused,
+                )
+            }
+            FuzzInstruction::SetPayoutParams { seed, prize_pool_cap_bps, sender_share_bps } => {
+                let (token, name) = self.token(seed);
+                instruction::set_payout_params(
+                    &program_id, &signer, &token.data_account, name, *prize_pool_cap_bps, *sender_share_bps,
+                )
+            }
+            FuzzInstruction::BatchPayout { seed, winners } => {
+                let (token, name) = self.token(seed);
+                for winner in winners {
+                    self.add_receipt(&winner.transfer.hash);
+                }
+                // pass the payout accounts the winners reference, as the
+                // builder expects
+                let payout_accounts = winners
+                    .iter()
+                    .map(|winner| winner.transfer.sender.index().max(winner.transfer.receiver.index()) + 1)
+                    .max()
+                    .unwrap_or(0);
+                let winners = winners
+                    .iter()
+                    .map(|winner| Winner {
+                        amount: winner.amount,
+                        sender: winner.transfer.sender.index() as u8,
+                        receiver: winner.transfer.receiver.index() as u8,
+                        transfer_hash: winner.transfer.hash,
+                        transfer_slot: transfer_slot(&winner.transfer),
+                    })
+                    .collect();
+                instruction::batch_payout(
+                    &program_id, &signer, &token.fluid_mint, &token.pda, &token.data_account,
+                    &token.solend.obligation, &token.solend.reserve, &token.user_fluid[..payout_accounts], winners,
+                    name,
+                )
+            }
+            FuzzInstruction::CloseReceipt { transfer_hash, destination } => {
+                instruction::close_receipt(&program_id, &signer, &self.key(*destination), *transfer_hash)
+            }
+            FuzzInstruction::DrawPayout { seed, sender, receiver, index } => {
+                let (token, name) = self.token(seed);
+                self.add_receipt(&logged_transfer_hash(&token.fluid_mint, *index));
+                instruction::draw_payout(
+                    &program_id, &signer, &token.fluid_mint, &token.pda, &token.solend.obligation,
+                    &token.solend.reserve, &token.user_fluid[sender.index()], &token.user_fluid[receiver.index()],
+                    &token.data_account, &token.transfer_log, name, *index,
+                )
+            }
+            FuzzInstruction::SetDrawTiers { seed, draw_tiers } => {
+                let (token, name) = self.token(seed);
+                let mut tiers = [DrawTier::default(); DRAW_TIERS];
+                for (tier, fuzzed) in tiers.iter_mut().zip(draw_tiers.iter()) {
+                    *tier = (*fuzzed).into();
+                }
+                instruction::set_draw_tiers(&program_id, &signer, &token.data_account, name, tiers)
+            }
+            FuzzInstruction::RegisterToken { seed } => {
+                let (token, name) = self.token(seed);
+                let mut instruction = instruction::register_token(
+                    &program_id, &signer, &token.token_mint, &solend_mock::id(), &token.solend.reserve,
+                    &self.lending_market, &token.solend.collateral, token.seed.to_string(),
+                ).unwrap();
+                instruction.data = FluidityInstruction::RegisterToken(name).try_to_vec().unwrap();
+                instruction
+            }
+            FuzzInstruction::InitTransferLog { seed } => {
+                let (token, name) = self.token(seed);
+                instruction::init_transfer_log(
+                    &program_id, &signer, &token.pda, &token.data_account, &token.transfer_log, name,
+                )
+            }
+            FuzzInstruction::TransferFluid { seed, sender, receiver, amount } => {
+                let (token, name) = self.token(seed);
+                instruction::transfer_fluid(
+                    &program_id, &token.data_account, &token.pda, &token.user_fluid[sender.index()],
+                    &token.user_fluid[receiver.index()], &self.users[sender.index()], &token.transfer_log,
+                    *amount, name,
+                )
+            }
+            FuzzInstruction::PayoutTransfer { seed, amount, sender, receiver, index } => {
+                let (token, name) = self.token(seed);
+                self.add_receipt(&logged_transfer_hash(&token.fluid_mint, *index));
+                instruction::payout_transfer(
+                    &program_id, &signer, &token.fluid_mint, &token.pda, &token.solend.obligation,
+                    &token.solend.reserve, &token.user_fluid[sender.index()], &token.user_fluid[receiver.index()],
+                    &token.data_account, &token.transfer_log, *amount, name, *index,
+                )
+            }
+            FuzzInstruction::MigrateData { seed } => {
+                let (token, name) = self.token(seed);
+                let mut instruction = instruction::migrate_data(
+                    &program_id, &signer, &token.token_mint, &token.fluid_mint, &solend_mock::id(),
+                    &token.solend.reserve, &token.solend.obligation, &token.solend.collateral,
+                    token.seed.to_string(), LegacyLayout::Mints,
+                ).unwrap();
+                instruction.data = FluidityInstruction::MigrateData(name, LegacyLayout::Mints).try_to_vec().unwrap();
+                instruction
+            }
+            FuzzInstruction::DeregisterToken { seed } => {
+                let (token, name) = self.token(seed);
+                let mut instruction = instruction::deregister_token(
+                    &program_id, &signer, &token.token_mint, &token.fluid_mint, &token.treasury, &token.solend,
+                    token.seed.to_string(),
+                ).unwrap();
+                instruction.data = FluidityInstruction::DeregisterToken(name).try_to_vec().unwrap();
+                instruction
+            }
+            FuzzInstruction::CloseTvlData { destination } => {
+                instruction::close_tvl_data(&program_id, &signer, &self.tvl_base, &self.key(*destination)).unwrap()
+            }
+        };
+
+        if let Some(substitution) = step.substitution {
+            if !instruction.accounts.is_empty() {
+                let account = substitution.account as usize % instruction.accounts.len();
+                let replacement = substitution.replacement as usize % self.accounts.len();
+                instruction.accounts[account].pubkey = *self.accounts[replacement].key;
+            }
+        }
+
+        instruction
+    }
+
+    // the accounts of an instruction as the runtime passes them, each a
+    // signer or writable if any of its metas are, creating any the fixture
+    // doesn't have
+    fn account_infos(&mut self, instruction: &Instruction) -> Vec<AccountInfo<'bump>> {
+        for meta in &instruction.accounts {
+            self.add_uncreated(meta.pubkey, 0);
+        }
+
+        instruction
+            .accounts
+            .iter()
+            .map(|meta| {
+                let mut account = self.account(&meta.pubkey).unwrap().clone();
+                let metas = instruction.accounts.iter().filter(|other| other.pubkey == meta.pubkey);
+                account.is_signer = metas.clone().any(|other| other.is_signer);
+                account.is_writable = metas.clone().any(|other| other.is_writable);
+                account
+            })
+            .collect()
+    }
+
+    fn snapshot(&self) -> Vec<Snapshot> {
+        self.accounts
+            .iter()
+            .map(|account| Snapshot {
+                lamports: account.lamports(),
+                data: account.data.borrow().to_vec(),
+                owner: *account.owner,
+            })
+            .collect()
+    }
+
+    // give the accounts the instruction that just ran assigned their new
+    // owners
+    fn apply_assigned(&mut self) {
+        let assigned = system::take_assigned();
+        for account in self.accounts.iter_mut() {
+            if let Some(owner) = assigned.get(account.key) {
+                account.owner = system::owner_ref(owner);
+            }
+        }
+    }
+
+    // roll the fixture back, as the runtime does when an instruction fails
+    fn restore(&mut self, snapshot: &[Snapshot]) {
+        for (account, state) in self.accounts.iter_mut().zip(snapshot) {
+            **account.lamports.borrow_mut() = state.lamports;
+            account.data.borrow_mut().copy_from_slice(&state.data);
+            account.owner = system::owner_ref(&state.owner);
+        }
+    }
+
+    // wipe the accounts an instruction left without lamports, as the
+    // runtime does
+    fn collect_garbage(&mut self) {
+        for account in self.accounts.iter_mut().filter(|account| account.lamports() == 0) {
+            account.data.borrow_mut().iter_mut().for_each(|byte| *byte = 0);
+            account.owner = system::owner_ref(&system_program::id());
+        }
+    }
+
+    // run an instruction like the runtime, rolling it back if it fails
+    pub fn process(&mut self, instruction: &Instruction) -> ProgramResult {
+        let accounts = self.account_infos(instruction);
+        let before = self.snapshot();
+
+        let program_id = self.program_id;
+        let result = stubs::with_program(program_id, || {
+            fluidity::process_instruction(&program_id, &accounts, &instruction.data)
+        });
+        self.apply_assigned();
+
+        match result {
+            Ok(()) => {
+                self.check_runtime_rules(instruction, &before);
+                self.collect_garbage();
+            }
+            Err(_) => self.restore(&before),
+        }
+
+        result
+    }
+
+    // lamports can't be made or destroyed, and accounts passed read only
+    // can't change
+    fn check_runtime_rules(&self, instruction: &Instruction, before: &[Snapshot]) {
+        let after = self.snapshot();
+
+        let total = |snapshot: &[Snapshot]| snapshot.iter().map(|account| account.lamports as u128).sum::<u128>();
+        assert_eq!(total(before), total(&after), "lamports weren't conserved");
+
+        let writable: HashSet<Pubkey> = instruction
+            .accounts
+            .iter()
+            .filter(|meta| meta.is_writable)
+            .map(|meta| meta.pubkey)
+            .collect();
+        for ((account, before), after) in self.accounts.iter().zip(before).zip(&after) {
+            if !writable.contains(account.key) {
+                assert!(before == after, "read only account {} changed", account.key);
+            }
+        }
+    }
+
+    fn balance(&self, key: &Pubkey) -> u64 {
+        self.unpack::<Account>(key).map_or(0, |account| account.amount)
+    }
+
+    fn fluid_supply(&self, token: &Token) -> u64 {
+        self.unpack::<Mint>(&token.fluid_mint).map_or(0, |mint| mint.supply)
+    }
+
+    // base tokens the token's deposits are worth
+    fn deposited_value(&self, token: &Token) -> Option<u64> {
+        let obligation: Obligation = self.unpack(&token.solend.obligation)?;
+        let reserve: Reserve = self.unpack(&token.solend.reserve)?;
+        let deposit = obligation
+            .deposits
+            .iter()
+            .find(|deposit| deposit.deposit_reserve == token.solend.reserve)?;
+
+        reserve.collateral_exchange_rate().ok()?.collateral_to_liquidity(deposit.deposited_amount).ok()
+    }
+
+    // base tokens a unit of the token's collateral is worth, rounded up
+    fn collateral_unit_value(&self, token: &Token) -> u64 {
+        self.unpack::<Reserve>(&token.solend.reserve)
+            .and_then(|reserve| reserve.collateral_exchange_rate().ok())
+            .and_then(|exchange_rate| exchange_rate.collateral_to_liquidity(1).ok())
+            .map_or(0, |value| value + 1)
+    }
+
+    // what the deposits are worth beyond the fluid supply, which payouts
+    // can't mint more than
+    fn prize_pool(&self, token: &Token) -> u64 {
+        self.deposited_value(token)
+            .unwrap_or(0)
+            .saturating_sub(self.fluid_supply(token))
+    }
+
+    // the fluid supply is always the sum of the fluid token accounts
+    fn check_fluid_supply(&self, token: &Token) {
+        let mint = match self.unpack::<Mint>(&token.fluid_mint) {
+            Some(mint) => mint,
+            None => return,
+        };
+        let balances: u128 = self
+            .accounts
+            .iter()
+            .filter(|account| account.owner == &spl_token::id())
+            .filter_map(|account| Account::unpack(&account.data.borrow()).ok())
+            .filter(|account| account.mint == token.fluid_mint)
+            .map(|account| account.amount as u128)
+            .sum();
+
+        assert_eq!(mint.supply as u128, balances, "{} fluid supply isn't its balances", token.seed);
+    }
+
+    // run a step, checking the fluid supply only moves the way the
+    // instruction should move it
+    pub fn step(&mut self, step: &Step) {
+        let instruction = self.instruction(step);
+
+        let tokens = self.tokens.clone();
+        let supplies: Vec<u64> = tokens.iter().map(|token| self.fluid_supply(token)).collect();
+        let prize_pools: Vec<u64> = tokens.iter().map(|token| self.prize_pool(token)).collect();
+        let backed: Vec<bool> = tokens
+            .iter()
+            .map(|token| self.deposited_value(token).unwrap_or(0) >= self.fluid_supply(token))
+            .collect();
+        let user_balances: Vec<Vec<u64>> = tokens
+            .iter()
+            .map(|token| token.user_tokens.iter().map(|account| self.balance(account)).collect())
+            .collect();
+
+        if self.process(&instruction).is_err() {
+            return;
+        }
+
+        // the token the step's accounts are of
+        let stepped = match &step.instruction {
+            FuzzInstruction::Wrap { seed, .. }
+            | FuzzInstruction::Unwrap { seed, .. }
+            | FuzzInstruction::Payout { seed, .. }
+            | FuzzInstruction::BatchPayout { seed, .. }
+            | FuzzInstruction::DrawPayout { seed, .. }
+            | FuzzInstruction::PayoutTransfer { seed, .. } => match seed {
+                Seed::Listed => Some(1),
+                _ => Some(0),
+            },
+            _ => None,
+        };
+        // the accounts a substituted step moves tokens between can't be known
+        let exact = step.substitution.is_none();
+
+        for (index, token) in tokens.iter().enumerate() {
+            self.check_fluid_supply(token);
+
+            let before = supplies[index];
+            let after = self.fluid_supply(token);
+
+            match &step.instruction {
+                FuzzInstruction::Wrap { user, amount, min_out, .. } if exact && stepped == Some(index) => {
+                    let balance = self.balance(&token.user_tokens[user.index()]);
+                    assert_eq!(
+                        balance + amount,
+                        user_balances[index][user.index()],
+                        "wrap didn't take the amount wrapped",
+                    );
+
+                    // the amount is minted while the prize pool covers the
+                    // rounding, and what the collateral is worth otherwise
+                    assert!(after >= before, "wrap burnt fluid tokens");
+                    let minted = after - before;
+                    assert!(minted <= *amount, "wrap minted more than the amount wrapped");
+                    assert!(
+                        minted + self.collateral_unit_value(token) >= *amount,
+                        "wrap minted less than the amount wrapped",
+                    );
+                    assert!(minted >= min_out.unwrap_or(0), "wrap minted less than the minimum");
+                    assert!(
+                        !backed[index] || self.deposited_value(token).unwrap_or(0) >= after,
+                        "wrap left the fluid supply unbacked",
+                    );
+                }
+                FuzzInstruction::Unwrap { user, amount, min_out, .. } if exact && stepped == Some(index) => {
+                    let balance = self.balance(&token.user_tokens[user.index()]);
+                    assert_eq!(after + amount, before, "unwrap didn't burn the amount unwrapped");
+                    assert!(balance >= user_balances[index][user.index()], "unwrap took base tokens");
+
+                    // the withdrawal rounds up while the prize pool covers
+                    // it and down otherwise, to within a unit of collateral
+                    // of the amount, unless it took everything deposited
+                    let withdrawn = balance - user_balances[index][user.index()];
+                    let unit_value = self.collateral_unit_value(token);
+                    assert!(withdrawn >= min_out.unwrap_or(0), "unwrap paid less than the minimum");
+                    assert!(
+                        withdrawn + unit_value >= *amount || self.deposited_value(token).unwrap_or(0) == 0,
+                        "unwrap paid less than the amount unwrapped",
+                    );
+                    assert!(withdrawn <= amount + unit_value, "unwrap paid more than the amount unwrapped");
+
+                    // and never out of the deposits backing the rest
+                    assert!(
+                        !backed[index] || self.deposited_value(token).unwrap_or(0) >= after,
+                        "unwrap left the fluid supply unbacked",
+                    );
+                }
+                FuzzInstruction::Wrap { .. } | FuzzInstruction::Unwrap { .. } if !exact => {}
+                FuzzInstruction::Payout { .. }
+                | FuzzInstruction::BatchPayout { .. }
+                | FuzzInstruction::DrawPayout { .. }
+                | FuzzInstruction::PayoutTransfer { .. } => {
+                    assert!(after >= before, "a payout burnt fluid tokens");
+                    assert!(
+                        after - before <= prize_pools[index],
+                        "a payout minted {} from a prize pool of {}",
+                        after - before,
+                        prize_pools[index],
+                    );
+                }
+                _ => assert_eq!(after, before, "fluid supply changed"),
+            }
+        }
+    }
+}
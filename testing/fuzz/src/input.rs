@@ -0,0 +1,176 @@
+// What the fuzzer derives from its bytes: the state the fixture's tokens
+// start in, and the instructions run against them, which pick accounts and
+// signers out of the fixture rather than making up keys
+
+use arbitrary::Arbitrary;
+use fluidity::instruction::{DrawTier, Role, DRAW_TIERS};
+
+// number of users holding base and fluid tokens
+pub const USERS: usize = 2;
+
+// the state the registered token starts in, the listed token starting with
+// the same reserve and nothing deposited
+#[derive(Arbitrary, Debug, Clone)]
+pub struct TokenState {
+    // decimals of the base token, and of its fluid token
+    pub decimals: u8,
+    // base tokens held by each user
+    pub user_tokens: [u64; USERS],
+    // fluid tokens held by each user, making up the fluid supply
+    pub user_fluid: [u64; USERS],
+    // base tokens held by the reserve
+    pub available_liquidity: u64,
+    // base tokens borrowed from the reserve
+    pub borrowed: u64,
+    // the reserve's yearly borrow rate, as a percentage
+    pub borrow_rate: u8,
+    // collateral minted by the reserve, all of it deposited in obligations
+    pub collateral_supply: u64,
+    // collateral deposited by the obligation authority, at most the supply
+    pub deposited_collateral: u64,
+    // the market price of a whole base token, in the quote currency
+    pub market_price: u64,
+    // slots passed since the reserve was refreshed
+    pub elapsed_slots: u16,
+}
+
+// a user of the fixture
+#[derive(Arbitrary, Debug, Clone, Copy, PartialEq)]
+pub enum User {
+    First,
+    Second,
+}
+
+impl User {
+    pub fn index(self) -> usize {
+        match self {
+            User::First => 0,
+            User::Second => 1,
+        }
+    }
+}
+
+// a key signing or named by an instruction
+#[derive(Arbitrary, Debug, Clone, Copy, PartialEq)]
+pub enum Key {
+    // holds every role
+    Admin,
+    User(User),
+    // holds no role and no tokens
+    Stranger,
+}
+
+// the token an instruction names
+#[derive(Arbitrary, Debug, Clone, PartialEq)]
+pub enum Seed {
+    // registered, with deposits and fluid tokens
+    Registered,
+    // with a reserve in the lending market, but not registered
+    Listed,
+    // named arbitrarily, and passed the registered token's accounts
+    Other(String),
+}
+
+#[derive(Arbitrary, Debug, Clone, Copy)]
+pub enum FuzzRole {
+    Admin,
+    PayoutOperator,
+    RegistryAdmin,
+    Guardian,
+}
+
+impl From<FuzzRole> for Role {
+    fn from(role: FuzzRole) -> Self {
+        match role {
+            FuzzRole::Admin => Role::Admin,
+            FuzzRole::PayoutOperator => Role::PayoutOperator,
+            FuzzRole::RegistryAdmin => Role::RegistryAdmin,
+            FuzzRole::Guardian => Role::Guardian,
+        }
+    }
+}
+
+#[derive(Arbitrary, Debug, Clone, Copy)]
+pub struct FuzzDrawTier {
+    pub odds: u32,
+    pub prize: u64,
+}
+
+impl From<FuzzDrawTier> for DrawTier {
+    fn from(tier: FuzzDrawTier) -> Self {
+        DrawTier { odds: tier.odds, prize: tier.prize }
+    }
+}
+
+// a transfer between users paid out by a payout
+#[derive(Arbitrary, Debug, Clone)]
+pub struct Transfer {
+    pub hash: [u8; 32],
+    // slots between the transfer and the fixture's clock
+    pub slots_ago: u32,
+    pub sender: User,
+    pub receiver: User,
+}
+
+#[derive(Arbitrary, Debug, Clone)]
+pub struct FuzzWinner {
+    pub amount: u64,
+    pub transfer: Transfer,
+}
+
+// a FluidityInstruction, with the accounts it's passed picked from the
+// fixture by the fields that aren't its own
+#[derive(Arbitrary, Debug, Clone)]
+pub enum FuzzInstruction {
+    // WrapMinOut and UnwrapMinOut when given a minimum
+    Wrap { seed: Seed, user: User, amount: u64, min_out: Option<u64> },
+    Unwrap { seed: Seed, user: User, amount: u64, min_out: Option<u64> },
+    Payout { seed: Seed, amount: u64, transfer: Transfer },
+    InitSolendObligation { seed: Seed, lamports: u64, size: u64 },
+    LogTvl { seeds: Vec<Seed> },
+    InitData { seed: Seed, lamports: u64, space: u64 },
+    InitConfig { admin: Key },
+    ProposeAdmin { new_admin: Key },
+    AcceptAdmin,
+    GrantRole { role: FuzzRole, key: Key },
+    RevokeRole { role: FuzzRole },
+    SetPaused { seed: Option<Seed>, paused: bool, unwrap_paused: bool },
+    SetPayoutParams { seed: Seed, prize_pool_cap_bps: u16, sender_share_bps: u16 },
+    BatchPayout { seed: Seed, winners: Vec<FuzzWinner> },
+    CloseReceipt { transfer_hash: [u8; 32], destination: Key },
+    // transfers are logged at the fixture's slot, after which no slot has
+    // been hashed, so draws fail once they've read the log
+    DrawPayout { seed: Seed, sender: User, receiver: User, index: u64 },
+    SetDrawTiers { seed: Seed, draw_tiers: [FuzzDrawTier; DRAW_TIERS] },
+    RegisterToken { seed: Seed },
+    InitTransferLog { seed: Seed },
+    TransferFluid { seed: Seed, sender: User, receiver: User, amount: u64 },
+    PayoutTransfer { seed: Seed, amount: u64, sender: User, receiver: User, index: u64 },
+    MigrateData { seed: Seed },
+    DeregisterToken { seed: Seed },
+    CloseTvlData { destination: Key },
+}
+
+// replace an account of an instruction with one of the fixture's, keeping
+// the flags it was passed with, each index taken modulo the count
+#[derive(Arbitrary, Debug, Clone, Copy)]
+pub struct Substitution {
+    pub account: u8,
+    pub replacement: u8,
+}
+
+// an instruction signed by a key, as the holder of the role it needs or
+// its payer - the instructions moving a user's tokens are signed by the user
+#[derive(Arbitrary, Debug, Clone)]
+pub struct Step {
+    pub signer: Key,
+    pub instruction: FuzzInstruction,
+    pub substitution: Option<Substitution>,
+}
+
+impl Step {
+    // run by the admin, with the accounts the instruction needs
+    pub fn new(instruction: FuzzInstruction) -> Self {
+        Self { signer: Key::Admin, instruction, substitution: None }
+    }
+}
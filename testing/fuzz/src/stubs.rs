@@ -0,0 +1,123 @@
+// Native stand-ins for the syscalls fluidity makes, so its cross program
+// invocations run the spl-token program, the system program and the mock
+// Solend in process, with the runtime's privilege checks
+
+use crate::{solend_mock, system};
+
+use {
+    solana_program::{
+        account_info::AccountInfo,
+        clock::Clock,
+        entrypoint::{ProgramResult, SUCCESS},
+        instruction::Instruction,
+        msg,
+        program_error::ProgramError,
+        program_stubs::{set_syscall_stubs, SyscallStubs},
+        pubkey::Pubkey,
+        rent::Rent,
+        system_program,
+    },
+    std::{cell::RefCell, sync::Once},
+};
+
+thread_local! {
+    // the programs running, the innermost last, each signing for the
+    // addresses derived from its own
+    static PROGRAMS: RefCell<Vec<Pubkey>> = RefCell::new(vec![]);
+
+    // the clock the sysvar getter returns
+    static CLOCK: RefCell<Clock> = RefCell::new(Clock::default());
+}
+
+static INSTALL: Once = Once::new();
+
+struct FuzzStubs;
+
+impl SyscallStubs for FuzzStubs {
+    // the programs log a lot, which slows fuzzing down
+    fn sol_log(&self, _message: &str) {}
+
+    fn sol_log_compute_units(&self) {}
+
+    fn sol_invoke_signed(
+        &self,
+        instruction: &Instruction,
+        account_infos: &[AccountInfo],
+        signers_seeds: &[&[&[u8]]],
+    ) -> ProgramResult {
+        let caller = PROGRAMS.with(|programs| *programs.borrow().last().expect("no program running"));
+        let signers = signers_seeds
+            .iter()
+            .map(|seeds| Pubkey::create_program_address(seeds, &caller))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // pass the callee the caller's accounts with the instruction's
+        // flags, which can only be those the caller has or signs for
+        let mut accounts = Vec::with_capacity(instruction.accounts.len());
+        for meta in &instruction.accounts {
+            let account = account_infos
+                .iter()
+                .find(|account| account.key == &meta.pubkey)
+                .ok_or_else(|| {
+                    msg!("instruction references an unknown account {}", meta.pubkey);
+                    ProgramError::NotEnoughAccountKeys
+                })?;
+            if meta.is_signer && !account.is_signer && !signers.contains(account.key) {
+                msg!("{} didn't sign", meta.pubkey);
+                return Err(ProgramError::MissingRequiredSignature);
+            }
+            if meta.is_writable && !account.is_writable {
+                msg!("{} isn't writable", meta.pubkey);
+                return Err(ProgramError::InvalidArgument);
+            }
+
+            let mut account = account.clone();
+            account.is_signer = meta.is_signer;
+            account.is_writable = meta.is_writable;
+            account.owner = system::owner_ref(&system::owner(&account));
+            accounts.push(account);
+        }
+
+        with_program(instruction.program_id, || {
+            if instruction.program_id == spl_token::id() {
+                spl_token::processor::Processor::process(&instruction.program_id, &accounts, &instruction.data)
+            } else if instruction.program_id == system_program::id() {
+                system::process_instruction(&accounts, &instruction.data)
+            } else if instruction.program_id == solend_mock::id() {
+                solend_mock::process_instruction(&instruction.program_id, &accounts, &instruction.data)
+            } else {
+                msg!("no stub for program {}", instruction.program_id);
+                Err(ProgramError::IncorrectProgramId)
+            }
+        })
+    }
+
+    fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+        let clock = CLOCK.with(|clock| clock.borrow().clone());
+        unsafe { *(var_addr as *mut Clock) = clock };
+        SUCCESS
+    }
+
+    fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+        unsafe { *(var_addr as *mut Rent) = Rent::default() };
+        SUCCESS
+    }
+}
+
+// route the syscalls of the programs run on this thread to the stubs, with
+// the clock the sysvar getter returns
+pub fn install(clock: &Clock) {
+    INSTALL.call_once(|| {
+        set_syscall_stubs(Box::new(FuzzStubs));
+    });
+    CLOCK.with(|current| *current.borrow_mut() = clock.clone());
+}
+
+// run a program, which signs for its derived addresses while it runs
+pub fn with_program<T>(program_id: Pubkey, run: impl FnOnce() -> T) -> T {
+    PROGRAMS.with(|programs| programs.borrow_mut().push(program_id));
+    let result = run();
+    PROGRAMS.with(|programs| programs.borrow_mut().pop());
+
+    result
+}
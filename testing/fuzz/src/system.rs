@@ -0,0 +1,175 @@
+// The parts of the system program fluidity invokes. Accounts can't be
+// resized in place, so the fixture creates each account it expects to be
+// allocated with its final size, zeroed and without lamports. An
+// AccountInfo only borrows its owner, so accounts assigned while an
+// instruction runs are recorded here, passed to later invocations with their
+// new owner, and rebuilt by the fixture once the instruction returns
+
+use {
+    solana_program::{
+        account_info::{next_account_info, AccountInfo},
+        entrypoint::ProgramResult,
+        msg,
+        program_error::ProgramError,
+        pubkey::Pubkey,
+        system_instruction::{SystemError, SystemInstruction},
+        system_program,
+    },
+    std::{cell::RefCell, collections::HashMap},
+};
+
+thread_local! {
+    // the owners of the accounts assigned by the running instruction
+    static ASSIGNED: RefCell<HashMap<Pubkey, Pubkey>> = RefCell::new(HashMap::new());
+
+    // a copy of every owner handed out, which AccountInfos can borrow for
+    // as long as they live - there are only a few programs to own accounts
+    static OWNERS: RefCell<HashMap<Pubkey, &'static Pubkey>> = RefCell::new(HashMap::new());
+}
+
+pub fn process_instruction(accounts: &[AccountInfo], input: &[u8]) -> ProgramResult {
+    let instruction: SystemInstruction =
+        bincode::deserialize(input).map_err(|_| ProgramError::InvalidInstructionData)?;
+    let accounts_iter = &mut accounts.iter();
+
+    match instruction {
+        SystemInstruction::CreateAccount { lamports, space, owner } => {
+            let from = next_account_info(accounts_iter)?;
+            let to = next_account_info(accounts_iter)?;
+
+            create_account(from, to, to.is_signer, lamports, space, &owner)
+        }
+        SystemInstruction::CreateAccountWithSeed { base, seed, lamports, space, owner } => {
+            let from = next_account_info(accounts_iter)?;
+            let to = next_account_info(accounts_iter)?;
+
+            if to.key != &Pubkey::create_with_seed(&base, &seed, &owner)? {
+                msg!("{} isn't derived from {} and {}", to.key, base, seed);
+                return Err(system_error(SystemError::AddressWithSeedMismatch));
+            }
+
+            // the base signs for the address, and is the payer if not passed
+            // after it
+            let base_signed = accounts.iter()
+                .any(|account| account.key == &base && account.is_signer);
+
+            create_account(from, to, base_signed, lamports, space, &owner)
+        }
+        SystemInstruction::Transfer { lamports } => {
+            let from = next_account_info(accounts_iter)?;
+            let to = next_account_info(accounts_iter)?;
+
+            transfer(from, to, lamports)
+        }
+        SystemInstruction::Allocate { space } => {
+            let account = next_account_info(accounts_iter)?;
+
+            allocate(account, account.is_signer, space)
+        }
+        SystemInstruction::Assign { owner } => {
+            let account = next_account_info(accounts_iter)?;
+
+            assign(account, account.is_signer, &owner)
+        }
+        _ => {
+            msg!("system instruction not supported by the stubs");
+            Err(ProgramError::InvalidInstructionData)
+        }
+    }
+}
+
+fn create_account(
+    from: &AccountInfo,
+    to: &AccountInfo,
+    signed: bool,
+    lamports: u64,
+    space: u64,
+    owner: &Pubkey,
+) -> ProgramResult {
+    if to.lamports() > 0 {
+        msg!("{} already in use", to.key);
+        return Err(system_error(SystemError::AccountAlreadyInUse));
+    }
+
+    allocate(to, signed, space)?;
+    assign(to, signed, owner)?;
+    transfer(from, to, lamports)
+}
+
+fn transfer(from: &AccountInfo, to: &AccountInfo, lamports: u64) -> ProgramResult {
+    if !from.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if from.owner != &system_program::id() || from.data_len() > 0 {
+        msg!("{} can't send lamports", from.key);
+        return Err(ProgramError::InvalidArgument);
+    }
+    if from.lamports() < lamports {
+        return Err(system_error(SystemError::ResultWithNegativeLamports));
+    }
+
+    **from.try_borrow_mut_lamports()? -= lamports;
+    **to.try_borrow_mut_lamports()? = to.lamports()
+        .checked_add(lamports)
+        .ok_or(ProgramError::InvalidArgument)?;
+
+    Ok(())
+}
+
+// check an account being allocated or assigned is signed for, and isn't in
+// use already
+fn check_unused(account: &AccountInfo, signed: bool) -> ProgramResult {
+    if !signed {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if owner(account) != system_program::id() || account.data.borrow().iter().any(|byte| *byte != 0) {
+        msg!("{} already in use", account.key);
+        return Err(system_error(SystemError::AccountAlreadyInUse));
+    }
+
+    Ok(())
+}
+
+fn allocate(account: &AccountInfo, signed: bool, space: u64) -> ProgramResult {
+    check_unused(account, signed)?;
+
+    if account.data_len() as u64 != space {
+        msg!("the stubs can't resize {} to {} bytes", account.key, space);
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    Ok(())
+}
+
+fn assign(account: &AccountInfo, signed: bool, owner: &Pubkey) -> ProgramResult {
+    check_unused(account, signed)?;
+
+    ASSIGNED.with(|assigned| assigned.borrow_mut().insert(*account.key, *owner));
+
+    Ok(())
+}
+
+fn system_error(error: SystemError) -> ProgramError {
+    ProgramError::Custom(error as u32)
+}
+
+// the owner of an account, including any assignment by the running instruction
+pub fn owner(account: &AccountInfo) -> Pubkey {
+    ASSIGNED.with(|assigned| assigned.borrow().get(account.key).copied())
+        .unwrap_or(*account.owner)
+}
+
+// take the accounts the instruction that just ran assigned, and their owners
+pub fn take_assigned() -> HashMap<Pubkey, Pubkey> {
+    ASSIGNED.with(|assigned| assigned.take())
+}
+
+// an owner for an AccountInfo to borrow
+pub fn owner_ref(owner: &Pubkey) -> &'static Pubkey {
+    OWNERS.with(|owners| {
+        *owners
+            .borrow_mut()
+            .entry(*owner)
+            .or_insert_with(|| Box::leak(Box::new(*owner)))
+    })
+}
@@ -0,0 +1,461 @@
+// Registers, wraps, pays out and unwraps a token against a mock Solend,
+// with `cargo test-bpf`. Both programs are registered with their native
+// processors, which ProgramTest runs unless it finds a BPF build of them
+#![cfg(feature = "test-bpf")]
+
+mod solend_mock;
+
+use fluidity::{
+    error::FluidityError,
+    instruction::{self, LendingInstruction, Role, SolendAccounts},
+    math::Decimal,
+    processor::{FluidityConfig, FluidityData, CONFIG_SPACE},
+    state::{
+        InitLendingMarketParams, InitReserveParams, LendingMarket, NewReserveCollateralParams,
+        NewReserveLiquidityParams, Obligation, Reserve, ReserveCollateral, ReserveConfig, ReserveLiquidity,
+    },
+};
+
+use {
+    borsh::BorshSerialize,
+    solana_program::{
+        instruction::{AccountMeta, Instruction, InstructionError},
+        program_option::COption,
+        program_pack::Pack,
+        pubkey::Pubkey,
+        rent::Rent,
+        system_program,
+        sysvar,
+    },
+    solana_program_test::{processor, ProgramTest, ProgramTestContext},
+    solana_sdk::{
+        account::Account,
+        signature::{Keypair, Signer},
+        transaction::{Transaction, TransactionError},
+    },
+};
+
+const SEED: &str = "USDC";
+
+const DECIMALS: u8 = 6;
+
+// base tokens the user starts with and wraps
+const USER_TOKENS: u64 = 10_000_000;
+const WRAPPED: u64 = 1_000_000;
+
+// liquidity someone else deposited and borrowed out, earning interest for
+// every depositor
+const BORROWED: u64 = 1_000_000_000;
+
+// the reserve's borrow rate, as a yearly percentage
+const BORROW_RATE: u8 = 250;
+
+// slot the tests warp to for interest to accrue
+const WARP_SLOT: u64 = 100_000;
+
+// the accounts of a token registered against the mock
+struct Token {
+    program_id: Pubkey,
+    // holds every role
+    authority: Keypair,
+    user: Keypair,
+    token_mint: Pubkey,
+    fluid_mint: Pubkey,
+    pda: Pubkey,
+    data_account: Pubkey,
+    user_token: Pubkey,
+    user_fluid: Pubkey,
+    receiver_fluid: Pubkey,
+    solend: SolendAccounts,
+}
+
+impl Token {
+    fn wrap(&self, amount: u64) -> Instruction {
+        instruction::wrap(
+            &self.program_id, &self.data_account, &self.token_mint, &self.fluid_mint, &self.pda,
+            &self.user.pubkey(), &self.user_token, &self.user_fluid, &self.solend, amount, SEED.to_string(),
+        )
+    }
+
+    fn unwrap(&self, amount: u64) -> Instruction {
+        instruction::unwrap(
+            &self.program_id, &self.data_account, &self.token_mint, &self.fluid_mint, &self.pda,
+            &self.user.pubkey(), &self.user_token, &self.user_fluid, &self.solend, amount, SEED.to_string(),
+        )
+    }
+
+    fn wrap_min_out(&self, amount: u64, min_out: u64) -> Instruction {
+        instruction::wrap_min_out(
+            &self.program_id, &self.data_account, &self.token_mint, &self.fluid_mint, &self.pda,
+            &self.user.pubkey(), &self.user_token, &self.user_fluid, &self.solend, amount, min_out,
+            SEED.to_string(),
+        )
+    }
+
+    fn unwrap_min_out(&self, amount: u64, min_out: u64) -> Instruction {
+        instruction::unwrap_min_out(
+            &self.program_id, &self.data_account, &self.token_mint, &self.fluid_mint, &self.pda,
+            &self.user.pubkey(), &self.user_token, &self.user_fluid, &self.solend, amount, min_out,
+            SEED.to_string(),
+        )
+    }
+
+    fn payout(&self, amount: u64, transfer_hash: [u8; 32], transfer_slot: u64) -> Instruction {
+        instruction::payout(
+            &self.program_id, &self.authority.pubkey(), &self.fluid_mint, &self.pda, &self.solend.obligation,
+            &self.solend.reserve, &self.user_fluid, &self.receiver_fluid, &self.data_account, amount,
+            SEED.to_string(), transfer_hash, transfer_slot,
+        )
+    }
+
+    // accrue interest on the reserve, which payouts value the pool with
+    fn refresh_reserve(&self) -> Instruction {
+        Instruction::new_with_borsh(
+            self.solend.solend_program,
+            &LendingInstruction::RefreshReserve,
+            vec![
+                AccountMeta::new(self.solend.reserve, false),
+                AccountMeta::new_readonly(self.solend.pyth_price, false),
+                AccountMeta::new_readonly(self.solend.switchboard_feed, false),
+                AccountMeta::new_readonly(sysvar::clock::id(), false),
+            ],
+        )
+    }
+}
+
+fn packed<T: Pack>(state: T, owner: &Pubkey) -> Account {
+    let mut data = vec![0; T::LEN];
+    T::pack(state, &mut data).unwrap();
+
+    Account {
+        lamports: Rent::default().minimum_balance(T::LEN),
+        data,
+        owner: *owner,
+        executable: false,
+        rent_epoch: 0,
+    }
+}
+
+fn mint(authority: &Pubkey, supply: u64) -> Account {
+    packed(
+        spl_token::state::Mint {
+            mint_authority: COption::Some(*authority),
+            supply,
+            decimals: DECIMALS,
+            is_initialized: true,
+            freeze_authority: COption::None,
+        },
+        &spl_token::id(),
+    )
+}
+
+fn token_account(mint: &Pubkey, owner: &Pubkey, amount: u64) -> Account {
+    packed(
+        spl_token::state::Account {
+            mint: *mint,
+            owner: *owner,
+            amount,
+            state: spl_token::state::AccountState::Initialized,
+            ..Default::default()
+        },
+        &spl_token::id(),
+    )
+}
+
+fn wallet() -> Account {
+    Account::new(10_000_000_000, 0, &system_program::id())
+}
+
+// set up the mock's lending market and reserve, the mints and the user's
+// token accounts, then register the token with InitData and
+// InitSolendObligation
+async fn setup() -> (ProgramTestContext, Token) {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = ProgramTest::new("fluidity", program_id, processor!(fluidity::process_instruction));
+    program_test.add_program("solend_mock", solend_mock::id(), processor!(solend_mock::process_instruction));
+
+    let authority = Keypair::new();
+    let user = Keypair::new();
+    program_test.add_account(authority.pubkey(), wallet());
+    program_test.add_account(user.pubkey(), wallet());
+
+    // the config account InitConfig would create, which needs the deploy
+    // authority's key, with the authority holding every role
+    let (config_address, bump) = instruction::find_config_address(&program_id);
+    let mut config = FluidityConfig::new(authority.pubkey(), bump);
+    for role in &[Role::PayoutOperator, Role::RegistryAdmin, Role::Guardian] {
+        *config.role_mut(*role).unwrap() = authority.pubkey();
+    }
+    let mut config_data = vec![0; CONFIG_SPACE as usize];
+    config.serialize(&mut &mut config_data[..]).unwrap();
+    program_test.add_account(config_address, Account {
+        lamports: Rent::default().minimum_balance(CONFIG_SPACE as usize),
+        data: config_data,
+        owner: program_id,
+        executable: false,
+        rent_epoch: 0,
+    });
+
+    let (pda, _) = instruction::find_obligation_authority_address(&program_id, SEED);
+    let data_account = instruction::find_data_address(&program_id, SEED).unwrap();
+
+    let token_mint = Pubkey::new_unique();
+    let fluid_mint = Pubkey::new_unique();
+    program_test.add_account(token_mint, mint(&Pubkey::new_unique(), USER_TOKENS));
+    program_test.add_account(fluid_mint, mint(&pda, 0));
+
+    let lending_market = Pubkey::new_unique();
+    let (lending_market_authority, lending_market_bump) =
+        solend_mock::find_lending_market_authority(&lending_market);
+    program_test.add_account(lending_market, packed(
+        LendingMarket::new(InitLendingMarketParams {
+            bump_seed: lending_market_bump,
+            owner: Pubkey::new_unique(),
+            quote_currency: *b"USD\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0",
+            token_program_id: spl_token::id(),
+            oracle_program_id: Pubkey::new_unique(),
+            switchboard_oracle_program_id: Pubkey::new_unique(),
+        }),
+        &solend_mock::id(),
+    ));
+
+    let reserve = Pubkey::new_unique();
+    let liquidity_supply = Pubkey::new_unique();
+    let collateral_mint = Pubkey::new_unique();
+    let collateral_supply = Pubkey::new_unique();
+    let pyth_price = Pubkey::new_unique();
+    let switchboard_feed = Pubkey::new_unique();
+    let mut reserve_state = Reserve::new(InitReserveParams {
+        current_slot: 0,
+        lending_market,
+        liquidity: ReserveLiquidity::new(NewReserveLiquidityParams {
+            mint_pubkey: token_mint,
+            mint_decimals: DECIMALS,
+            supply_pubkey: liquidity_supply,
+            pyth_oracle_pubkey: pyth_price,
+            switchboard_oracle_pubkey: switchboard_feed,
+            market_price: Decimal::one(),
+        }),
+        collateral: ReserveCollateral::new(NewReserveCollateralParams {
+            mint_pubkey: collateral_mint,
+            supply_pubkey: collateral_supply,
+        }),
+        config: ReserveConfig {
+            optimal_utilization_rate: 100,
+            min_borrow_rate: BORROW_RATE,
+            optimal_borrow_rate: BORROW_RATE,
+            max_borrow_rate: BORROW_RATE,
+            deposit_limit: u64::MAX,
+            ..Default::default()
+        },
+    });
+    reserve_state.liquidity.borrowed_amount_wads = Decimal::from(BORROWED);
+    reserve_state.collateral.mint_total_supply = BORROWED;
+    program_test.add_account(reserve, packed(reserve_state, &solend_mock::id()));
+    program_test.add_account(liquidity_supply, token_account(&token_mint, &lending_market_authority, 0));
+    program_test.add_account(collateral_mint, mint(&lending_market_authority, BORROWED));
+    program_test.add_account(collateral_supply, token_account(&collateral_mint, &lending_market_authority, BORROWED));
+
+    let obligation = Pubkey::create_with_seed(&pda, &lending_market.to_string()[0..32], &solend_mock::id()).unwrap();
+    let collateral = Pubkey::new_unique();
+    program_test.add_account(collateral, token_account(&collateral_mint, &pda, 0));
+
+    let user_token = Pubkey::new_unique();
+    let user_fluid = Pubkey::new_unique();
+    let receiver_fluid = Pubkey::new_unique();
+    program_test.add_account(user_token, token_account(&token_mint, &user.pubkey(), USER_TOKENS));
+    program_test.add_account(user_fluid, token_account(&fluid_mint, &user.pubkey(), 0));
+    program_test.add_account(receiver_fluid, token_account(&fluid_mint, &Pubkey::new_unique(), 0));
+
+    let mut context = program_test.start_with_context().await;
+    let rent = context.banks_client.get_rent().await.unwrap();
+
+    let token = Token {
+        program_id,
+        authority,
+        user,
+        token_mint,
+        fluid_mint,
+        pda,
+        data_account,
+        user_token,
+        user_fluid,
+        receiver_fluid,
+        solend: SolendAccounts {
+            solend_program: solend_mock::id(),
+            collateral,
+            reserve,
+            reserve_liquidity_supply: liquidity_supply,
+            reserve_collateral_mint: collateral_mint,
+            lending_market,
+            lending_market_authority,
+            reserve_collateral_supply: collateral_supply,
+            obligation,
+            pyth_price,
+            switchboard_feed,
+        },
+    };
+
+    let init_data = instruction::init_data(
+        &program_id, &token.authority.pubkey(), &data_account, &token_mint, &fluid_mint, &pda,
+        &solend_mock::id(), &reserve, &obligation, &collateral, SEED.to_string(),
+        rent.minimum_balance(FluidityData::LEN), FluidityData::LEN as u64,
+    );
+    let init_obligation = instruction::init_solend_obligation(
+        &program_id, &token.authority.pubkey(), &solend_mock::id(), &obligation, &lending_market, &pda,
+        &data_account, rent.minimum_balance(Obligation::LEN), Obligation::LEN as u64, SEED.to_string(),
+    );
+    process(&mut context, &[init_data, init_obligation], &[&token.authority]).await.unwrap();
+
+    (context, token)
+}
+
+async fn process(
+    context: &mut ProgramTestContext,
+    instructions: &[Instruction],
+    signers: &[&Keypair],
+) -> Result<(), TransactionError> {
+    let recent_blockhash = context.banks_client.get_recent_blockhash().await.unwrap();
+    let mut all_signers = vec![&context.payer];
+    all_signers.extend(signers);
+
+    let transaction = Transaction::new_signed_with_payer(
+        instructions,
+        Some(&context.payer.pubkey()),
+        &all_signers,
+        recent_blockhash,
+    );
+
+    context.banks_client.process_transaction(transaction).await.map_err(|err| err.unwrap())
+}
+
+async fn load<T: Pack>(context: &mut ProgramTestContext, address: &Pubkey) -> T {
+    let account = context.banks_client.get_account(*address).await.unwrap().unwrap();
+    T::unpack(&account.data).unwrap()
+}
+
+async fn balance(context: &mut ProgramTestContext, address: &Pubkey) -> u64 {
+    load::<spl_token::state::Account>(context, address).await.amount
+}
+
+#[tokio::test]
+async fn test_register_wrap_payout_unwrap() {
+    let (mut context, token) = setup().await;
+
+    let obligation: Obligation = load(&mut context, &token.solend.obligation).await;
+    assert_eq!(obligation.owner, token.pda);
+    assert!(obligation.deposits.is_empty());
+
+    // wrapping deposits into the reserve and credits the obligation
+    process(&mut context, &[token.wrap(WRAPPED)], &[&token.user]).await.unwrap();
+    assert_eq!(balance(&mut context, &token.user_token).await, USER_TOKENS - WRAPPED);
+    assert_eq!(balance(&mut context, &token.user_fluid).await, WRAPPED);
+    assert_eq!(balance(&mut context, &token.solend.reserve_liquidity_supply).await, WRAPPED);
+    let obligation: Obligation = load(&mut context, &token.solend.obligation).await;
+    assert_eq!(obligation.deposits.len(), 1);
+    assert_eq!(obligation.deposits[0].deposit_reserve, token.solend.reserve);
+    let collateral_amount = obligation.deposits[0].deposited_amount;
+    assert!(collateral_amount > 0);
+
+    // interest grows the deposit past the fluid supply, which pays for the
+    // prize, split 8:2 by default
+    context.warp_to_slot(WARP_SLOT).unwrap();
+    process(
+        &mut context,
+        &[token.refresh_reserve(), token.payout(1_000, [1; 32], 1)],
+        &[&token.authority],
+    ).await.unwrap();
+    assert_eq!(balance(&mut context, &token.user_fluid).await, WRAPPED + 800);
+    assert_eq!(balance(&mut context, &token.receiver_fluid).await, 200);
+
+    let reserve: Reserve = load(&mut context, &token.solend.reserve).await;
+    let deposited_value = reserve.collateral_exchange_rate().unwrap()
+        .collateral_to_liquidity(collateral_amount).unwrap();
+    assert!(deposited_value > WRAPPED + 1_000);
+
+    // unwrapping redeems the collateral for the base token, the interest
+    // paying for the rounding and backing the prizes
+    process(&mut context, &[token.unwrap(WRAPPED)], &[&token.user]).await.unwrap();
+    assert_eq!(balance(&mut context, &token.user_fluid).await, 800);
+    let received = balance(&mut context, &token.user_token).await - (USER_TOKENS - WRAPPED);
+    assert!(received >= WRAPPED && received <= WRAPPED + 2);
+
+    let obligation: Obligation = load(&mut context, &token.solend.obligation).await;
+    let reserve: Reserve = load(&mut context, &token.solend.reserve).await;
+    let remaining_value = reserve.collateral_exchange_rate().unwrap()
+        .collateral_to_liquidity(obligation.deposits[0].deposited_amount).unwrap();
+    let fluid_supply = load::<spl_token::state::Mint>(&mut context, &token.fluid_mint).await.supply;
+    assert_eq!(fluid_supply, 1_000);
+    assert!(remaining_value >= fluid_supply);
+}
+
+#[tokio::test]
+async fn test_payout_refuses_paid_transfer() {
+    let (mut context, token) = setup().await;
+
+    process(&mut context, &[token.wrap(WRAPPED)], &[&token.user]).await.unwrap();
+    context.warp_to_slot(WARP_SLOT).unwrap();
+    process(
+        &mut context,
+        &[token.refresh_reserve(), token.payout(1_000, [1; 32], 1)],
+        &[&token.authority],
+    ).await.unwrap();
+
+    // the receipt of the first payout refuses another for the same transfer
+    assert_eq!(
+        process(
+            &mut context,
+            &[token.refresh_reserve(), token.payout(500, [1; 32], 1)],
+            &[&token.authority],
+        ).await,
+        Err(TransactionError::InstructionError(
+            1,
+            InstructionError::Custom(FluidityError::PayoutAlreadyPaid as u32),
+        ))
+    );
+
+    // and only the payout operator can pay out
+    let payout = instruction::payout(
+        &token.program_id, &token.user.pubkey(), &token.fluid_mint, &token.pda, &token.solend.obligation,
+        &token.solend.reserve, &token.user_fluid, &token.receiver_fluid, &token.data_account, 1_000,
+        SEED.to_string(), [2; 32], 1,
+    );
+    assert_eq!(
+        process(&mut context, &[payout], &[&token.user]).await,
+        Err(TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(FluidityError::InvalidAuthority as u32),
+        ))
+    );
+}
+
+#[tokio::test]
+async fn test_min_out_refuses_shortfall() {
+    let (mut context, token) = setup().await;
+
+    // wrapping mints the amount wrapped, and no more
+    assert_eq!(
+        process(&mut context, &[token.wrap_min_out(WRAPPED, WRAPPED + 1)], &[&token.user]).await,
+        Err(TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(FluidityError::SlippageExceeded as u32),
+        ))
+    );
+    process(&mut context, &[token.wrap_min_out(WRAPPED, WRAPPED)], &[&token.user]).await.unwrap();
+    assert_eq!(balance(&mut context, &token.user_fluid).await, WRAPPED);
+
+    // once interest has accrued, unwrapping withdraws at least the amount
+    context.warp_to_slot(WARP_SLOT).unwrap();
+    process(&mut context, &[token.refresh_reserve()], &[]).await.unwrap();
+    assert_eq!(
+        process(&mut context, &[token.unwrap_min_out(WRAPPED / 2, WRAPPED / 2 + 1_000)], &[&token.user]).await,
+        Err(TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(FluidityError::SlippageExceeded as u32),
+        ))
+    );
+    process(&mut context, &[token.unwrap_min_out(WRAPPED / 2, WRAPPED / 2)], &[&token.user]).await.unwrap();
+    assert_eq!(balance(&mut context, &token.user_fluid).await, WRAPPED / 2);
+    let received = balance(&mut context, &token.user_token).await - (USER_TOKENS - WRAPPED);
+    assert!(received >= WRAPPED / 2);
+}
@@ -0,0 +1,425 @@
+// A native stand-in for the Solend program, implementing the instructions
+// fluidity invokes with the crate's own Reserve and Obligation state, so
+// flows can run in ProgramTest without a Solend build
+
+use fluidity::{
+    error::LendingError,
+    instruction::LendingInstruction,
+    math::{Decimal, TryAdd, TryDiv, TryMul},
+    state::{InitObligationParams, LendingMarket, Obligation, Reserve},
+};
+
+use {
+    borsh::BorshDeserialize,
+    solana_program::{
+        account_info::{next_account_info, AccountInfo},
+        clock::Clock,
+        entrypoint::ProgramResult,
+        msg,
+        program::invoke_signed,
+        program_error::ProgramError,
+        program_pack::{IsInitialized, Pack},
+        pubkey::Pubkey,
+        rent::Rent,
+        sysvar::Sysvar,
+    },
+    std::str::FromStr,
+};
+
+// the address fluidity expects the Solend program at
+pub fn id() -> Pubkey {
+    Pubkey::from_str("ALend7Ketfx5bxh6ghsCDXAoDrhvEmsXT3cynB6aPLgx").unwrap()
+}
+
+// derive a lending market's authority, which owns its reserves' supplies and
+// mints their collateral
+pub fn find_lending_market_authority(lending_market: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[lending_market.as_ref()], &id())
+}
+
+pub fn process_instruction(program_id: &Pubkey, accounts: &[AccountInfo], input: &[u8]) -> ProgramResult {
+    match LendingInstruction::try_from_slice(input)? {
+        LendingInstruction::RefreshReserve => {
+            refresh_reserve(program_id, accounts)
+        }
+        LendingInstruction::DepositReserveLiquidity { liquidity_amount } => {
+            deposit_reserve_liquidity(program_id, accounts, liquidity_amount)
+        }
+        LendingInstruction::InitObligation => {
+            init_obligation(program_id, accounts)
+        }
+        LendingInstruction::RefreshObligation => {
+            refresh_obligation(program_id, accounts)
+        }
+        LendingInstruction::DepositObligationCollateral { collateral_amount } => {
+            deposit_obligation_collateral(program_id, accounts, collateral_amount)
+        }
+        LendingInstruction::WithdrawObligationCollateralAndRedeemReserveCollateral { collateral_amount } => {
+            withdraw_and_redeem(program_id, accounts, collateral_amount)
+        }
+        _ => {
+            msg!("instruction not supported by the mock");
+            Err(LendingError::InstructionUnpackError.into())
+        }
+    }
+}
+
+// accrue interest since the last refresh, keeping the stored market price
+fn refresh_reserve(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let reserve_info = next_account_info(accounts_iter)?;
+    let pyth_price_info = next_account_info(accounts_iter)?;
+    let switchboard_feed_info = next_account_info(accounts_iter)?;
+    let clock = Clock::from_account_info(next_account_info(accounts_iter)?)?;
+
+    check_owner(reserve_info, program_id)?;
+    let mut reserve = Reserve::unpack(&reserve_info.data.borrow())?;
+    if pyth_price_info.key != &reserve.liquidity.pyth_oracle_pubkey ||
+        switchboard_feed_info.key != &reserve.liquidity.switchboard_oracle_pubkey {
+        msg!("bad oracles");
+        return Err(LendingError::InvalidOracleConfig.into());
+    }
+
+    reserve.accrue_interest(clock.slot)?;
+    reserve.last_update.update_slot(clock.slot);
+    Reserve::pack(reserve, &mut reserve_info.data.borrow_mut())
+}
+
+// take liquidity from the user, minting collateral at the reserve's rate
+fn deposit_reserve_liquidity(program_id: &Pubkey, accounts: &[AccountInfo], liquidity_amount: u64) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let source_liquidity = next_account_info(accounts_iter)?;
+    let destination_collateral = next_account_info(accounts_iter)?;
+    let reserve_info = next_account_info(accounts_iter)?;
+    let liquidity_supply = next_account_info(accounts_iter)?;
+    let collateral_mint = next_account_info(accounts_iter)?;
+    let lending_market_info = next_account_info(accounts_iter)?;
+    let lending_market_authority = next_account_info(accounts_iter)?;
+    let user_transfer_authority = next_account_info(accounts_iter)?;
+    let clock = Clock::from_account_info(next_account_info(accounts_iter)?)?;
+    let token_program = next_account_info(accounts_iter)?;
+
+    if liquidity_amount == 0 {
+        return Err(LendingError::InvalidAmount.into());
+    }
+
+    let mut reserve = load_fresh_reserve(program_id, reserve_info, clock.slot)?;
+    let bump = load_lending_market(program_id, lending_market_info, lending_market_authority, token_program)?;
+    if &reserve.lending_market != lending_market_info.key {
+        msg!("reserve is of another lending market");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+    if &reserve.liquidity.supply_pubkey != liquidity_supply.key ||
+        &reserve.collateral.mint_pubkey != collateral_mint.key {
+        msg!("bad reserve accounts");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+
+    let collateral_amount = reserve.deposit_liquidity(liquidity_amount)?;
+    reserve.last_update.mark_stale();
+    Reserve::pack(reserve, &mut reserve_info.data.borrow_mut())?;
+
+    invoke_signed(
+        &spl_token::instruction::transfer(
+            token_program.key,
+            source_liquidity.key,
+            liquidity_supply.key,
+            user_transfer_authority.key,
+            &[],
+            liquidity_amount,
+        )?,
+        &[source_liquidity.clone(), liquidity_supply.clone(), user_transfer_authority.clone(), token_program.clone()],
+        &[],
+    )?;
+
+    invoke_signed(
+        &spl_token::instruction::mint_to(
+            token_program.key,
+            collateral_mint.key,
+            destination_collateral.key,
+            lending_market_authority.key,
+            &[],
+            collateral_amount,
+        )?,
+        &[
+            collateral_mint.clone(), destination_collateral.clone(),
+            lending_market_authority.clone(), token_program.clone(),
+        ],
+        &[&[lending_market_info.key.as_ref(), &[bump]]],
+    )
+}
+
+// initialise an obligation created by its owner
+fn init_obligation(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let obligation_info = next_account_info(accounts_iter)?;
+    let lending_market_info = next_account_info(accounts_iter)?;
+    let obligation_owner = next_account_info(accounts_iter)?;
+    let clock = Clock::from_account_info(next_account_info(accounts_iter)?)?;
+    let rent = Rent::from_account_info(next_account_info(accounts_iter)?)?;
+
+    check_owner(obligation_info, program_id)?;
+    check_owner(lending_market_info, program_id)?;
+    if !obligation_owner.is_signer {
+        msg!("obligation owner must sign");
+        return Err(LendingError::InvalidSigner.into());
+    }
+    if !rent.is_exempt(obligation_info.lamports(), obligation_info.data_len()) {
+        return Err(LendingError::NotRentExempt.into());
+    }
+
+    if Obligation::unpack_unchecked(&obligation_info.data.borrow())?.is_initialized() {
+        return Err(LendingError::AlreadyInitialized.into());
+    }
+
+    let obligation = Obligation::new(InitObligationParams {
+        current_slot: clock.slot,
+        lending_market: *lending_market_info.key,
+        owner: *obligation_owner.key,
+        deposits: vec![],
+        borrows: vec![],
+    });
+    Obligation::pack(obligation, &mut obligation_info.data.borrow_mut())
+}
+
+// value each deposit through its refreshed reserve
+fn refresh_obligation(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let obligation_info = next_account_info(accounts_iter)?;
+    let clock = Clock::from_account_info(next_account_info(accounts_iter)?)?;
+
+    check_owner(obligation_info, program_id)?;
+    let mut obligation = Obligation::unpack(&obligation_info.data.borrow())?;
+
+    let mut deposited_value = Decimal::zero();
+    for deposit in obligation.deposits.iter_mut() {
+        let reserve_info = next_account_info(accounts_iter)?;
+        if reserve_info.key != &deposit.deposit_reserve {
+            msg!("reserves out of order");
+            return Err(LendingError::InvalidAccountInput.into());
+        }
+        let reserve = load_fresh_reserve(program_id, reserve_info, clock.slot)?;
+
+        let liquidity = reserve.collateral_exchange_rate()?
+            .decimal_collateral_to_liquidity(deposit.deposited_amount.into())?;
+        deposit.market_value = liquidity
+            .try_mul(reserve.liquidity.market_price)?
+            .try_div(10u64.pow(reserve.liquidity.mint_decimals as u32))?;
+        deposited_value = deposited_value.try_add(deposit.market_value)?;
+    }
+
+    obligation.deposited_value = deposited_value;
+    obligation.last_update.update_slot(clock.slot);
+    Obligation::pack(obligation, &mut obligation_info.data.borrow_mut())
+}
+
+// move collateral from its owner into the reserve, crediting the obligation
+fn deposit_obligation_collateral(program_id: &Pubkey, accounts: &[AccountInfo], collateral_amount: u64) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let source_collateral = next_account_info(accounts_iter)?;
+    let collateral_supply = next_account_info(accounts_iter)?;
+    let reserve_info = next_account_info(accounts_iter)?;
+    let obligation_info = next_account_info(accounts_iter)?;
+    let lending_market_info = next_account_info(accounts_iter)?;
+    let obligation_owner = next_account_info(accounts_iter)?;
+    let user_transfer_authority = next_account_info(accounts_iter)?;
+    let clock = Clock::from_account_info(next_account_info(accounts_iter)?)?;
+    let token_program = next_account_info(accounts_iter)?;
+
+    if collateral_amount == 0 {
+        return Err(LendingError::InvalidAmount.into());
+    }
+
+    let reserve = load_fresh_reserve(program_id, reserve_info, clock.slot)?;
+    if &reserve.collateral.supply_pubkey != collateral_supply.key {
+        msg!("bad collateral supply");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+
+    let mut obligation = load_owned_obligation(program_id, obligation_info, lending_market_info, obligation_owner)?;
+    obligation.find_or_add_collateral_to_deposits(*reserve_info.key)?.deposit(collateral_amount)?;
+    obligation.last_update.mark_stale();
+    Obligation::pack(obligation, &mut obligation_info.data.borrow_mut())?;
+
+    invoke_signed(
+        &spl_token::instruction::transfer(
+            token_program.key,
+            source_collateral.key,
+            collateral_supply.key,
+            user_transfer_authority.key,
+            &[],
+            collateral_amount,
+        )?,
+        &[source_collateral.clone(), collateral_supply.clone(), user_transfer_authority.clone(), token_program.clone()],
+        &[],
+    )
+}
+
+// take collateral out of the obligation and redeem it for liquidity
+fn withdraw_and_redeem(program_id: &Pubkey, accounts: &[AccountInfo], collateral_amount: u64) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let collateral_supply = next_account_info(accounts_iter)?;
+    let destination_collateral = next_account_info(accounts_iter)?;
+    let reserve_info = next_account_info(accounts_iter)?;
+    let obligation_info = next_account_info(accounts_iter)?;
+    let lending_market_info = next_account_info(accounts_iter)?;
+    let lending_market_authority = next_account_info(accounts_iter)?;
+    let destination_liquidity = next_account_info(accounts_iter)?;
+    let collateral_mint = next_account_info(accounts_iter)?;
+    let liquidity_supply = next_account_info(accounts_iter)?;
+    let obligation_owner = next_account_info(accounts_iter)?;
+    let user_transfer_authority = next_account_info(accounts_iter)?;
+    let clock = Clock::from_account_info(next_account_info(accounts_iter)?)?;
+    let token_program = next_account_info(accounts_iter)?;
+
+    if collateral_amount == 0 {
+        return Err(LendingError::InvalidAmount.into());
+    }
+
+    let mut reserve = load_fresh_reserve(program_id, reserve_info, clock.slot)?;
+    let bump = load_lending_market(program_id, lending_market_info, lending_market_authority, token_program)?;
+    if &reserve.collateral.supply_pubkey != collateral_supply.key ||
+        &reserve.collateral.mint_pubkey != collateral_mint.key ||
+        &reserve.liquidity.supply_pubkey != liquidity_supply.key {
+        msg!("bad reserve accounts");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+
+    let mut obligation = load_owned_obligation(program_id, obligation_info, lending_market_info, obligation_owner)?;
+    if obligation.last_update.is_stale(clock.slot)? {
+        msg!("obligation is stale and must be refreshed in the current slot");
+        return Err(LendingError::ObligationStale.into());
+    }
+    let (collateral, index) = obligation.find_collateral_in_deposits(*reserve_info.key)?;
+    if collateral_amount > collateral.deposited_amount {
+        msg!("withdraw amount is more than deposited");
+        return Err(LendingError::WithdrawTooLarge.into());
+    }
+    obligation.withdraw(collateral_amount, index)?;
+    obligation.last_update.mark_stale();
+    Obligation::pack(obligation, &mut obligation_info.data.borrow_mut())?;
+
+    let liquidity_amount = reserve.redeem_collateral(collateral_amount)?;
+    reserve.last_update.mark_stale();
+    Reserve::pack(reserve, &mut reserve_info.data.borrow_mut())?;
+
+    let authority_seeds: &[&[u8]] = &[lending_market_info.key.as_ref(), &[bump]];
+
+    invoke_signed(
+        &spl_token::instruction::transfer(
+            token_program.key,
+            collateral_supply.key,
+            destination_collateral.key,
+            lending_market_authority.key,
+            &[],
+            collateral_amount,
+        )?,
+        &[
+            collateral_supply.clone(), destination_collateral.clone(),
+            lending_market_authority.clone(), token_program.clone(),
+        ],
+        &[authority_seeds],
+    )?;
+
+    invoke_signed(
+        &spl_token::instruction::burn(
+            token_program.key,
+            destination_collateral.key,
+            collateral_mint.key,
+            user_transfer_authority.key,
+            &[],
+            collateral_amount,
+        )?,
+        &[
+            destination_collateral.clone(), collateral_mint.clone(),
+            user_transfer_authority.clone(), token_program.clone(),
+        ],
+        &[],
+    )?;
+
+    invoke_signed(
+        &spl_token::instruction::transfer(
+            token_program.key,
+            liquidity_supply.key,
+            destination_liquidity.key,
+            lending_market_authority.key,
+            &[],
+            liquidity_amount,
+        )?,
+        &[
+            liquidity_supply.clone(), destination_liquidity.clone(),
+            lending_market_authority.clone(), token_program.clone(),
+        ],
+        &[authority_seeds],
+    )
+}
+
+fn check_owner(account: &AccountInfo, program_id: &Pubkey) -> ProgramResult {
+    if account.owner != program_id {
+        msg!("{} isn't owned by the mock", account.key);
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+
+    Ok(())
+}
+
+// read a reserve refreshed in the current slot
+fn load_fresh_reserve(program_id: &Pubkey, reserve_info: &AccountInfo, slot: u64) -> Result<Reserve, ProgramError> {
+    check_owner(reserve_info, program_id)?;
+    let reserve = Reserve::unpack(&reserve_info.data.borrow())?;
+    if reserve.last_update.is_stale(slot)? {
+        msg!("reserve is stale and must be refreshed in the current slot");
+        return Err(LendingError::ReserveStale.into());
+    }
+
+    Ok(reserve)
+}
+
+// check a lending market and its authority, returning the authority's bump
+fn load_lending_market(
+    program_id: &Pubkey,
+    lending_market_info: &AccountInfo,
+    lending_market_authority: &AccountInfo,
+    token_program: &AccountInfo,
+) -> Result<u8, ProgramError> {
+    check_owner(lending_market_info, program_id)?;
+    let lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
+    if &lending_market.token_program_id != token_program.key {
+        return Err(LendingError::InvalidTokenProgram.into());
+    }
+    if lending_market_authority.key != &Pubkey::create_program_address(
+        &[lending_market_info.key.as_ref(), &[lending_market.bump_seed]],
+        program_id,
+    )? {
+        return Err(LendingError::InvalidMarketAuthority.into());
+    }
+
+    Ok(lending_market.bump_seed)
+}
+
+// read an obligation of the lending market, checking its owner signed
+fn load_owned_obligation(
+    program_id: &Pubkey,
+    obligation_info: &AccountInfo,
+    lending_market_info: &AccountInfo,
+    obligation_owner: &AccountInfo,
+) -> Result<Obligation, ProgramError> {
+    check_owner(obligation_info, program_id)?;
+    let obligation = Obligation::unpack(&obligation_info.data.borrow())?;
+    if &obligation.lending_market != lending_market_info.key {
+        msg!("obligation is of another lending market");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+    if &obligation.owner != obligation_owner.key || !obligation_owner.is_signer {
+        msg!("obligation owner must sign");
+        return Err(LendingError::InvalidSigner.into());
+    }
+
+    Ok(obligation)
+}